@@ -13,57 +13,294 @@ function! unrepl#FindDef() abort
 
   let symbol = expand('<cword>')
   let fname = expand('%:p')
-  let cmd = unrepl#GetCmd() . ' find_def ' . fname . ' ' . symbol
-  let lines = systemlist(cmd)
+  let cmd = unrepl#GetCmd() . ' --format json find_def ' . shellescape(fname) . ' ' . shellescape(symbol)
+  let res = json_decode(system(cmd))
 
-  for line in lines
-    let parts = split(line, ' ')
-
-    if parts[0] ==# 'IS-EMPTY'
-      return
-    elseif parts[0]  ==# 'LINE'
-      let l:linenum = parts[1]
-    elseif parts[0] ==# 'COLUMN'
-      let l:column = parts[1]
-    elseif parts[0] ==# 'FILE'
-      let l:file = parts[1]
-    elseif parts[0] ==# 'JAR'
-      let l:jar = parts[1]
-    endif
-  endfor
+  if res is v:null
+    return
+  elseif type(res) == v:t_dict && has_key(res, 'error')
+    call s:Warn(res.error.message)
+    return
+  endif
+
+  call s:JumpToLocation(res.file, res.line, res.column)
+endfunction
+
+function! unrepl#Doc(...) abort
+  if s:ErrorCheck()
+    return
+  endif
+
+  let symbol = a:0 ? a:1 : expand('<cword>')
+  let fname = expand('%:p')
+  let cmd = unrepl#GetCmd() . ' doc ' . shellescape(fname) . ' ' . shellescape(symbol)
+
+  echo system(cmd)
+endfunction
+
+function! unrepl#Eval(code) abort
+  if s:ErrorCheck()
+    return
+  endif
+
+  let fname = expand('%:p')
+  let cmd = unrepl#GetCmd() . ' --format json eval --file ' . shellescape(fname) . ' ' . shellescape(a:code)
+  call s:EchoEvalResult(system(cmd))
+endfunction
+
+" Omnifunc, use with `setlocal omnifunc=unrepl#Complete`
+function! unrepl#Complete(findstart, base) abort
+  if a:findstart
+    let line = getline('.')[0 : col('.') - 2]
+    return col('.') - 1 - strlen(matchstr(line, '\k\+$'))
+  endif
+
+  if s:ErrorCheck()
+    return []
+  endif
+
+  let fname = expand('%:p')
+  let cmd = unrepl#GetCmd() . ' complete ' . shellescape(fname) . ' ' . shellescape(a:base)
+  let res = system(cmd)
+
+  if v:shell_error
+    return []
+  endif
 
-  if exists('l:jar')
-    let content_cmd = unrepl#GetCmd() . ' read_jar ' . l:jar . ' ' . l:file
-    let contents = systemlist(content_cmd)
+  return json_decode(res)
+endfunction
 
-    let winview = winsaveview()  " Save the current cursor position
+" Loads undefined vars and namespaces of current file into the quickfix list
+function! unrepl#Lint() abort
+  if s:ErrorCheck()
+    return
+  endif
 
-    let l:tmpfname = tempname() . '.clj'
+  let fname = expand('%:p')
+  let cmd = unrepl#GetCmd() . ' lint ' . shellescape(fname)
+  let lines = systemlist(cmd)
 
-    call writefile(contents, l:tmpfname)
+  if v:shell_error
+    call s:Warn(join(lines, "\n"))
+    return
+  endif
 
-    call s:JumpToLocation(l:tmpfname, l:linenum, l:column)
+  call setqflist([], ' ', {'lines': lines, 'efm': '%f:%l:%c: %m', 'title': 'unrepl lint'})
+endfunction
 
+" Loads usages of symbol under cursor into the quickfix list
+function! unrepl#FindUsages() abort
+  if s:ErrorCheck()
     return
   endif
 
-  if !exists('l:file')
+  let fname = expand('%:p')
+  let cmd = unrepl#GetCmd() . ' find_usages ' . shellescape(fname) . ' ' . shellescape(expand('<cword>'))
+  let lines = systemlist(cmd)
+
+  if v:shell_error
+    call s:Warn(join(lines, "\n"))
     return
   endif
 
-  call s:JumpToLocation(l:file, l:linenum, l:column)
+  call setqflist([], ' ', {'lines': lines, 'efm': '%f:%l:%c: %m', 'title': 'unrepl usages'})
 endfunction
 
-function! unrepl#Doc() abort
+" Loads unused private vars, aliases and refers of current file into the location list
+function! unrepl#Unused() abort
   if s:ErrorCheck()
     return
   endif
 
-  let symbol = expand('<cword>')
   let fname = expand('%:p')
-  let cmd = unrepl#GetCmd() . ' doc ' . fname . ' ' . symbol
+  let cmd = unrepl#GetCmd() . ' --format json unused ' . shellescape(fname)
+  let res = system(cmd)
 
-  echo system(cmd)
+  if v:shell_error
+    call s:Warn(res)
+    return
+  endif
+
+  let items = map(json_decode(res), {_, d -> {
+        \ 'filename': d.file, 'lnum': d.line, 'col': d.column, 'text': d.message, 'type': 'W'}})
+  call setloclist(0, items, 'r')
+endfunction
+
+" Evaluates the innermost or the outermost form under cursor, buffer is sent on stdin so
+" unsaved changes are read too
+function! unrepl#EvalForm(outermost) abort
+  if s:ErrorCheck()
+    return
+  endif
+
+  let cmd = unrepl#GetCmd() . ' --format json eval --form ' . (a:outermost ? 'outermost' : 'innermost')
+        \ . ' --file ' . shellescape(expand('%:p'))
+        \ . ' --line ' . line('.') . ' --column ' . col('.')
+  call s:EchoEvalResult(system(cmd, getline(1, '$')))
+endfunction
+
+" Evaluates code typed at the prompt in the namespace of current file
+function! unrepl#EvalPrompt() abort
+  let code = input('=> ')
+
+  if code !=# ''
+    redraw
+    call unrepl#Eval(code)
+  endif
+endfunction
+
+" Evaluates text moved over by `cp` operator, or selected text when `visual` is given
+function! unrepl#EvalMotion(type, ...) abort
+  let reg = getreg('"')
+  let regtype = getregtype('"')
+  let select = a:0 && a:1 ? 'gv' : a:type ==# 'line' ? "'[V']" : '`[v`]'
+
+  try
+    silent exe 'normal! ' . select . 'y'
+    let code = getreg('"')
+  finally
+    call setreg('"', reg, regtype)
+  endtry
+
+  call unrepl#Eval(code)
+endfunction
+
+" Reloads namespace of current file, `all` reloads namespaces it requires too
+function! unrepl#Require(all, ...) abort
+  if s:ErrorCheck()
+    return
+  endif
+
+  let cmd = unrepl#GetCmd() . ' --format json reload ' . (a:all ? '--all ' : '')
+        \ . shellescape(expand('%:p')) . ' ' . join(map(copy(a:000), 'shellescape(v:val)'))
+  call s:EchoEvalResult(system(cmd))
+endfunction
+
+" Loads current buffer with `load-file`, unsaved changes are loaded too
+function! unrepl#LoadFile() abort
+  if s:ErrorCheck()
+    return
+  endif
+
+  let cmd = unrepl#GetCmd() . ' --format json load_file --stdin ' . shellescape(expand('%:p'))
+  call s:EchoEvalResult(system(cmd, getline(1, '$')))
+endfunction
+
+" Shows source of definition of `symbol` in the preview window
+function! unrepl#Source(symbol) abort
+  if s:ErrorCheck()
+    return
+  endif
+
+  let cmd = unrepl#GetCmd() . ' --format json source '
+        \ . shellescape(expand('%:p')) . ' ' . shellescape(a:symbol)
+  let res = json_decode(system(cmd))
+
+  if res is v:null
+    call s:Warn('No source found for ' . a:symbol)
+    return
+  elseif has_key(res, 'error')
+    call s:Warn(res.error.message)
+    return
+  endif
+
+  let ft = &filetype
+  silent pedit unrepl://source
+  wincmd P
+  setlocal buftype=nofile bufhidden=wipe noswapfile modifiable
+  silent %delete _
+  call setline(1, split(res.source, "\n"))
+  let b:unrepl_source_file = res.file
+  let &l:filetype = res.file =~# '\.java$' ? 'java' : ft
+  setlocal nomodifiable
+  wincmd p
+endfunction
+
+" Loads public vars matching `query` into the quickfix list, docstrings are searched with `docs`
+function! unrepl#Apropos(docs, query) abort
+  if s:ErrorCheck()
+    return
+  endif
+
+  let cmd = unrepl#GetCmd() . ' --format json apropos ' . (a:docs ? '--docs ' : '')
+        \ . shellescape(a:query)
+  let res = json_decode(system(cmd))
+
+  if type(res) == v:t_dict
+    call s:Warn(res.error.message)
+    return
+  endif
+
+  let items = map(res, {_, m -> {'text': m.name . (m.doc is v:null ? '' : '  ' . split(m.doc, "\n")[0])}})
+  call setqflist([], ' ', {'items': items, 'title': 'unrepl apropos ' . a:query})
+  copen
+endfunction
+
+" Runs tests of namespace of current file and of its `-test` namespace, or of given namespaces.
+" Failures are loaded into the quickfix list
+function! unrepl#RunTests(...) abort
+  if s:ErrorCheck()
+    return
+  endif
+
+  let cmd = unrepl#GetCmd() . ' --format json run_tests ' . shellescape(expand('%:p'))
+        \ . ' ' . join(map(copy(a:000), 'shellescape(v:val)'))
+  let res = json_decode(system(cmd))
+
+  " Summary has `error` count too
+  if type(res.error) == v:t_dict
+    call s:Warn(res.error.message)
+    return
+  endif
+
+  let items = map(res.failures, {_, d -> {
+        \ 'filename': d.file, 'lnum': d.line, 'col': d.column, 'text': d.message, 'type': 'E'}})
+  call setqflist([], ' ', {'items': items, 'title': 'unrepl tests'})
+
+  if res.ex isnot v:null
+    call s:Warn(res.ex)
+  elseif res.fail + res.error > 0
+    copen | wincmd p
+    call s:Warn(printf('Ran %d tests, %d failures, %d errors', res.test, res.fail, res.error))
+  else
+    cclose
+    echo printf('Ran %d tests, %d assertions passed', res.test, res.pass)
+  endif
+endfunction
+
+" Shows result of the last evaluation
+function! unrepl#Last() abort
+  if s:ErrorCheck()
+    return
+  endif
+
+  call s:EchoEvalResult(system(unrepl#GetCmd() . ' --format json last'))
+endfunction
+
+function! s:EchoEvalResult(json) abort
+  let res = json_decode(a:json)
+
+  if type(res) != v:t_dict
+    return
+  elseif has_key(res, 'error')
+    call s:Warn(res.error.message)
+    return
+  endif
+
+  let lines = split(join(res.out, '') . join(res.err, ''), "\n") + res.values
+
+  if res.ex isnot v:null
+    call add(lines, res.root_ex isnot v:null ? res.root_ex : res.ex)
+  endif
+
+  " Results of loading code have the place of syntax error
+  let error = get(res, 'compile_error', v:null)
+  if error isnot v:null
+    call setqflist([{'filename': error.file, 'lnum': error.line, 'col': error.column,
+          \ 'text': error.message, 'type': 'E'}], ' ')
+  endif
+
+  echo join(lines, "\n")
 endfunction
 
 function! s:Warn(msg) abort
@@ -0,0 +1,36 @@
+" Commands and maps of fireplace, so users switching from it keep their habits
+
+function! unrepl#fireplace#Setup() abort
+  command! -buffer -bar -range=0 -nargs=? Eval
+        \ call s:Eval(<q-args>, <count>, <line1>, <line2>)
+  command! -buffer -bar -bang -nargs=* Require call unrepl#Require(<bang>0, <f-args>)
+  command! -buffer -bar -nargs=1 Doc call unrepl#Doc(<q-args>)
+  command! -buffer -bar -nargs=1 Source call unrepl#Source(<q-args>)
+  command! -buffer -bar -bang -nargs=1 Apropos call unrepl#Apropos(<bang>0, <q-args>)
+  command! -buffer -bar -nargs=* RunTests call unrepl#RunTests(<f-args>)
+  command! -buffer -bar Last call unrepl#Last()
+  command! -buffer -bar LoadFile call unrepl#LoadFile()
+
+  if get(g:, 'unrepl_no_maps')
+    return
+  endif
+
+  nnoremap <buffer><silent> K :<C-U>call unrepl#Doc()<CR>
+  nnoremap <buffer><silent> [d :<C-U>call unrepl#Source(expand('<cword>'))<CR>
+  nnoremap <buffer><silent> ]d :<C-U>call unrepl#Source(expand('<cword>'))<CR>
+  nnoremap <buffer><silent> cp :<C-U>set opfunc=unrepl#EvalMotion<CR>g@
+  xnoremap <buffer><silent> cp :<C-U>call unrepl#EvalMotion(visualmode(), 1)<CR>
+  nnoremap <buffer><silent> cpp :<C-U>call unrepl#EvalForm(0)<CR>
+  nnoremap <buffer><silent> cqp :<C-U>call unrepl#EvalPrompt()<CR>
+endfunction
+
+" `:Eval` evaluates its argument, given lines, or the outermost form under cursor like fireplace
+function! s:Eval(code, count, line1, line2) abort
+  if a:code !=# ''
+    call unrepl#Eval(a:code)
+  elseif a:count
+    call unrepl#Eval(join(getline(a:line1, a:line2), "\n"))
+  else
+    call unrepl#EvalForm(1)
+  endif
+endfunction
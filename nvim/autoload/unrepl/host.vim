@@ -0,0 +1,91 @@
+" Talks to `unrepl nvim` running as a remote plugin, so the binary is started once
+" and keeps nrepl connection open
+
+function! unrepl#host#Start() abort
+  if exists('s:job') && s:job > 0
+    return s:job
+  endif
+
+  let s:job = jobstart([unrepl#GetCmd(), 'nvim'], {'rpc': v:true, 'on_exit': function('s:OnExit')})
+
+  return s:job
+endfunction
+
+function! s:OnExit(job, code, event) abort
+  unlet! s:job
+endfunction
+
+function! s:Request(method, ...) abort
+  return call('rpcrequest', [unrepl#host#Start(), a:method] + a:000)
+endfunction
+
+function! unrepl#host#FindDef() abort
+  let res = s:Request('FindDef', expand('%:p'), expand('<cword>'))
+
+  if res is v:null
+    return
+  endif
+
+  let fname = res.file
+
+  normal! m`
+  if fname != expand('%:p')
+    exec 'keepjumps e ' . fnameescape(fname)
+  endif
+  call cursor(str2nr(res.line), str2nr(res.column))
+  normal! zz
+endfunction
+
+function! unrepl#host#Doc() abort
+  let doc = s:Request('Doc', expand('%:p'), expand('<cword>'))
+
+  if doc isnot v:null
+    echo doc
+  endif
+endfunction
+
+" Output arrives with `unrepl#host#Output` while evaluation is running
+function! unrepl#host#Eval(code) abort
+  call rpcnotify(unrepl#host#Start(), 'Eval', expand('%:p'), a:code)
+endfunction
+
+function! unrepl#host#Interrupt() abort
+  return s:Request('Interrupt')
+endfunction
+
+" Omnifunc, use with `setlocal omnifunc=unrepl#host#Complete`
+function! unrepl#host#Complete(findstart, base) abort
+  if a:findstart
+    return unrepl#Complete(a:findstart, a:base)
+  endif
+
+  return s:Request('Complete', expand('%:p'), a:base)
+endfunction
+
+function! s:OutputBuffer() abort
+  let bufnr = bufnr('unrepl://output')
+
+  if bufnr < 0
+    let bufnr = bufadd('unrepl://output')
+    call setbufvar(bufnr, '&buftype', 'nofile')
+    call setbufvar(bufnr, '&swapfile', 0)
+    call bufload(bufnr)
+  endif
+
+  return bufnr
+endfunction
+
+" Called by the host with `kind` being one of out, err, value or ex
+function! unrepl#host#Output(kind, text) abort
+  let lines = split(a:text, "\n")
+
+  if a:kind ==# 'ex'
+    let lines = map(lines, '";; " . v:val')
+  endif
+
+  call appendbufline(s:OutputBuffer(), '$', lines)
+
+  if a:kind ==# 'value' || a:kind ==# 'ex'
+    echo a:text
+  endif
+endfunction
@@ -0,0 +1,9 @@
+if exists('g:loaded_unrepl')
+  finish
+endif
+let g:loaded_unrepl = 1
+
+augroup unrepl_fireplace
+  autocmd!
+  autocmd FileType clojure call unrepl#fireplace#Setup()
+augroup END
@@ -0,0 +1,934 @@
+//! Static analysis of Clojure source: definitions, locals and var references
+
+use crate::config::SessionKind;
+use crate::ns::{self, NsDecl, Refer};
+use crate::reader::{self, Form, Node, Pos};
+use failure::Error as StdError;
+use serde::Serialize;
+use std::io::Read;
+
+/// Forms which are not vars, so they can't be undefined
+pub const SPECIAL_FORMS: [&str; 24] = [
+    "def",
+    "if",
+    "do",
+    "let*",
+    "quote",
+    "var",
+    "fn*",
+    "loop*",
+    "recur",
+    "throw",
+    "try",
+    "catch",
+    "finally",
+    "monitor-enter",
+    "monitor-exit",
+    "new",
+    "set!",
+    ".",
+    "letfn*",
+    "case*",
+    "reify*",
+    "deftype*",
+    "import*",
+    "&",
+];
+
+/// Var defined in analyzed source
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Def {
+    pub name: String,
+    /// Defining form, like `defn` or `defmacro`
+    pub kind: String,
+    pub private: bool,
+    pub pos: Pos,
+    pub arglists: Vec<String>,
+    pub doc: Option<String>,
+}
+
+/// Reference to a var, `ns` is qualifier as written in source: an alias or a namespace
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Usage {
+    pub ns: Option<String>,
+    pub name: String,
+    pub pos: Pos,
+    /// `::alias/kw` keyword, references only the alias
+    pub keyword: bool,
+    /// Appears inside of syntax-quote, so it's resolved when the macro expands
+    pub syntax_quoted: bool,
+}
+
+impl Usage {
+    pub fn full_name(&self) -> String {
+        match &self.ns {
+            Some(ns) => format!("{}/{}", ns, self.name),
+            None => self.name.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Analysis {
+    pub ns: Option<NsDecl>,
+    pub defs: Vec<Def>,
+    pub usages: Vec<Usage>,
+}
+
+impl Analysis {
+    pub fn ns_name(&self) -> Option<&str> {
+        self.ns.as_ref().map(|ns| ns.name.as_str())
+    }
+
+    pub fn find_def(&self, name: &str) -> Option<&Def> {
+        self.defs.iter().find(|d| d.name == name)
+    }
+
+    /// Tells if `clojure.core/name` is referred, honouring `:refer-clojure`
+    pub fn is_core_referred(&self, name: &str) -> bool {
+        match &self.ns {
+            Some(decl) => {
+                !decl.refer_clojure_exclude.iter().any(|n| n == name)
+                    && decl
+                        .refer_clojure_only
+                        .as_ref()
+                        .is_none_or(|only| only.iter().any(|n| n == name))
+            }
+            None => true,
+        }
+    }
+
+    /// Namespace of `ns/name` symbol written in this file. Vars referred with `:refer :all`
+    /// can't be told from `clojure.core` ones without runtime, they are taken as the latter.
+    pub fn resolve(&self, ns: Option<&str>, name: &str) -> Option<String> {
+        let decl = self.ns.as_ref();
+
+        if let Some(ns) = ns {
+            return Some(
+                decl.and_then(|d| d.resolve_alias(ns))
+                    .unwrap_or(ns)
+                    .to_string(),
+            );
+        }
+
+        if self.find_def(name).is_some() {
+            return Some(self.ns_name().unwrap_or("user").to_string());
+        }
+
+        let referred = decl.and_then(|d| {
+            d.requires.iter().find(|r| match &r.refer {
+                Some(Refer::Only(names)) => names.iter().any(|n| n == name),
+                _ => false,
+            })
+        });
+
+        match referred {
+            Some(require) => Some(require.ns.to_string()),
+            None if SPECIAL_FORMS.contains(&name) => None,
+            None if self.is_core_referred(name) => Some("clojure.core".to_string()),
+            None => None,
+        }
+    }
+
+    /// Tells if `usage` may reference `ns/name` var, that's the case for names referred
+    /// with `:refer :all` from `ns` too
+    pub fn references(&self, usage: &Usage, ns: &str, name: &str) -> bool {
+        if usage.keyword || usage.name != name {
+            return false;
+        }
+
+        let is_referred_all = self.ns.as_ref().is_some_and(|d| {
+            d.requires
+                .iter()
+                .any(|r| r.ns == ns && r.refer == Some(Refer::All))
+        });
+
+        if self.resolve(usage.ns.as_deref(), name).as_deref() == Some(ns) {
+            return true;
+        }
+
+        usage.ns.is_none() && is_referred_all && self.find_def(name).is_none()
+    }
+}
+
+/// Forms with a binding vector `[lhs rhs ...]` followed by body
+const BINDING_FORMS: [&str; 14] = [
+    "let",
+    "let*",
+    "loop",
+    "loop*",
+    "when-let",
+    "if-let",
+    "when-some",
+    "if-some",
+    "when-first",
+    "with-open",
+    "with-local-vars",
+    "dotimes",
+    "go-loop",
+    "with-redefs-fn",
+];
+
+/// Forms whose binding vector is `[var value ...]`, vars are references, not locals
+const VAR_BINDING_FORMS: [&str; 2] = ["binding", "with-redefs"];
+
+const FN_DEF_FORMS: [&str; 5] = ["defn", "defn-", "defmacro", "definline", "deftest"];
+
+const DEF_FORMS: [&str; 4] = ["def", "defonce", "defmulti", "defstruct"];
+
+struct Walker {
+    analysis: Analysis,
+    locals: Vec<String>,
+    syntax_quoted: bool,
+}
+
+fn head_name(items: &[Node]) -> Option<String> {
+    match items.first().map(|n| &n.form) {
+        Some(Form::Symbol { name, .. }) => Some(name.to_string()),
+        _ => None,
+    }
+}
+
+fn symbol_name(node: &Node) -> Option<&str> {
+    match &node.form {
+        Form::Symbol { ns: None, name } => Some(name),
+        _ => None,
+    }
+}
+
+/// Arglists of fn tail: `[x] body` or `([x] body) ([x y] body)`
+fn arglists(tail: &[Node]) -> Vec<String> {
+    match tail.first().map(|n| &n.form) {
+        Some(Form::Vector(_)) => vec![tail[0].to_string()],
+        Some(Form::List(_)) => tail
+            .iter()
+            .filter_map(|arity| match arity.children().and_then(|c| c.first()) {
+                Some(
+                    params @ Node {
+                        form: Form::Vector(_),
+                        ..
+                    },
+                ) => Some(params.to_string()),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+impl Walker {
+    fn new() -> Self {
+        Self {
+            analysis: Analysis::default(),
+            locals: vec![],
+            syntax_quoted: false,
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.locals.iter().any(|l| l == name)
+    }
+
+    /// Adds def of `node` if it's a symbol, returns its index in `defs`
+    fn add_def(&mut self, node: &Node, kind: &str, private: bool) -> Option<usize> {
+        let name = match &node.form {
+            Form::Symbol { name, .. } => name,
+            _ => return None,
+        };
+
+        self.analysis.defs.push(Def {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            private: private || node.has_meta_flag("private"),
+            pos: node.start,
+            arglists: vec![],
+            doc: None,
+        });
+        Some(self.analysis.defs.len() - 1)
+    }
+
+    fn add_usage(&mut self, node: &Node) {
+        let (ns, name) = match &node.form {
+            Form::Symbol { ns, name } => (ns, name),
+            _ => return,
+        };
+
+        if ns.is_none() {
+            if self.is_local(name) || SPECIAL_FORMS.contains(&name.as_str()) {
+                return;
+            }
+            // Gensyms of syntax-quote
+            if self.syntax_quoted && name.ends_with('#') {
+                return;
+            }
+        }
+
+        // Java interop: `.method`, `Class.`, `..`
+        if name.starts_with('.') || (name.ends_with('.') && name.len() > 1) {
+            return;
+        }
+
+        self.analysis.usages.push(Usage {
+            ns: ns.clone(),
+            name: name.to_string(),
+            pos: node.start,
+            keyword: false,
+            syntax_quoted: self.syntax_quoted,
+        });
+    }
+
+    fn add_keyword_usage(&mut self, node: &Node) {
+        match &node.form {
+            Form::Keyword {
+                ns: Some(ns),
+                name,
+                auto: true,
+            } => self.analysis.usages.push(Usage {
+                ns: Some(ns.to_string()),
+                name: name.to_string(),
+                pos: node.start,
+                keyword: true,
+                syntax_quoted: self.syntax_quoted,
+            }),
+            Form::NamespacedMap {
+                ns: Some(ns),
+                auto: true,
+                ..
+            } => self.analysis.usages.push(Usage {
+                ns: Some(ns.to_string()),
+                name: String::new(),
+                pos: node.start,
+                keyword: true,
+                syntax_quoted: self.syntax_quoted,
+            }),
+            _ => {}
+        }
+    }
+
+    fn walk_all(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.walk(node);
+        }
+    }
+
+    fn walk(&mut self, node: &Node) {
+        match &node.form {
+            Form::Symbol { .. } => self.add_usage(node),
+            Form::Keyword { .. } => self.add_keyword_usage(node),
+            Form::List(items) => self.walk_list(items),
+            Form::Vector(items) | Form::Set(items) | Form::Map(items) => self.walk_all(items),
+            Form::NamespacedMap { forms, .. } => {
+                self.add_keyword_usage(node);
+                self.walk_all(forms);
+            }
+            Form::Fn(items) => {
+                let mark = self.locals.len();
+                self.locals.extend(
+                    [
+                        "%", "%&", "%1", "%2", "%3", "%4", "%5", "%6", "%7", "%8", "%9",
+                    ]
+                    .iter()
+                    .map(|s| s.to_string()),
+                );
+                self.walk_list(items);
+                self.locals.truncate(mark);
+            }
+            Form::SyntaxQuote(inner) => {
+                let was_quoted = self.syntax_quoted;
+                self.syntax_quoted = true;
+                self.walk(inner);
+                self.syntax_quoted = was_quoted;
+            }
+            Form::Unquote(inner) | Form::UnquoteSplicing(inner) => {
+                let was_quoted = self.syntax_quoted;
+                self.syntax_quoted = false;
+                self.walk(inner);
+                self.syntax_quoted = was_quoted;
+            }
+            Form::Deref(inner) | Form::Var(inner) | Form::Eval(inner) => self.walk(inner),
+            Form::Tagged { form, .. } => self.walk(form),
+            Form::Quote(_) => {}
+            _ => {}
+        }
+    }
+
+    /// Adds locals bound by destructuring form `lhs`, walks default values of `:or`
+    fn bind(&mut self, lhs: &Node) {
+        match &lhs.form {
+            Form::Symbol { ns: None, name } if name != "&" => self.locals.push(name.to_string()),
+            Form::Vector(items) => {
+                let mut iter = items.iter();
+                while let Some(item) = iter.next() {
+                    if item.as_keyword().as_deref() == Some("as") {
+                        if let Some(alias) = iter.next() {
+                            self.bind(alias);
+                        }
+                    } else {
+                        self.bind(item);
+                    }
+                }
+            }
+            Form::Map(items) | Form::NamespacedMap { forms: items, .. } => {
+                for kv in items.chunks(2) {
+                    let (key, value) = match kv {
+                        [key, value] => (key, value),
+                        _ => continue,
+                    };
+
+                    match &key.form {
+                        Form::Keyword { name, .. }
+                            if name == "keys" || name == "strs" || name == "syms" =>
+                        {
+                            for sym in value.children().into_iter().flatten() {
+                                if let Form::Symbol { name, .. } = &sym.form {
+                                    self.locals.push(name.to_string());
+                                }
+                            }
+                        }
+                        Form::Keyword { name, .. } if name == "as" => self.bind(value),
+                        Form::Keyword { name, .. } if name == "or" => {
+                            for default in value.children().into_iter().flatten().skip(1).step_by(2)
+                            {
+                                self.walk(default);
+                            }
+                        }
+                        _ => {
+                            self.bind(key);
+                            self.walk(value);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Walks `[lhs rhs ...]` adding locals one by one, the caller is responsible for truncating
+    fn walk_bindings(&mut self, bindings: &Node, is_for: bool) {
+        let items = match &bindings.form {
+            Form::Vector(items) => items,
+            _ => return self.walk(bindings),
+        };
+
+        for pair in items.chunks(2) {
+            match pair {
+                [key, value] if is_for && key.as_keyword().as_deref() == Some("let") => {
+                    self.walk_bindings(value, false)
+                }
+                [key, value] if matches!(key.form, Form::Keyword { .. }) => self.walk(value),
+                [lhs, rhs] => {
+                    self.walk(rhs);
+                    self.bind(lhs);
+                }
+                [rest] => self.walk(rest),
+                _ => {}
+            }
+        }
+    }
+
+    /// Walks `[params] body` of a single arity
+    fn walk_arity(&mut self, items: &[Node]) {
+        let mark = self.locals.len();
+
+        if let Some(params) = items.first() {
+            self.bind(params);
+        }
+        self.walk_all(items.get(1..).unwrap_or_default());
+
+        self.locals.truncate(mark);
+    }
+
+    /// Walks fn tail: `[params] body` or `([params] body) ...`
+    fn walk_fn_tail(&mut self, tail: &[Node]) {
+        match tail.first().map(|n| &n.form) {
+            Some(Form::Vector(_)) => self.walk_arity(tail),
+            _ => {
+                for arity in tail {
+                    match &arity.form {
+                        Form::List(items) => self.walk_arity(items),
+                        // attr-map at the end of multi-arity defn
+                        _ => self.walk(arity),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Walks method implementations of `reify`, `deftype`, `extend-protocol` and such
+    fn walk_impls(&mut self, specs: &[Node]) {
+        for spec in specs {
+            match &spec.form {
+                Form::List(items) => {
+                    // `(method [this x] body)`, method name is not a var reference
+                    self.walk_fn_tail(items.get(1..).unwrap_or_default())
+                }
+                // Protocol or interface name
+                _ => self.walk(spec),
+            }
+        }
+    }
+
+    fn walk_def(&mut self, head: &str, items: &[Node]) {
+        let name = match items.get(1) {
+            Some(name) => name,
+            None => return,
+        };
+
+        // Name isn't a symbol in code like `(def ~name 1)` of macros, and defs in the body are
+        // added after this one, so it's referred to by index
+        let def_index = self.add_def(name, head, head == "defn-");
+
+        let mut tail = items.get(2..).unwrap_or_default();
+        let mut doc = None;
+
+        if let Some(s) = tail.first().and_then(|n| n.as_str()) {
+            if FN_DEF_FORMS.contains(&head) || tail.len() > 1 {
+                doc = Some(s.to_string());
+                tail = &tail[1..];
+            }
+        }
+
+        if FN_DEF_FORMS.contains(&head) {
+            if let Some(Form::Map(_)) = tail.first().map(|n| &n.form) {
+                tail = &tail[1..];
+            }
+
+            let mark = self.locals.len();
+            if head == "defmacro" {
+                self.locals.push("&form".to_string());
+                self.locals.push("&env".to_string());
+            }
+
+            if head == "deftest" {
+                self.walk_all(tail);
+            } else {
+                self.walk_fn_tail(tail);
+            }
+            self.locals.truncate(mark);
+
+            if let Some(i) = def_index {
+                let def = &mut self.analysis.defs[i];
+                def.arglists = arglists(tail);
+                def.doc = doc;
+            }
+        } else {
+            self.walk_all(tail);
+
+            let def = match def_index {
+                Some(i) => &mut self.analysis.defs[i],
+                None => return,
+            };
+            def.doc = doc;
+            if let Some(Form::List(fn_items)) = tail.first().map(|n| &n.form) {
+                if head_name(fn_items).as_deref() == Some("fn") {
+                    let fn_tail = match fn_items.get(1).map(|n| &n.form) {
+                        Some(Form::Symbol { .. }) => &fn_items[2..],
+                        _ => &fn_items[1..],
+                    };
+                    def.arglists = arglists(fn_tail);
+                }
+            }
+        }
+    }
+
+    fn walk_fn(&mut self, items: &[Node]) {
+        let mark = self.locals.len();
+        let mut tail = &items[1..];
+
+        // Named fn can call itself
+        if let Some(name) = tail.first().and_then(symbol_name) {
+            self.locals.push(name.to_string());
+            tail = &tail[1..];
+        }
+
+        self.walk_fn_tail(tail);
+        self.locals.truncate(mark);
+    }
+
+    fn walk_list(&mut self, items: &[Node]) {
+        let head = match head_name(items) {
+            Some(head) if !self.is_local(&head) => head,
+            _ => return self.walk_all(items),
+        };
+
+        let qualified = matches!(&items[0].form, Form::Symbol { ns: Some(_), .. });
+        let rest = &items[1..];
+
+        // Special and core forms are only recognized when written unqualified or as clojure.core
+        if qualified
+            && !matches!(&items[0].form, Form::Symbol { ns: Some(ns), .. } if ns == "clojure.core")
+            && !BINDING_FORMS.contains(&head.as_str())
+        {
+            return self.walk_all(items);
+        }
+
+        self.walk(&items[0]);
+
+        match head.as_str() {
+            "quote" | "ns" => {}
+            h if FN_DEF_FORMS.contains(&h) || DEF_FORMS.contains(&h) => self.walk_def(h, items),
+            "declare" => {
+                for name in rest {
+                    self.add_def(name, "declare", false);
+                }
+            }
+            "defmethod" => {
+                self.walk_all(rest.get(..2).unwrap_or_default());
+                let tail = rest.get(2..).unwrap_or_default();
+                let tail = match tail.first().map(|n| &n.form) {
+                    Some(Form::Symbol { .. }) => &tail[1..],
+                    _ => tail,
+                };
+                self.walk_fn_tail(tail);
+            }
+            "fn" | "fn*" => self.walk_fn(items),
+            "letfn" | "letfn*" => {
+                let mark = self.locals.len();
+                let fns = rest
+                    .first()
+                    .and_then(|n| n.children())
+                    .cloned()
+                    .unwrap_or_default();
+
+                for f in fns.iter() {
+                    if let Some(name) = f.children().and_then(|c| c.first()).and_then(symbol_name) {
+                        self.locals.push(name.to_string());
+                    }
+                }
+                for f in fns.iter() {
+                    if let Some(fn_items) = f.children() {
+                        self.walk_fn_tail(fn_items.get(1..).unwrap_or_default());
+                    }
+                }
+                self.walk_all(&rest[1.min(rest.len())..]);
+                self.locals.truncate(mark);
+            }
+            h if BINDING_FORMS.contains(&h) || h == "for" || h == "doseq" => {
+                let mark = self.locals.len();
+                if let Some(bindings) = rest.first() {
+                    self.walk_bindings(bindings, h == "for" || h == "doseq");
+                }
+                self.walk_all(rest.get(1..).unwrap_or_default());
+                self.locals.truncate(mark);
+            }
+            h if VAR_BINDING_FORMS.contains(&h) => self.walk_all(rest),
+            "as->" => {
+                let mark = self.locals.len();
+                if let Some(expr) = rest.first() {
+                    self.walk(expr);
+                }
+                if let Some(name) = rest.get(1) {
+                    self.bind(name);
+                }
+                self.walk_all(rest.get(2..).unwrap_or_default());
+                self.locals.truncate(mark);
+            }
+            "try" => {
+                for form in rest {
+                    match form.children().map(|c| head_name(c)) {
+                        Some(Some(h)) if h == "catch" => {
+                            let c = form.children().unwrap();
+                            let mark = self.locals.len();
+                            if let Some(e) = c.get(2) {
+                                self.bind(e);
+                            }
+                            self.walk_all(c.get(3..).unwrap_or_default());
+                            self.locals.truncate(mark);
+                        }
+                        _ => self.walk(form),
+                    }
+                }
+            }
+            "case" => {
+                if let Some(expr) = rest.first() {
+                    self.walk(expr);
+                }
+                let clauses = rest.get(1..).unwrap_or_default();
+                for pair in clauses.chunks(2) {
+                    match pair {
+                        // Test constants are not evaluated
+                        [_, expr] => self.walk(expr),
+                        [default] => self.walk(default),
+                        _ => {}
+                    }
+                }
+            }
+            "defprotocol" | "definterface" => {
+                if let Some(name) = rest.first() {
+                    self.add_def(name, &head, false);
+                }
+                for sig in rest.get(1..).unwrap_or_default() {
+                    if let Some(method) = sig.children().and_then(|c| c.first()) {
+                        self.add_def(method, &head, false);
+                        let def = self.analysis.defs.last_mut().unwrap();
+                        def.arglists = sig.children().unwrap()[1..]
+                            .iter()
+                            .filter(|n| matches!(n.form, Form::Vector(_)))
+                            .map(|n| n.to_string())
+                            .collect();
+                    }
+                }
+            }
+            "defrecord" | "deftype" => {
+                let name = match rest.first() {
+                    Some(name) => name,
+                    None => return,
+                };
+                self.add_def(name, &head, false);
+
+                if let Some(type_name) = name.as_symbol() {
+                    let mut ctors = vec![format!("->{}", type_name)];
+                    if head == "defrecord" {
+                        ctors.push(format!("map->{}", type_name));
+                    }
+                    for ctor in ctors {
+                        let mut ctor_node = name.clone();
+                        ctor_node.form = Form::Symbol {
+                            ns: None,
+                            name: ctor,
+                        };
+                        self.add_def(&ctor_node, &head, false);
+                    }
+                }
+
+                let mark = self.locals.len();
+                if let Some(fields) = rest.get(1) {
+                    self.bind(fields);
+                }
+                self.walk_impls(rest.get(2..).unwrap_or_default());
+                self.locals.truncate(mark);
+            }
+            "reify" | "extend-type" | "extend-protocol" => self.walk_impls(rest),
+            "proxy" => {
+                self.walk_all(rest.get(..2).unwrap_or_default());
+                self.walk_impls(rest.get(2..).unwrap_or_default());
+            }
+            "." => {
+                if let Some(target) = rest.first() {
+                    self.walk(target);
+                }
+                match rest.get(1).map(|n| &n.form) {
+                    Some(Form::List(call)) => self.walk_all(call.get(1..).unwrap_or_default()),
+                    Some(_) => self.walk_all(&rest[2..]),
+                    None => {}
+                }
+            }
+            "new" => self.walk_all(rest.get(1..).unwrap_or_default()),
+            _ => self.walk_all(rest),
+        }
+    }
+}
+
+/// Analyzes `nodes` read from a single file, `features` pick branches of reader conditionals
+pub fn analyze(nodes: &[Node], features: &[&str]) -> Analysis {
+    let nodes = reader::resolve_conditionals(nodes, features);
+    let mut walker = Walker::new();
+
+    for node in nodes.iter() {
+        if ns::is_ns_form(node) && walker.analysis.ns.is_none() {
+            walker.analysis.ns = ns::parse_ns_form(node.children().unwrap(), features);
+        } else {
+            walker.walk(node);
+        }
+    }
+
+    walker.analysis
+}
+
+/// Reader conditional features for a file when there's no session to ask, `.cljc` files are
+/// analyzed as Clojure
+pub fn features_for_path(path: &str) -> [&'static str; 2] {
+    if path.ends_with(".cljs") {
+        SessionKind::Cljs.features()
+    } else {
+        SessionKind::Clj.features()
+    }
+}
+
+/// Reads and analyzes `source`
+pub fn analyze_source(source: &str, path: &str) -> Result<Analysis, reader::Error> {
+    analyze_source_with(source, &features_for_path(path))
+}
+
+/// Same as `analyze_source`, but with explicit reader conditional `features`
+pub fn analyze_source_with(source: &str, features: &[&str]) -> Result<Analysis, reader::Error> {
+    let nodes = reader::read_all(source)?;
+    Ok(analyze(&nodes, features))
+}
+
+/// Same as `analyze_source`, but reads source from `path`, `-` stands for stdin
+pub fn analyze_file(path: &str) -> Result<Analysis, StdError> {
+    let mut source = String::new();
+
+    if path == "-" {
+        std::io::stdin().read_to_string(&mut source)?;
+    } else {
+        std::fs::File::open(path)?.read_to_string(&mut source)?;
+    }
+
+    Ok(analyze_source(&source, path)?)
+}
+
+/// Splits `ns/name`, `/` alone is a symbol name
+pub fn split_symbol(symbol: &str) -> (Option<&str>, &str) {
+    match symbol.rfind('/') {
+        Some(i) if i > 0 && i < symbol.len() - 1 => (Some(&symbol[..i]), &symbol[i + 1..]),
+        _ => (None, symbol),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usages(src: &str) -> Vec<String> {
+        analyze_source(src, "test.clj")
+            .unwrap()
+            .usages
+            .into_iter()
+            .filter(|u| !u.keyword)
+            .map(|u| u.full_name())
+            .collect()
+    }
+
+    #[test]
+    fn locals_are_not_usages_test() {
+        assert_eq!(
+            usages(
+                "(defn f [a {:keys [b] :as m} & [c]]
+                   (let [d (inc a) [e] m] (str/join a b c d e m x)))"
+            ),
+            vec!["defn", "let", "inc", "str/join", "x"]
+        );
+        assert_eq!(
+            usages("(for [x xs :let [y (g x)] :when (p y)] #(h % y))"),
+            vec!["for", "xs", "g", "p", "h"]
+        );
+        assert_eq!(
+            usages("(try (f) (catch Exception e (log e)) (finally (done)))"),
+            vec!["f", "log", "done"]
+        );
+        assert_eq!(
+            usages("(letfn [(a [x] (b x)) (b [y] (a y))] (a 1))"),
+            vec!["letfn"]
+        );
+        assert_eq!(
+            usages("(letfn [()] (f)) (letfn [[]] 1)"),
+            vec!["letfn", "f", "letfn"]
+        );
+        assert_eq!(
+            usages("(defmacro m [x] `(let [y# ~x] (foo y# ~&form)))"),
+            vec!["defmacro", "let", "foo"]
+        );
+        assert_eq!(
+            usages("(. obj (method arg)) (.foo bar) (Foo. 1)"),
+            vec!["obj", "arg", "bar"]
+        );
+        assert_eq!(
+            usages("(case x :a (f) b (g) (h))"),
+            vec!["case", "x", "f", "g", "h"]
+        );
+        assert_eq!(
+            usages("(ns foo) (doseq [n ['a]] (eval `(def ~n 1)))"),
+            vec!["doseq", "eval"]
+        );
+        assert_eq!(usages("(defn \"x\" [] (f))"), vec!["defn", "f"]);
+    }
+
+    #[test]
+    fn defs_test() {
+        let a = analyze_source(
+            "(ns foo (:require [a.b :as ab]))
+             (defn- private-f \"Doc\" ([x] x) ([x y] y))
+             (def ^:private v 1)
+             (defprotocol P (m [this]))
+             (defrecord R [a] P (m [this] a))
+             ::ab/kw",
+            "foo.clj",
+        )
+        .unwrap();
+
+        assert_eq!(a.ns_name(), Some("foo"));
+        assert_eq!(
+            a.defs
+                .iter()
+                .map(|d| (d.name.as_str(), d.private))
+                .collect::<Vec<_>>(),
+            vec![
+                ("private-f", true),
+                ("v", true),
+                ("P", false),
+                ("m", false),
+                ("R", false),
+                ("->R", false),
+                ("map->R", false)
+            ]
+        );
+        assert_eq!(a.defs[0].arglists, vec!["[x]", "[x y]"]);
+        assert_eq!(a.defs[0].doc.as_deref(), Some("Doc"));
+
+        // Def with a name that isn't a symbol keeps the previous def as it is
+        let b = analyze_source(
+            "(defn f \"Doc\" [x] x) (def ~n \"Other\" (fn [y]))",
+            "foo.clj",
+        )
+        .unwrap();
+        assert_eq!(b.defs.len(), 1);
+        assert_eq!(b.defs[0].doc.as_deref(), Some("Doc"));
+        assert_eq!(b.defs[0].arglists, vec!["[x]"]);
+        assert!(a
+            .usages
+            .iter()
+            .any(|u| u.keyword && u.ns.as_deref() == Some("ab")));
+    }
+
+    #[test]
+    fn references_test() {
+        let a = analyze_source(
+            "(ns foo (:require [clojure.string :as str :refer [join]] [a.b :refer :all]))
+             (defn split [x] x)
+             (str/join (join) (split) (clojure.string/split) (map) (helper))",
+            "foo.clj",
+        )
+        .unwrap();
+        let refs = |ns, name| {
+            a.usages
+                .iter()
+                .filter(|u| a.references(u, ns, name))
+                .map(|u| u.full_name())
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(refs("clojure.string", "join"), vec!["str/join", "join"]);
+        assert_eq!(
+            refs("clojure.string", "split"),
+            vec!["clojure.string/split"]
+        );
+        assert_eq!(refs("foo", "split"), vec!["split"]);
+        assert_eq!(refs("clojure.core", "map"), vec!["map"]);
+        assert_eq!(refs("a.b", "helper"), vec!["helper"]);
+        assert_eq!(
+            a.resolve(Some("str"), "join").as_deref(),
+            Some("clojure.string")
+        );
+        assert_eq!(a.resolve(None, "def"), None);
+    }
+
+    #[test]
+    fn cljc_features_test() {
+        let src = "(ns foo (:require #?(:clj [clojure.java.io :as io] :cljs [goog.string :as gs])))
+                   #?(:clj (def x 1) :cljs (def y 2))";
+        let requires = |a: &Analysis| {
+            let ns = a.ns.as_ref().unwrap();
+            ns.requires.iter().map(|r| r.ns.clone()).collect::<Vec<_>>()
+        };
+
+        let a = analyze_source(src, "foo.cljc").unwrap();
+        assert_eq!(requires(&a), vec!["clojure.java.io"]);
+        assert_eq!(a.defs[0].name, "x");
+
+        let a = analyze_source_with(src, &SessionKind::Cljs.features()).unwrap();
+        assert_eq!(requires(&a), vec!["goog.string"]);
+        assert_eq!(a.defs[0].name, "y");
+    }
+}
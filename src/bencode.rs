@@ -1,3 +1,5 @@
+#![allow(non_local_definitions)]
+
 use failure::Fail;
 use serde_bencode::value::Value;
 use serde_json::value::Value as JsonValue;
@@ -29,7 +31,7 @@ pub fn try_into_string(val: Value) -> Result<String, Error> {
 pub fn try_into_str_vec(val: Value) -> Result<Vec<String>, Error> {
     if let Value::List(vals) = val {
         vals.into_iter()
-            .map(|v| try_into_string(v))
+            .map(try_into_string)
             .collect::<Result<Vec<String>, Error>>()
     } else {
         Err(Error::InvalidType {
@@ -57,7 +59,7 @@ pub fn to_json_value(val: Value) -> Result<JsonValue, JsonError> {
         Value::List(items) => Ok(JsonValue::Array(
             items
                 .into_iter()
-                .map(|i| Ok(to_json_value(i)?))
+                .map(to_json_value)
                 .collect::<Result<Vec<JsonValue>, JsonError>>()?,
         )),
         Value::Dict(hm) => {
@@ -1,19 +1,500 @@
+//! Helper functions for commandline
+
+#![allow(non_local_definitions)]
+
+pub mod apropos;
+pub mod cljs;
+pub mod complete;
+pub mod daemon;
+pub mod doc;
+pub mod eval;
 pub mod find_def;
+pub mod find_usages;
+pub mod index;
+pub mod interrupt;
+pub mod jack_in;
+pub mod last;
+pub mod lint;
+pub mod load_file;
+pub mod lsp;
+pub mod nvim;
 pub mod op;
-pub mod doc;
+pub mod port;
 pub mod read_jar;
+pub mod reload;
+pub mod repl;
+pub mod require;
+pub mod run_tests;
+pub mod show_ns;
+pub mod source;
+pub mod status;
+pub mod stop;
+pub mod unused;
+
+use crate::config::{self, Session, SessionKind};
+use crate::jar;
+use crate::location;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::transport::Addr;
+use crate::nrepl::NreplOp;
+use crate::ns;
+use crate::reader;
+use crate::server;
+use clap::{clap_app, App, ArgMatches};
+use failure::{Error as StdError, Fail};
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use zip::result::ZipError;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "File doesn't have NS declaration")]
+    NoNsDecl,
+    #[fail(display = "Please specify nrepl PORT, no port file is found, `jack_in` starts nrepl")]
+    NoPort,
+    #[fail(display = "Bad {} value: {}", name, value)]
+    BadArg { name: String, value: String },
+    #[fail(display = "Failed to connect to nrepl: {}", error)]
+    ConnectFailed { error: nrepl::Error },
+    #[fail(display = "Interrupted")]
+    Interrupted,
+    #[fail(display = "{}", ex)]
+    EvalFailed { ex: String },
+    #[fail(display = "No form at {}:{}", line, column)]
+    NoForm { line: i64, column: i64 },
+}
+
+/// Subcommands which print their results and exit, so they could be run by daemon.
+/// `jack_in` isn't one of them, nrepl it starts shouldn't be a child of daemon.
+pub const FORWARDED: [&str; 23] = [
+    "show_ns",
+    "op",
+    "find_def",
+    "find_usages",
+    "read_jar",
+    "doc",
+    "eval",
+    "interrupt",
+    "complete",
+    "lint",
+    "unused",
+    "cljs",
+    "index",
+    "source",
+    "reload",
+    "require",
+    "load_file",
+    "apropos",
+    "run_tests",
+    "last",
+    "port",
+    "stop",
+    "status",
+];
+
+/// Opens nrepl connection when subcommand needs it
+pub type Connect<'a> = dyn Fn() -> Result<Arc<nrepl::NreplStream>, StdError> + 'a;
+
+/// Output format chosen by `--format`, see README for the schema of each subcommand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    /// `KEY VALUE` lines, list items are separated by empty lines
+    Kv,
+    /// Human readable text, the only format streaming `eval` output
+    Text,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Self::Json),
+            "kv" => Some(Self::Kv),
+            "text" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    /// Format given to `unrepl` or to its subcommand
+    pub fn from_matches(matches: &ArgMatches, sub_matches: Option<&ArgMatches>) -> Option<Self> {
+        sub_matches
+            .and_then(|m| m.value_of("format"))
+            .or_else(|| matches.value_of("format"))
+            .and_then(Self::from_name)
+    }
+}
+
+/// Where subcommands print to: stdout and stderr of the process or a daemon client
+pub struct Output {
+    out: Box<dyn Write>,
+    err: Box<dyn Write>,
+    format: Option<Format>,
+    /// Working directory of the process or the client, default DIR and FILE are resolved in it
+    cwd: PathBuf,
+}
+
+impl Output {
+    pub fn new(out: Box<dyn Write>, err: Box<dyn Write>, format: Option<Format>) -> Self {
+        Self {
+            out,
+            err,
+            format,
+            cwd: std::env::current_dir().unwrap_or_default(),
+        }
+    }
+
+    pub fn with_cwd(mut self, cwd: PathBuf) -> Self {
+        self.cwd = cwd;
+        self
+    }
+
+    pub fn stdio(format: Option<Format>) -> Self {
+        Self::new(
+            Box::new(std::io::stdout()),
+            Box::new(std::io::stderr()),
+            format,
+        )
+    }
+
+    /// Format asked by user or the subcommand's `default` one
+    pub fn format(&self, default: Format) -> Format {
+        self.format.unwrap_or(default)
+    }
+
+    /// Prints `value` in chosen format, `text` renders it for humans
+    pub fn print<T: Serialize>(
+        &mut self,
+        default: Format,
+        value: &T,
+        text: impl FnOnce(&T) -> String,
+    ) -> Result<(), StdError> {
+        match self.format(default) {
+            Format::Json => writeln!(self.out, "{}", serde_json::to_string(value)?)?,
+            Format::Kv => {
+                for line in kv_lines(&serde_json::to_value(value)?) {
+                    writeln!(self.out, "{}", line)?;
+                }
+            }
+            Format::Text => {
+                let text = text(value);
+                if !text.is_empty() {
+                    writeln!(self.out, "{}", text)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prints error as `{"error": {"kind": ..., "message": ...}}` object or `ERROR-KIND` and
+    /// `ERROR-MESSAGE` lines to stdout, text format goes to stderr as before
+    pub fn print_error(&mut self, e: &StdError) -> std::io::Result<()> {
+        let kind = error_kind(e);
+        let message = e.to_string();
+
+        match self.format(Format::Text) {
+            Format::Json => writeln!(
+                self.out,
+                "{}",
+                json!({"error": {"kind": kind, "message": message}})
+            ),
+            Format::Kv => writeln!(
+                self.out,
+                "ERROR-KIND {}\nERROR-MESSAGE {}",
+                kind,
+                escape_kv(&message)
+            ),
+            Format::Text => writeln!(self.err, "ERROR: {}", message),
+        }
+    }
+
+    pub fn out(&mut self) -> &mut dyn Write {
+        &mut self.out
+    }
+
+    pub fn err(&mut self) -> &mut dyn Write {
+        &mut self.err
+    }
+
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(unrepl =>
+        (version: "0.1")
+        (author: "Michael Lutsiuk <michael.lutsiuk@gmail.com>")
+        (@arg PORT: +takes_value -p --port
+            "Nrepl port, or URI nrepl://HOST:PORT or nrepl+unix:///PATH of nrepl socket")
+        (@arg host: +takes_value +global --host "Host of nrepl with PORT number, 127.0.0.1 by default")
+        (@arg format: --format +takes_value +global possible_value[json kv text] "Output format")
+    )
+    .subcommand(show_ns::app())
+    .subcommand(op::app())
+    .subcommand(find_def::app())
+    .subcommand(find_usages::app())
+    .subcommand(read_jar::app())
+    .subcommand(doc::app())
+    .subcommand(eval::app())
+    .subcommand(interrupt::app())
+    .subcommand(complete::app())
+    .subcommand(lint::app())
+    .subcommand(unused::app())
+    .subcommand(repl::app())
+    .subcommand(cljs::app())
+    .subcommand(index::app())
+    .subcommand(source::app())
+    .subcommand(reload::app())
+    .subcommand(require::app())
+    .subcommand(load_file::app())
+    .subcommand(apropos::app())
+    .subcommand(run_tests::app())
+    .subcommand(last::app())
+    .subcommand(port::app())
+    .subcommand(jack_in::app())
+    .subcommand(stop::app())
+    .subcommand(status::app())
+    .subcommand(nvim::app())
+    .subcommand(lsp::app())
+    .subcommand(daemon::app())
+}
+
+/// Host given with `--host`, it's global, so subcommands see it too
+pub fn nrepl_host<'a>(matches: &'a ArgMatches) -> &'a str {
+    matches.value_of("host").unwrap_or(server::LOCAL_HOST)
+}
+
+/// Address of nrepl from `--port` and `--host` or port file found for `file`,
+/// see `nrepl::port::discover`
+pub fn nrepl_addr(matches: &ArgMatches, file: Option<&str>) -> Result<Addr, Error> {
+    let host = nrepl_host(matches);
+
+    let port_str = match matches.value_of("PORT") {
+        Some(port_str) => port_str,
+        None => {
+            let cwd = std::env::current_dir().unwrap_or_default();
+            return nrepl::port::discover(file.map(Path::new), &cwd, host)
+                .map(|found| Addr::tcp(host, found.port))
+                .ok_or(Error::NoPort);
+        }
+    };
+    let bad_arg = || Error::BadArg {
+        name: "port".to_string(),
+        value: port_str.to_string(),
+    };
 
-///! Helper functions for commandline
+    match port_str.parse::<u16>() {
+        Ok(port) => Ok(Addr::tcp(host, port)),
+        Err(_) => port_str.parse::<Addr>().map_err(|_| bad_arg()),
+    }
+}
+
+pub fn connect(addr: &Addr) -> Result<Arc<nrepl::NreplStream>, StdError> {
+    match nrepl::NreplStream::new(addr) {
+        Ok(n) => Ok(Arc::new(n)),
+        Err(error) => Err(Error::ConnectFailed { error }.into()),
+    }
+}
+
+/// Runs one of `FORWARDED` subcommands, `connect` is called only by those needing nrepl
+pub fn run(
+    name: &str,
+    matches: &ArgMatches,
+    connect: &Connect,
+    out: &mut Output,
+) -> Result<(), StdError> {
+    match name {
+        "show_ns" => show_ns::run(matches, connect, out),
+        "op" => op::run(matches, &*connect()?, out),
+        "find_def" => find_def::run(matches, connect, out),
+        "find_usages" => find_usages::run(matches, connect, out),
+        "read_jar" => read_jar::run(matches, out),
+        "doc" => doc::run(matches, connect, out),
+        "eval" => eval::run(matches, &*connect()?, out),
+        "interrupt" => interrupt::run(matches, &*connect()?, out),
+        "complete" => complete::run(matches, connect, out),
+        "lint" => lint::run(matches, &*connect()?, out),
+        "unused" => unused::run(matches, out),
+        "cljs" => cljs::run(matches, &*connect()?, out),
+        "index" => index::run(matches, connect, out),
+        "source" => source::run(matches, connect, out),
+        "reload" => reload::run(matches, &*connect()?, out),
+        "require" => require::run(matches, &*connect()?, out),
+        "load_file" => load_file::run(matches, &*connect()?, out),
+        "apropos" => apropos::run(matches, &*connect()?, out),
+        "run_tests" => run_tests::run(matches, &*connect()?, out),
+        "last" => last::run(matches, &*connect()?, out),
+        "port" => port::run(matches, out),
+        "jack_in" => jack_in::run(matches, out),
+        "stop" => stop::run(matches, out),
+        "status" => status::run(matches, out),
+        _ => Ok(()),
+    }
+}
+
+/// Connects to nrepl for subcommands which can answer from the index built by `index` too.
+/// `None` means nrepl isn't running, but there's the index.
+pub fn connect_or_index(connect: &Connect) -> Result<Option<Arc<nrepl::NreplStream>>, StdError> {
+    match connect() {
+        Ok(n) => Ok(Some(n)),
+        Err(e) => match e.downcast_ref::<Error>() {
+            Some(Error::NoPort) | Some(Error::ConnectFailed { .. })
+                if !config::with_db(|conn| crate::index::is_empty(conn))? =>
+            {
+                Ok(None)
+            }
+            _ => Err(e),
+        },
+    }
+}
+
+/// Returns NS of `file` (`-` for stdin), reads it without nrepl when possible and falls back to
+/// evaluating `clojure.tools.namespace` on nrepl side, which is possible only in Clojure session
+pub fn file_ns(
+    file: &str,
+    session: &Session,
+    nrepl_stream: &nrepl::NreplStream,
+) -> Result<Option<String>, failure::Error> {
+    match ns::read_file_ns_decl(file) {
+        Ok(Some(decl)) => Ok(Some(decl.name)),
+        _ if file == "-" || session.kind() == SessionKind::Cljs => Ok(None),
+        _ => ops::GetNsName::new(file.to_string(), session.clone()).send(nrepl_stream),
+    }
+}
 
 pub fn die_err(msg: &str) -> ! {
     eprintln!("{}", msg);
     std::process::exit(1);
 }
 
-pub fn print_parseable(data: &Vec<(&str, String)>) {
+/// Machine readable kind of error, part of the documented error object
+pub fn error_kind(e: &StdError) -> &'static str {
+    if let Some(e) = e.downcast_ref::<Error>() {
+        return match e {
+            Error::NoNsDecl => "no-ns-decl",
+            Error::NoPort => "no-port",
+            Error::BadArg { .. } => "bad-arg",
+            Error::ConnectFailed { .. } => "connect-failed",
+            Error::Interrupted => "interrupted",
+            Error::EvalFailed { .. } => "eval-failed",
+            Error::NoForm { .. } => "no-form",
+        };
+    }
+    if let Some(e) = e.downcast_ref::<nrepl::Error>() {
+        return match e {
+            nrepl::Error::ConnectionClosed => "connection-closed",
+            nrepl::Error::Timeout { .. } => "timeout",
+            nrepl::Error::IOError { .. } => "io",
+            _ => "nrepl-protocol",
+        };
+    }
+    if let Some(e) = e.downcast_ref::<ops::Error>() {
+        return match e {
+            ops::Error::InfoOpUnavailable
+            | ops::Error::CompleteOpUnavailable
+            | ops::Error::XrefOpUnavailable { .. } => "op-unavailable",
+            _ => "nrepl-response",
+        };
+    }
+    if let Some(e) = e.downcast_ref::<nrepl::session::Error>() {
+        return match e {
+            nrepl::session::Error::NoCljsSession => "no-cljs-session",
+            nrepl::session::Error::CljsUpgradeFailed { .. } => "cljs-upgrade-failed",
+            _ => "session",
+        };
+    }
+    if let Some(interrupt::Error::NoEvalStarted) = e.downcast_ref::<interrupt::Error>() {
+        return "no-eval-started";
+    }
+    if e.downcast_ref::<jar::Error>().is_some() || e.downcast_ref::<ZipError>().is_some() {
+        return "jar";
+    }
+    if e.downcast_ref::<find_def::Error>().is_some() {
+        return "no-java-sources";
+    }
+    if let Some(e) = e.downcast_ref::<server::Error>() {
+        return match e {
+            server::Error::UnknownTool { .. } => "unknown-tool",
+            _ => "jack-in-failed",
+        };
+    }
+    if e.downcast_ref::<nrepl::transport::Error>().is_some() {
+        return "bad-arg";
+    }
+    if e.downcast_ref::<location::Error>().is_some() {
+        return "bad-location";
+    }
+    if e.downcast_ref::<config::Error>().is_some() || e.downcast_ref::<rusqlite::Error>().is_some()
+    {
+        return "config";
+    }
+    if e.downcast_ref::<reader::Error>().is_some() || e.downcast_ref::<ns::Error>().is_some() {
+        return "read";
+    }
+    if e.downcast_ref::<std::io::Error>().is_some() {
+        return "io";
+    }
+
+    "unknown"
+}
+
+/// Newlines are the only thing which can't appear in `KEY VALUE` line
+fn escape_kv(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn kv_value(value: &JsonValue) -> Option<String> {
+    match value {
+        JsonValue::Null => None,
+        JsonValue::String(s) => Some(escape_kv(s)),
+        JsonValue::Bool(b) => Some(if *b { "TRUE" } else { "FALSE" }.to_string()),
+        value => Some(value.to_string()),
+    }
+}
+
+/// Renders object as `KEY VALUE` lines, arrays give a line per item with the same key.
+/// Top level arrays give a block of lines per item separated by empty lines.
+pub fn kv_lines(value: &JsonValue) -> Vec<String> {
+    match value {
+        JsonValue::Object(map) => {
+            let mut lines = vec![];
+
+            for (k, v) in map {
+                let key = k.to_uppercase().replace('_', "-");
+                let items = match v {
+                    JsonValue::Array(items) => items.iter().collect(),
+                    v => vec![v],
+                };
+
+                for item in items {
+                    if let Some(item) = kv_value(item) {
+                        lines.push(format!("{} {}", key, item));
+                    }
+                }
+            }
+
+            lines
+        }
+        JsonValue::Array(items) => items
+            .iter()
+            .map(kv_lines)
+            .collect::<Vec<Vec<String>>>()
+            .join(&String::new()),
+        value => kv_value(value).into_iter().collect(),
+    }
+}
+
+pub fn print_parseable(out: &mut dyn Write, data: &Vec<(&str, String)>) -> std::io::Result<()> {
     for (k, v) in data {
-        println!("{} {}", k.to_uppercase(), v)
+        writeln!(out, "{} {}", k.to_uppercase(), v)?;
     }
+
+    Ok(())
 }
 
 pub fn die_if_err<T, E: std::fmt::Display>(res: Result<T, E>) -> T {
@@ -24,3 +505,29 @@ pub fn die_if_err<T, E: std::fmt::Display>(res: Result<T, E>) -> T {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kv_lines_test() {
+        let value = json!([
+            {"file": "a b.clj", "line": 1, "is_ns": true, "jar": null},
+            {"doc": "x\ny", "items": ["a", "b"]}
+        ]);
+
+        assert_eq!(
+            kv_lines(&value),
+            vec![
+                "FILE a b.clj",
+                "IS-NS TRUE",
+                "LINE 1",
+                "",
+                "DOC x\\ny",
+                "ITEMS a",
+                "ITEMS b"
+            ]
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use crate::cmd;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(apropos =>
+        (about: "Lists public vars with names matching QUERY, like `:Apropos` of fireplace")
+        (@arg docs: -d --docs "Match docstrings too")
+        (@arg QUERY: +required "Case-insensitive regex")
+    )
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    nrepl_stream: &nrepl::NreplStream,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let session = session::get_existing_session_id(nrepl_stream)?;
+    let query = matches.value_of("QUERY").unwrap().to_string();
+
+    let found = ops::Apropos::new(session, query)
+        .docs(matches.is_present("docs"))
+        .send(nrepl_stream)?;
+
+    out.print(cmd::Format::Text, &found, |found| {
+        found
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n")
+    })
+}
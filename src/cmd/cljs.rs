@@ -0,0 +1,58 @@
+use crate::cmd;
+use crate::config::SessionKind;
+use crate::nrepl;
+use crate::nrepl::session;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde_json::json;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(cljs =>
+        (about: "Starts ClojureScript session used for .cljs files, prints its id")
+        (@group upgrade =>
+            (@arg shadow: -s --shadow +takes_value "shadow-cljs BUILD to select")
+            (@arg piggieback: -p --piggieback +takes_value "REPL-ENV form for piggieback, like (cljs.repl.node/repl-env)")
+        )
+        (@arg cljc: --cljc +takes_value possible_value[clj cljs] "Session kind to use for .cljc files")
+    )
+}
+
+/// Code which turns Clojure session into ClojureScript one
+fn upgrade_code(matches: &ArgMatches) -> Option<String> {
+    if let Some(build) = matches.value_of("shadow") {
+        let build = build.trim_start_matches(':');
+        return Some(format!(
+            "(do (require 'shadow.cljs.devtools.api) (shadow.cljs.devtools.api/nrepl-select :{}))",
+            build
+        ));
+    }
+
+    matches.value_of("piggieback").map(|repl_env| {
+        format!(
+            "(do (require 'cider.piggieback) (cider.piggieback/cljs-repl {}))",
+            repl_env
+        )
+    })
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    nrepl_stream: &nrepl::NreplStream,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    if let Some(kind) = matches.value_of("cljc").and_then(SessionKind::from_name) {
+        session::set_cljc_kind(nrepl_stream, kind)?;
+    }
+
+    let session = match upgrade_code(matches) {
+        Some(code) => session::start_cljs(nrepl_stream, &code)?,
+        None => session::get_session(nrepl_stream, SessionKind::Cljs)?,
+    };
+
+    let cljc = session::cljc_kind(nrepl_stream)?.name();
+    let result = json!({ "session": session.id(), "cljc": cljc });
+
+    out.print(cmd::Format::Kv, &result, |_| {
+        format!("Session {}, .cljc files use {}", session.id(), cljc)
+    })
+}
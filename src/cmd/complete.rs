@@ -0,0 +1,159 @@
+use crate::analysis;
+use crate::cmd;
+use crate::config::{self, Session};
+use crate::index;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use crate::project;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde::Serialize;
+
+struct Opts {
+    file: String,
+    prefix: String,
+    ns: Option<String>,
+    context: Option<String>,
+}
+
+impl Opts {
+    fn parse(matches: &ArgMatches) -> Opts {
+        Opts {
+            file: matches.value_of("FILE").unwrap().to_string(),
+            prefix: matches.value_of("PREFIX").unwrap().to_string(),
+            ns: matches.value_of("ns").map(|ns| ns.to_string()),
+            context: matches.value_of("context").map(|c| c.to_string()),
+        }
+    }
+}
+
+/// Item of vim's `complete-items`, see `:help complete-items`
+#[derive(Debug, Serialize)]
+pub struct CompleteItem {
+    pub word: String,
+    pub kind: String,
+    pub menu: String,
+    pub info: String,
+    pub icase: i64,
+}
+
+fn kind(candidate_type: &Option<String>) -> String {
+    match candidate_type.as_deref() {
+        Some("function") => "f",
+        Some("macro") => "m",
+        Some("var") => "v",
+        Some("namespace") => "n",
+        Some("class") => "c",
+        Some("method") | Some("static-method") => "M",
+        Some("field") | Some("static-field") => "F",
+        Some("keyword") => "k",
+        Some("local") => "l",
+        Some("special-form") => "s",
+        _ => "",
+    }
+    .to_string()
+}
+
+impl From<ops::Candidate> for CompleteItem {
+    fn from(c: ops::Candidate) -> Self {
+        let info = vec![c.arglists.join("\n"), c.doc.clone().unwrap_or_default()]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        CompleteItem {
+            kind: kind(&c.candidate_type),
+            menu: c.ns.unwrap_or_default(),
+            word: c.candidate,
+            info,
+            icase: 0,
+        }
+    }
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(complete =>
+        (about: "Completes PREFIX, prints JSON list of vim complete-items")
+        (@arg ns: -n --ns +takes_value "NS to complete in, overrides NS of FILE")
+        (@arg context: -c --context +takes_value "Top level form with __prefix__ in place of PREFIX")
+        (@arg FILE: +required "FILE with NS to complete in")
+        (@arg PREFIX: +required "PREFIX to complete")
+    )
+}
+
+/// Completes `prefix` in `ns` or NS of `file` when `ns` is not given
+pub fn complete_items(
+    nrepl_stream: &nrepl::NreplStream,
+    session: &Session,
+    file: &str,
+    ns: Option<String>,
+    prefix: String,
+    context: Option<String>,
+) -> Result<Vec<CompleteItem>, failure::Error> {
+    let ns = match ns {
+        Some(ns) => Some(ns),
+        None => cmd::file_ns(file, session, nrepl_stream)?,
+    };
+
+    let op = ops::Complete::new(session.clone(), ns, prefix).context(context);
+
+    Ok(op
+        .send(nrepl_stream)?
+        .into_iter()
+        .map(CompleteItem::from)
+        .collect())
+}
+
+/// Completes `prefix` from the index, for when nrepl isn't running
+pub fn index_complete_items(
+    file: &str,
+    ns: Option<String>,
+    prefix: &str,
+) -> Result<Vec<CompleteItem>, failure::Error> {
+    let analysis = analysis::analyze_file(file)?;
+    let root = project::file_root(file)?.to_string_lossy().to_string();
+    let ns = ns
+        .or_else(|| analysis.ns_name().map(String::from))
+        .unwrap_or_else(|| "user".to_string());
+
+    Ok(
+        config::with_db(|conn| index::complete(conn, &root, &analysis, &ns, prefix))?
+            .into_iter()
+            .map(CompleteItem::from)
+            .collect(),
+    )
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    connect: &cmd::Connect,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let opts = Opts::parse(matches);
+
+    let items = match cmd::connect_or_index(connect)? {
+        Some(nrepl_stream) => {
+            let session = session::get_file_session(&nrepl_stream, Some(&opts.file))?;
+            complete_items(
+                &nrepl_stream,
+                &session,
+                &opts.file,
+                opts.ns,
+                opts.prefix,
+                opts.context,
+            )?
+        }
+        None => index_complete_items(&opts.file, opts.ns, &opts.prefix)?,
+    };
+
+    out.print(cmd::Format::Json, &items, |items| {
+        items
+            .iter()
+            .map(|i| i.word.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n")
+    })
+}
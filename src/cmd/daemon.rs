@@ -0,0 +1,328 @@
+//! Daemon keeping nrepl connections and sessions between invocations of the CLI.
+//!
+//! It listens on a unix socket in `config_path()`. A client sends a single JSON line with its
+//! arguments, nrepl address and working directory, the daemon runs the subcommand and answers
+//! with JSON lines of `{"out": ...}` and `{"err": ...}` output followed by `{"exit": code}`.
+
+#![allow(non_local_definitions)]
+
+use crate::cmd;
+use crate::config;
+use crate::nrepl;
+use clap::{clap_app, App, ArgMatches};
+use failure::{Error as StdError, Fail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Args which are paths, daemon resolves them in working directory of client as it has its own
+const PATH_ARGS: [&str; 4] = ["FILE", "file", "JAR", "DIR"];
+
+#[derive(Debug, Fail)]
+enum Error {
+    #[fail(display = "Daemon is already running on {}", path)]
+    AlreadyRunning { path: String },
+    #[fail(display = "Daemon closed connection before subcommand finished")]
+    ConnectionClosed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Request {
+    args: Vec<String>,
+    /// Nrepl address resolved by client, `None` when it's not known
+    addr: Option<String>,
+    /// Absolute working directory of client, path args and their defaults are resolved in it
+    cwd: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Response {
+    Out(String),
+    Err(String),
+    Exit(i32),
+}
+
+/// Sends everything written to it as `Response::Out` or `Response::Err`
+struct ResponseWriter {
+    stream: Arc<Mutex<UnixStream>>,
+    is_err: bool,
+}
+
+impl ResponseWriter {
+    fn send(stream: &Mutex<UnixStream>, resp: &Response) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(resp)?;
+        line.push('\n');
+        stream.lock().unwrap().write_all(line.as_bytes())
+    }
+}
+
+impl Write for ResponseWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let text = String::from_utf8_lossy(buf).to_string();
+        let resp = if self.is_err {
+            Response::Err(text)
+        } else {
+            Response::Out(text)
+        };
+
+        Self::send(&self.stream, &resp)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Daemon {
+    /// Connections by nrepl address
+    streams: Mutex<HashMap<String, Arc<nrepl::NreplStream>>>,
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(daemon =>
+        (about: "Runs daemon which keeps nrepl connections, other subcommands are forwarded to it")
+    )
+}
+
+pub fn socket_path() -> PathBuf {
+    let mut path = config::config_path();
+    path.push("daemon.sock");
+    path
+}
+
+impl Daemon {
+    fn stream(&self, addr: &Option<String>) -> Result<Arc<nrepl::NreplStream>, StdError> {
+        let addr = match addr {
+            Some(addr) => addr,
+            None => return Err(cmd::Error::NoPort.into()),
+        };
+        let mut streams = self.streams.lock().unwrap();
+
+        if let Some(stream) = streams.get(addr) {
+            if !stream.is_closed() {
+                return Ok(stream.clone());
+            }
+        }
+
+        let stream = cmd::connect(&addr.parse()?)?;
+        streams.insert(addr.to_string(), stream.clone());
+
+        Ok(stream)
+    }
+
+    fn handle(&self, stream: UnixStream) -> Result<(), StdError> {
+        let mut line = String::new();
+        BufReader::new(stream.try_clone()?).read_line(&mut line)?;
+        let req: Request = serde_json::from_str(&line)?;
+
+        let stream = Arc::new(Mutex::new(stream));
+        let matches = cmd::app().get_matches_from_safe(absolute_args(&req.args, &req.cwd));
+        let format = matches
+            .as_ref()
+            .ok()
+            .and_then(|m| cmd::Format::from_matches(m, m.subcommand().1));
+        let mut out = cmd::Output::new(
+            Box::new(ResponseWriter {
+                stream: stream.clone(),
+                is_err: false,
+            }),
+            Box::new(ResponseWriter {
+                stream: stream.clone(),
+                is_err: true,
+            }),
+            format,
+        )
+        .with_cwd(req.cwd.clone());
+
+        let code = match matches {
+            Ok(matches) => {
+                let (name, argm) = matches.subcommand();
+                let connect = || self.stream(&req.addr);
+
+                match argm.map(|argm| cmd::run(name, argm, &connect, &mut out)) {
+                    Some(Err(e)) => {
+                        out.print_error(&e)?;
+                        1
+                    }
+                    _ => 0,
+                }
+            }
+            Err(e) => {
+                writeln!(out.err(), "{}", e)?;
+                1
+            }
+        };
+
+        ResponseWriter::send(&stream, &Response::Exit(code))?;
+
+        Ok(())
+    }
+}
+
+/// Tells if subcommand needs stdin of the client, such subcommands are not forwarded
+fn reads_stdin(name: &str, matches: &ArgMatches) -> bool {
+    (name == "eval" && !matches.is_present("CODE"))
+        || PATH_ARGS.iter().any(|a| matches.value_of(a) == Some("-"))
+}
+
+/// Value of path arg `name` of subcommand parsed from `args`
+fn path_arg(args: &[String], name: &str) -> Option<String> {
+    let matches = cmd::app().get_matches_from_safe(args).ok()?;
+    let (subcommand, argm) = matches.subcommand();
+
+    // FILE of `read_jar` is an entry inside of JAR
+    if subcommand == "read_jar" && name == "FILE" {
+        return None;
+    }
+
+    argm?.value_of(name).map(String::from)
+}
+
+/// Replaces relative path args with absolute ones in `cwd`. The same text may be a value of
+/// another arg, like in `doc x x`, so the place of each value is the one where clap sees it.
+fn absolute_args(args: &[String], cwd: &Path) -> Vec<String> {
+    let mut args = args.to_vec();
+
+    for name in PATH_ARGS.iter() {
+        let value = match path_arg(&args, name) {
+            Some(value) if value != "-" && Path::new(&value).is_relative() => value,
+            _ => continue,
+        };
+        let absolute = cwd.join(&value).to_string_lossy().to_string();
+
+        // Value is either an arg on its own or follows `=` of `--file=value`
+        for i in 0..args.len() {
+            let mut candidate = args.clone();
+            candidate[i] = match args[i].strip_suffix(value.as_str()) {
+                Some(prefix) => format!("{}{}", prefix, absolute),
+                None => continue,
+            };
+
+            if path_arg(&candidate, name).as_deref() == Some(absolute.as_str()) {
+                args = candidate;
+                break;
+            }
+        }
+    }
+
+    args
+}
+
+/// Runs subcommand in the daemon if it's running, returns exit code of the subcommand.
+/// `None` means the subcommand should be run directly.
+pub fn forward(
+    args: Vec<String>,
+    addr: Option<String>,
+    name: &str,
+    matches: &ArgMatches,
+) -> Option<Result<i32, StdError>> {
+    if !cmd::FORWARDED.contains(&name) || reads_stdin(name, matches) {
+        return None;
+    }
+
+    let cwd = std::env::current_dir().ok()?;
+    let stream = UnixStream::connect(socket_path()).ok()?;
+    let req = Request { args, addr, cwd };
+
+    Some(send_request(stream, req))
+}
+
+fn send_request(mut stream: UnixStream, req: Request) -> Result<i32, StdError> {
+    let mut line = serde_json::to_string(&req)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+
+    for line in BufReader::new(stream).lines() {
+        match serde_json::from_str(&line?)? {
+            Response::Out(text) => {
+                print!("{}", text);
+                std::io::stdout().flush()?;
+            }
+            Response::Err(text) => eprint!("{}", text),
+            Response::Exit(code) => return Ok(code),
+        }
+    }
+
+    Err(Error::ConnectionClosed.into())
+}
+
+pub fn run(_matches: &ArgMatches) -> Result<(), StdError> {
+    let path = socket_path();
+
+    if UnixStream::connect(&path).is_ok() {
+        return Err(Error::AlreadyRunning {
+            path: path.to_string_lossy().to_string(),
+        }
+        .into());
+    }
+
+    // Socket file is left behind when daemon is killed
+    if path.exists() {
+        std::fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    let handler_path = path.clone();
+    ctrlc::set_handler(move || {
+        let _ = std::fs::remove_file(&handler_path);
+        std::process::exit(0);
+    })?;
+
+    cmd::print_parseable(
+        &mut std::io::stdout(),
+        &vec![("SOCKET", path.to_string_lossy().to_string())],
+    )?;
+
+    let daemon = Arc::new(Daemon {
+        streams: Mutex::new(HashMap::new()),
+    });
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let daemon = daemon.clone();
+
+        // Each client gets its own thread, so `interrupt` works while `eval` is running
+        std::thread::spawn(move || {
+            if let Err(e) = daemon.handle(stream) {
+                eprintln!("ERROR: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute_args_test() {
+        let absolute = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            absolute_args(&args, Path::new("/home/me"))
+        };
+
+        assert_eq!(
+            absolute(&["unrepl", "doc", "x", "x"]),
+            vec!["unrepl", "doc", "/home/me/x", "x"]
+        );
+        assert_eq!(
+            absolute(&["unrepl", "eval", "--file=src/a.clj", "(inc 1)"]),
+            vec!["unrepl", "eval", "--file=/home/me/src/a.clj", "(inc 1)"]
+        );
+        assert_eq!(
+            absolute(&["unrepl", "read_jar", "lib/a.jar", "foo/core.clj"]),
+            vec!["unrepl", "read_jar", "/home/me/lib/a.jar", "foo/core.clj"]
+        );
+        assert_eq!(
+            absolute(&["unrepl", "doc", "/tmp/a.clj", "x"]),
+            vec!["unrepl", "doc", "/tmp/a.clj", "x"]
+        );
+    }
+}
@@ -1,9 +1,15 @@
+use crate::analysis;
 use crate::cmd;
+use crate::config::{self, Session};
+use crate::index;
 use crate::nrepl;
 use crate::nrepl::ops;
 use crate::nrepl::session;
 use crate::nrepl::NreplOp;
+use crate::project;
 use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde_json::json;
 
 struct Opts {
     file: String,
@@ -22,19 +28,53 @@ impl Opts {
 pub fn app<'a, 'b>() -> App<'a, 'b> {
     clap_app!(doc =>
         (about: "Shows DOC for symbol")
-        (@arg FILE: +required "FILE with NS containing SYMBOL")
+        (@arg FILE: +required "FILE with NS containing SYMBOL, `-` to read it from stdin")
         (@arg SYMBOL: +required "SYMBOL")
     )
 }
 
-pub fn run(matches: &ArgMatches, nrepl_stream: &nrepl::NreplStream) {
+/// Returns doc of `symbol` in NS of `file`
+pub fn doc(
+    nrepl_stream: &nrepl::NreplStream,
+    session: &Session,
+    file: &str,
+    symbol: &str,
+) -> Result<Option<String>, failure::Error> {
+    let ns = match cmd::file_ns(file, session, nrepl_stream)? {
+        Some(ns) => ns,
+        None => return Err(cmd::Error::NoNsDecl.into()),
+    };
+
+    let op = ops::Info::new(session.clone(), ns, symbol.to_string());
+
+    Ok(op.send(nrepl_stream)?.map(|res| res.into_doc()))
+}
+
+/// Returns doc of `symbol` from the index, for when nrepl isn't running
+pub fn index_doc(file: &str, symbol: &str) -> Result<Option<String>, StdError> {
+    let analysis = analysis::analyze_file(file)?;
+    let root = project::file_root(file)?.to_string_lossy().to_string();
+    let found = config::with_db(|conn| index::resolve(conn, &root, &analysis, symbol))?;
+
+    Ok(found.map(|indexed| indexed.doc()))
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    connect: &cmd::Connect,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
     let opts = Opts::parse(matches);
-    let session = cmd::die_if_err(session::get_existing_session_id(nrepl_stream));
-    let ns = cmd::die_if_err(ops::GetNsName::new(opts.file, session.clone()).send(nrepl_stream));
-    let op = ops::Info::new(session, ns.unwrap(), opts.symbol);
-    let res = cmd::die_if_err(op.send(nrepl_stream));
 
-    if let Some(res) = res {
-        println!("{}", res.into_resp().doc);
-    }
+    let doc = match cmd::connect_or_index(connect)? {
+        Some(nrepl_stream) => {
+            let session = session::get_file_session(&nrepl_stream, Some(&opts.file))?;
+            doc(&nrepl_stream, &session, &opts.file, &opts.symbol)?
+        }
+        None => index_doc(&opts.file, &opts.symbol)?,
+    };
+
+    out.print(cmd::Format::Text, &json!({ "doc": doc }), |_| {
+        doc.clone().unwrap_or_default()
+    })
 }
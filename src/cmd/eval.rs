@@ -0,0 +1,209 @@
+use crate::bencode as bc;
+use crate::cmd;
+use crate::config;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use crate::reader;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use std::io::Read;
+
+struct Opts {
+    code: String,
+    file: Option<String>,
+    ns: Option<String>,
+    line: Option<i64>,
+    column: Option<i64>,
+}
+
+fn parse_int_arg(matches: &ArgMatches, name: &str) -> Result<Option<i64>, cmd::Error> {
+    match matches.value_of(name) {
+        Some(v) => match v.parse::<i64>() {
+            Ok(n) => Ok(Some(n)),
+            _ => Err(cmd::Error::BadArg {
+                name: name.to_string(),
+                value: v.to_string(),
+            }),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Text and position of the form at LINE and COLUMN of source read from stdin
+fn form_code(outermost: bool, line: i64, column: i64) -> Result<(String, i64, i64), StdError> {
+    let mut source = String::new();
+    std::io::stdin().read_to_string(&mut source)?;
+
+    let nodes = reader::read_all(&source)?;
+    let node = reader::form_at(&nodes, line as usize, column as usize, outermost)
+        .ok_or(cmd::Error::NoForm { line, column })?;
+
+    Ok((
+        source[node.start.offset..node.end.offset].to_string(),
+        node.start.line as i64,
+        node.start.column as i64,
+    ))
+}
+
+impl Opts {
+    fn parse(matches: &ArgMatches) -> Result<Opts, StdError> {
+        let mut line = parse_int_arg(matches, "line")?;
+        let mut column = parse_int_arg(matches, "column")?;
+
+        let code = match (matches.value_of("form"), matches.value_of("CODE")) {
+            (Some(form), _) => {
+                let (code, form_line, form_column) =
+                    form_code(form == "outermost", line.unwrap_or(1), column.unwrap_or(1))?;
+                line = Some(form_line);
+                column = Some(form_column);
+                code
+            }
+            (None, Some(code)) => code.to_string(),
+            (None, None) => {
+                let mut code = String::new();
+                std::io::stdin().read_to_string(&mut code)?;
+                code
+            }
+        };
+
+        Ok(Opts {
+            code,
+            file: matches.value_of("file").map(|f| f.to_string()),
+            ns: matches.value_of("ns").map(|ns| ns.to_string()),
+            line,
+            column,
+        })
+    }
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(eval =>
+        (about: "Evaluates CODE, reads it from stdin when CODE is not given")
+        (@arg file: -f --file +takes_value "FILE to take NS from and to report in stacktraces")
+        (@arg ns: -n --ns +takes_value "NS to evaluate CODE in, overrides NS of FILE")
+        (@arg line: -l --line +takes_value "LINE of CODE in FILE")
+        (@arg column: -c --column +takes_value "COLUMN of CODE in FILE")
+        (@arg form: --form +takes_value possible_value[innermost outermost] requires[line column]
+            conflicts_with[CODE]
+            "Evaluate the innermost or outermost form at LINE and COLUMN, source of FILE is read \
+             from stdin then")
+        (@arg CODE: "CODE to evaluate")
+    )
+}
+
+/// Prints `out`, `value` and `err` of eval response as soon as they arrive
+pub fn print_output(out: &mut cmd::Output, resp: &nrepl::Resp) -> std::io::Result<()> {
+    if let Some(s) = resp.get("out") {
+        write!(
+            out.out(),
+            "{}",
+            bc::try_into_string(s.clone()).unwrap_or_default()
+        )?;
+        out.out().flush()?;
+    }
+    if let Some(value) = resp.get("value") {
+        writeln!(
+            out.out(),
+            "{}",
+            bc::try_into_string(value.clone()).unwrap_or_default()
+        )?;
+    }
+    if let Some(err) = resp.get("err") {
+        write!(
+            out.err(),
+            "{}",
+            bc::try_into_string(err.clone()).unwrap_or_default()
+        )?;
+        out.err().flush()?;
+    }
+
+    Ok(())
+}
+
+/// Output, values and exception of evaluation in the order REPL would show them
+pub fn result_text(res: &ops::EvalResult) -> String {
+    let mut lines: Vec<String> = (res.out.concat() + &res.err.concat())
+        .lines()
+        .map(String::from)
+        .collect();
+    lines.extend(res.values.iter().cloned());
+
+    if let Some(ex) = res.root_ex.as_ref().or(res.ex.as_ref()) {
+        lines.push(ex.to_string());
+    }
+
+    lines.join("\n")
+}
+
+/// Prints result of evaluation which wasn't streamed, exceptions are errors only in text format
+pub fn print_result(out: &mut cmd::Output, res: &ops::EvalResult) -> Result<(), StdError> {
+    if out.format(cmd::Format::Text) != cmd::Format::Text {
+        return out.print(cmd::Format::Text, res, |_| String::new());
+    }
+
+    write!(out.out(), "{}", res.out.concat())?;
+    for value in res.values.iter() {
+        writeln!(out.out(), "{}", value)?;
+    }
+    write!(out.err(), "{}", res.err.concat())?;
+
+    match res.root_ex.as_ref().or(res.ex.as_ref()) {
+        Some(ex) => Err(cmd::Error::EvalFailed { ex: ex.to_string() }.into()),
+        None => Ok(()),
+    }
+}
+
+/// Setting with the result of the last evaluation, shown by `last`
+pub const LAST_RESULT_SETTING: &str = "last_eval_result";
+
+fn save_last_result(addr: &str, res: &ops::EvalResult) -> Result<(), StdError> {
+    config::save_setting(addr, LAST_RESULT_SETTING, &serde_json::to_string(res)?)
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    nrepl_stream: &nrepl::NreplStream,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let opts = Opts::parse(matches)?;
+    let session = session::get_file_session(nrepl_stream, opts.file.as_deref())?;
+
+    let ns = match (opts.ns, &opts.file) {
+        (Some(ns), _) => Some(ns),
+        (None, Some(file)) => cmd::file_ns(file, &session, nrepl_stream)?,
+        (None, None) => None,
+    };
+
+    let id = nrepl_stream.gen_id();
+    config::save_last_eval_id(&session, &id)?;
+
+    let addr = nrepl_stream.addr_string();
+    let op = ops::Eval::new(session, opts.code)
+        .id(id)
+        .ns(ns)
+        .file(opts.file)
+        .position(opts.line, opts.column);
+
+    // Structured formats print whole result, exceptions are a part of it
+    if out.format(cmd::Format::Text) != cmd::Format::Text {
+        let res = op.send(nrepl_stream)?;
+        save_last_result(&addr, &res)?;
+        return out.print(cmd::Format::Text, &res, |_| String::new());
+    }
+
+    let res = op.send_stream(nrepl_stream, |resp| {
+        let _ = print_output(out, resp);
+    })?;
+    save_last_result(&addr, &res)?;
+
+    if res.interrupted {
+        return Err(cmd::Error::Interrupted.into());
+    }
+
+    match res.root_ex.or(res.ex) {
+        Some(ex) => Err(cmd::Error::EvalFailed { ex }.into()),
+        None => Ok(()),
+    }
+}
@@ -1,139 +1,282 @@
+#![allow(non_local_definitions)]
+
+use crate::analysis;
 use crate::cmd;
+use crate::config::{self, Session};
+use crate::index::{self, Indexed};
+use crate::jar;
+use crate::java;
+use crate::location::Location;
 use crate::nrepl;
 use crate::nrepl::ops;
 use crate::nrepl::session;
 use crate::nrepl::NreplOp;
+use crate::project;
 use clap::{clap_app, App, ArgMatches};
-use failure::Fail;
-use std::path::Path;
+use failure::{Error as StdError, Fail};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "No sources found for Java class {}", class)]
+    NoJavaSources { class: String },
+}
 
 struct Opts {
     file: String,
     symbol: String,
 }
 
-enum File {
-    Jar { jar: String, file: String },
-    File(String),
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DefinitionKind {
+    Ns,
+    Symbol,
+    Class,
+    Member,
 }
 
-#[derive(Debug, Fail)]
-enum FileError {
-    #[fail(display = "File format returned from Nrepl is not correct: {}", _0)]
-    IncorrectPathFormat(String),
+/// Where ns or symbol is defined. Definitions inside of `jar` are extracted to the cache,
+/// `file` is the extracted file then and `entry` is its path inside of `jar`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Definition {
+    pub kind: DefinitionKind,
+    pub file: String,
+    pub jar: Option<String>,
+    pub entry: Option<String>,
+    pub line: i64,
+    pub column: i64,
+    pub resource: String,
 }
 
 impl Opts {
-    fn parse(matches: &ArgMatches) -> Opts {
+    fn parse(matches: &ArgMatches) -> Result<Opts, std::io::Error> {
         let file = matches.value_of("FILE").unwrap().to_string();
         let symbol = matches.value_of("SYMBOL").unwrap().to_string();
 
-        let file = Path::new(&file)
-            .canonicalize()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
+        let file = if file == "-" {
+            file
+        } else {
+            Path::new(&file)
+                .canonicalize()?
+                .to_string_lossy()
+                .to_string()
+        };
 
-        Opts { file, symbol }
+        Ok(Opts { file, symbol })
     }
 }
 
 pub fn app<'a, 'b>() -> App<'a, 'b> {
     clap_app!(find_def =>
         (about: "Shows position of ns/symbol")
-        (@arg FILE: +required "FILE with NS containing symbol")
+        (@arg FILE: +required "FILE with NS containing symbol, `-` to read it from stdin")
         (@arg SYMBOL: +required "SYMBOL")
     )
 }
 
-fn parse_file(path: String) -> Result<File, FileError> {
-    let parts: Vec<&str> = path.split(":").collect();
-
-    let first_part = parts.get(0).unwrap();
+/// Finds position of `symbol` in NS of `file`
+pub fn find_def(
+    nrepl_stream: &nrepl::NreplStream,
+    session: &Session,
+    file: &str,
+    symbol: &str,
+) -> Result<Option<Definition>, failure::Error> {
+    let ns = match cmd::file_ns(file, session, nrepl_stream)? {
+        Some(ns) => ns,
+        None => return Err(cmd::Error::NoNsDecl.into()),
+    };
 
-    match *first_part {
-        "jar" => {
-            let jar_path = parts.get(2).unwrap();
-            let jar_parts: Vec<&str> = jar_path.split("!").collect();
+    let op = ops::Info::new(session.clone(), ns, symbol.to_string());
 
-            let mut f = jar_parts.get(1).unwrap().to_string();
-
-            if f.starts_with("/") {
-                f.remove(0);
-            }
+    let (kind, res) = match op.send(nrepl_stream)? {
+        Some(ops::InfoResponseType::Ns(res)) => (DefinitionKind::Ns, res),
+        Some(ops::InfoResponseType::Symbol(res)) => (DefinitionKind::Symbol, res),
+        Some(ops::InfoResponseType::Java(info)) => {
+            return java_definition(nrepl_stream, session, info).map(Some)
+        }
+        None => return Ok(None),
+    };
 
-            Ok(File::Jar {
-                jar: jar_parts.get(0).unwrap().to_string(),
-                file: f,
-            })
+    let (jar, entry, file) = match Location::parse(&res.file)? {
+        Location::Jar { jar, entry } => {
+            let path = jar::extract_cached(&jar, &entry)?;
+            (Some(jar), Some(entry), path.to_string_lossy().to_string())
         }
-        "file" => Ok(File::File(parts.get(1).unwrap().to_string())),
-        _ => Err(FileError::IncorrectPathFormat(path)),
-    }
+        Location::File(file) => (None, None, file),
+    };
+
+    Ok(Some(Definition {
+        kind,
+        file,
+        jar,
+        entry,
+        line: res.line,
+        column: match kind {
+            DefinitionKind::Symbol => res.col.unwrap_or(1),
+            _ => 1,
+        },
+        resource: res.resource,
+    }))
 }
 
-pub fn run(matches: &ArgMatches, nrepl_stream: &nrepl::NreplStream) {
-    let opts = Opts::parse(matches);
-    let session = cmd::die_if_err(session::get_existing_session_id(nrepl_stream));
-    let ns = cmd::die_if_err(ops::GetNsName::new(opts.file, session.clone()).send(nrepl_stream));
+/// Sources JAR of `class`: `-sources.jar` next to its JAR or `src.zip` of JDK
+fn java_sources_jar(
+    nrepl_stream: &nrepl::NreplStream,
+    session: &Session,
+    class: &str,
+) -> Result<Option<PathBuf>, StdError> {
+    let source = ops::ClassSource::new(session.clone(), class.to_string()).send(nrepl_stream)?;
 
-    if ns.is_none() {
-        cmd::die_err("File doesn't have NS declaration");
-    }
+    let jar = match source.location.as_deref().map(Location::parse) {
+        Some(Ok(Location::File(path))) if path.ends_with(".jar") => {
+            java::sources_jar(Path::new(&path))
+        }
+        Some(_) => None,
+        None => source
+            .java_home
+            .and_then(|home| java::jdk_sources(Path::new(&home))),
+    };
 
-    let op = ops::Info::new(session, ns.unwrap(), opts.symbol);
-    let res = cmd::die_if_err(op.send(nrepl_stream));
+    Ok(jar)
+}
+
+/// Finds declaration of Java class or member in its sources extracted to the jar cache
+fn java_definition(
+    nrepl_stream: &nrepl::NreplStream,
+    session: &Session,
+    info: ops::JavaInfo,
+) -> Result<Definition, StdError> {
+    let kind = match info.member {
+        Some(_) => DefinitionKind::Member,
+        None => DefinitionKind::Class,
+    };
+    let no_sources = || Error::NoJavaSources {
+        class: info.class.clone(),
+    };
 
-    if let Some(res) = res {
-        match res {
-            ops::InfoResponseType::Ns(res) => {
-                let f = parse_file(res.file).unwrap();
+    // Runtime knows sources when they are on classpath, otherwise we look for them
+    let (jar, entry, file) = match info.file.as_deref().map(Location::parse) {
+        Some(Ok(Location::Jar { jar, entry })) => {
+            let path = jar::extract_cached(&jar, &entry)?;
+            (Some(jar), Some(entry), path)
+        }
+        Some(Ok(Location::File(file))) if Path::new(&file).is_absolute() => {
+            (None, None, PathBuf::from(file))
+        }
+        _ => {
+            let jar =
+                java_sources_jar(nrepl_stream, session, &info.class)?.ok_or_else(no_sources)?;
+            let entry = match &info.file {
+                Some(file) if file.ends_with(".java") => file.to_string(),
+                _ => java::source_entry(&info.class),
+            };
+            let entry = java::find_entry(&jar, &entry)?.ok_or_else(no_sources)?;
+            let path = jar::extract_cached(&jar.to_string_lossy(), &entry)?;
 
-                let line = res.line.to_string();
+            (Some(jar.to_string_lossy().to_string()), Some(entry), path)
+        }
+    };
 
-                let mut data = vec![
-                    ("IS-NS", "TRUE".to_string()),
-                    ("LINE", line.to_string()),
-                    ("COLUMN", "1".to_string()),
-                    ("RESOURCE", res.resource),
-                ];
+    // Line reported by runtime is exact, searching for declaration is a guess
+    let (line, column) = match info.line {
+        Some(line) => (line, 1),
+        None => {
+            let source = std::fs::read_to_string(&file)?;
+            java::find_declaration(&source, &info.class, info.member.as_deref())
+                .or_else(|| java::find_declaration(&source, &info.class, None))
+                .map(|(line, column)| (line as i64, column as i64))
+                .unwrap_or((1, 1))
+        }
+    };
 
-                match f {
-                    File::Jar { jar, file } => {
-                        data.push(("JAR", jar));
-                        data.push(("FILE", file))
-                    }
+    Ok(Definition {
+        kind,
+        file: file.to_string_lossy().to_string(),
+        resource: entry.clone().unwrap_or_default(),
+        jar,
+        entry,
+        line,
+        column,
+    })
+}
 
-                    File::File(file) => data.push(("FILE", file)),
-                }
+/// Classpath resource of `ns` defined in `path`
+fn ns_resource(ns: &str, path: &str) -> String {
+    let ext = Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_else(|| "clj".to_string());
 
-                cmd::print_parseable(&data);
-            }
+    format!("{}.{}", ns.replace('.', "/").replace('-', "_"), ext)
+}
 
-            ops::InfoResponseType::Symbol(res) => {
-                let f = parse_file(res.file).unwrap();
+/// Finds position of `symbol` in the index, for when nrepl isn't running
+pub fn index_find_def(file: &str, symbol: &str) -> Result<Option<Definition>, StdError> {
+    let analysis = analysis::analyze_file(file)?;
+    let root = project::file_root(file)?.to_string_lossy().to_string();
 
-                let mut data = vec![
-                    ("IS-SYMBOL", "TRUE".to_string()),
-                    ("LINE", res.line.to_string()),
-                    ("COLUMN", res.col.unwrap().to_string()),
-                    ("RESOURCE", res.resource),
-                ];
+    let (kind, ns, path, entry, line, column) =
+        match config::with_db(|conn| index::resolve(conn, &root, &analysis, symbol))? {
+            Some(Indexed::Ns(ns)) => (DefinitionKind::Ns, ns.name, ns.path, ns.entry, ns.line, 1),
+            Some(Indexed::Var(var)) => (
+                DefinitionKind::Symbol,
+                var.ns,
+                var.path,
+                var.entry,
+                var.line,
+                var.column,
+            ),
+            None => return Ok(None),
+        };
 
-                match f {
-                    File::Jar { jar, file } => {
-                        data.push(("JAR", jar));
-                        data.push(("FILE", file))
-                    }
+    let (jar, file) = match &entry {
+        Some(entry) => (
+            Some(path.clone()),
+            jar::extract_cached(&path, entry)?
+                .to_string_lossy()
+                .to_string(),
+        ),
+        None => (None, path.clone()),
+    };
 
-                    File::File(file) => data.push(("FILE", file)),
-                }
+    Ok(Some(Definition {
+        kind,
+        resource: entry.clone().unwrap_or_else(|| ns_resource(&ns, &path)),
+        file,
+        jar,
+        entry,
+        line,
+        column,
+    }))
+}
 
-                cmd::print_parseable(&data);
-            }
+/// Finds position of `symbol` with nrepl or in the index when nrepl isn't running
+pub fn connect_find_def(
+    connect: &cmd::Connect,
+    file: &str,
+    symbol: &str,
+) -> Result<Option<Definition>, StdError> {
+    match cmd::connect_or_index(connect)? {
+        Some(nrepl_stream) => {
+            let session = session::get_file_session(&nrepl_stream, Some(file))?;
+            find_def(&nrepl_stream, &session, file, symbol)
         }
-    } else {
-        cmd::print_parseable(&vec![("IS-EMPTY", "TRUE".to_string())]);
+        None => index_find_def(file, symbol),
     }
 }
+
+pub fn run(
+    matches: &ArgMatches,
+    connect: &cmd::Connect,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let opts = Opts::parse(matches)?;
+    let def = connect_find_def(connect, &opts.file, &opts.symbol)?;
+
+    out.print(cmd::Format::Kv, &def, |def| match def {
+        Some(def) => format!("{}:{}:{}", def.file, def.line, def.column),
+        None => String::new(),
+    })
+}
@@ -0,0 +1,183 @@
+use crate::analysis;
+use crate::cmd;
+use crate::cmd::lint::FileDiagnostic;
+use crate::jar;
+use crate::location::Location;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use crate::project;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use std::path::{Path, PathBuf};
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(find_usages =>
+        (about: "Lists usages of SYMBOL as quickfix lines: FILE:LINE:COL: TEXT. Uses cider-nrepl \
+                 xref ops when they are available, otherwise scans source paths of the project")
+        (@arg deps: -d --deps "List vars SYMBOL uses instead, needs cider-nrepl")
+        (@arg scan: -s --scan "Scan source paths even when xref ops are available")
+        (@arg FILE: +required "FILE with NS SYMBOL is resolved in")
+        (@arg SYMBOL: +required "SYMBOL")
+    )
+}
+
+/// Asks xref op, returns `None` when nrepl doesn't have it
+fn xref_usages(
+    nrepl_stream: &nrepl::NreplStream,
+    file: &str,
+    symbol: &str,
+    deps: bool,
+) -> Result<Option<Vec<FileDiagnostic>>, StdError> {
+    let session = session::get_file_session(nrepl_stream, Some(file))?;
+    let ns = cmd::file_ns(file, &session, nrepl_stream)?.unwrap_or_else(|| "user".to_string());
+    let op = ops::FnRefs::new(session.clone(), ns, symbol.to_string()).deps(deps);
+
+    if !session.is_op_available(op.op_name()) {
+        return Ok(None);
+    }
+    let vars = op.send(nrepl_stream)?;
+
+    let mut items = vec![];
+    for var in vars {
+        let file = match var.file.as_deref().map(Location::parse) {
+            Some(Ok(Location::File(file))) => file,
+            Some(Ok(Location::Jar { jar, entry })) => jar::extract_cached(&jar, &entry)?
+                .to_string_lossy()
+                .to_string(),
+            _ => continue,
+        };
+
+        items.push(FileDiagnostic {
+            file,
+            line: var.line.unwrap_or(1) as usize,
+            column: var.column.unwrap_or(1) as usize,
+            message: var.name,
+        });
+    }
+
+    Ok(Some(items))
+}
+
+/// Finds usages of `symbol` as it's resolved in `file` reading every source file of project
+fn scan_usages(file: &Path, symbol: &str) -> Result<Vec<FileDiagnostic>, StdError> {
+    let source = std::fs::read_to_string(file)?;
+    let analysis = analysis::analyze_source(&source, &file.to_string_lossy())?;
+    let (ns, name) = analysis::split_symbol(symbol);
+    let ns = match analysis.resolve(ns, name) {
+        Some(ns) => ns,
+        None => return Ok(vec![]),
+    };
+
+    let dir = file.parent().unwrap_or_else(|| Path::new("."));
+    let root = project::find_root(dir).unwrap_or_else(|| dir.to_path_buf());
+    let mut files = project::source_files(&root);
+    if !files.iter().any(|f| f == file) {
+        files.insert(0, file.to_path_buf());
+    }
+
+    let mut items = vec![];
+    for path in files {
+        let path_str = path.to_string_lossy().to_string();
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(_) => continue,
+        };
+        // Files which can't be read by our reader are skipped rather than failing the search
+        let analysis = match analysis::analyze_source(&source, &path_str) {
+            Ok(analysis) => analysis,
+            Err(_) => continue,
+        };
+        let lines: Vec<&str> = source.lines().collect();
+
+        for usage in analysis.usages.iter() {
+            if analysis.references(usage, &ns, name) {
+                items.push(FileDiagnostic {
+                    file: path_str.to_string(),
+                    line: usage.pos.line,
+                    column: usage.pos.column,
+                    message: lines
+                        .get(usage.pos.line - 1)
+                        .map(|l| l.trim().to_string())
+                        .unwrap_or_default(),
+                });
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    connect: &cmd::Connect,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let file: PathBuf = Path::new(matches.value_of("FILE").unwrap()).canonicalize()?;
+    let symbol = matches.value_of("SYMBOL").unwrap();
+    let deps = matches.is_present("deps");
+
+    // Without nrepl connection usages are still found by scanning
+    let xref = match connect() {
+        Ok(n) if !matches.is_present("scan") => {
+            xref_usages(&n, &file.to_string_lossy(), symbol, deps)?
+        }
+        _ => None,
+    };
+
+    let items = match xref {
+        Some(items) => items,
+        None if deps => {
+            return Err(ops::Error::XrefOpUnavailable {
+                op: "fn-deps".to_string(),
+            }
+            .into())
+        }
+        None => scan_usages(&file, symbol)?,
+    };
+
+    cmd::lint::print_file_diagnostics(out, cmd::Format::Text, &items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_usages_test() {
+        let dir = std::env::temp_dir().join(format!("unrepl-usages-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src/foo")).unwrap();
+        std::fs::write(dir.join("deps.edn"), "{:paths [\"src\"]}").unwrap();
+        std::fs::write(
+            dir.join("src/foo/util.clj"),
+            "(ns foo.util)\n(defn helper [x] x)\n(helper 1)\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("src/foo/core.clj"),
+            "(ns foo.core\n  (:require [foo.util :as u]))\n(defn f [helper] (u/helper helper))\n",
+        )
+        .unwrap();
+
+        let file = dir.join("src/foo/core.clj").canonicalize().unwrap();
+        let usages = scan_usages(&file, "u/helper")
+            .unwrap()
+            .into_iter()
+            .map(|d| {
+                let name = Path::new(&d.file).file_name().unwrap().to_string_lossy();
+                format!("{}:{}:{}: {}", name, d.line, d.column, d.message)
+            })
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            usages,
+            vec![
+                "core.clj:3:19: (defn f [helper] (u/helper helper))",
+                "util.clj:3:2: (helper 1)"
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap_or_default();
+    }
+}
@@ -0,0 +1,111 @@
+use crate::cmd;
+use crate::cmd::status;
+use crate::config;
+use crate::index;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use crate::project;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(index =>
+        (about: "Indexes namespaces and vars of project sources and of JARs and directories on \
+                 classpath, so find_def, doc and complete work without nrepl. Files are indexed \
+                 again only when they are modified")
+        (@arg classpath: -c --classpath +takes_value
+            "CLASSPATH to index, it's asked from nrepl by default")
+        (@arg DIR: "Directory of project, the current one by default")
+    )
+}
+
+/// Number of files and JARs in each state
+#[derive(Debug, Default, Serialize)]
+pub struct IndexResult {
+    pub indexed: usize,
+    pub unchanged: usize,
+    /// Couldn't be read, like broken JARs
+    pub failed: usize,
+    /// Indexed before, but don't exist anymore
+    pub removed: usize,
+}
+
+/// Classpath of running nrepl, relative entries are relative to `root`
+fn nrepl_classpath(connect: &cmd::Connect, root: &Path) -> Result<Vec<PathBuf>, StdError> {
+    let nrepl_stream = connect()?;
+    let session = session::get_existing_session_id(&nrepl_stream)?;
+
+    Ok(ops::Classpath::new(session)
+        .send(&nrepl_stream)?
+        .into_iter()
+        .map(|entry| root.join(entry))
+        .collect())
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    connect: &cmd::Connect,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let root = status::project_root(matches, out.cwd())?;
+
+    // Without nrepl and `--classpath` only project sources are indexed
+    let classpath = match matches.value_of("classpath") {
+        Some(classpath) => std::env::split_paths(classpath)
+            .map(|entry| root.join(entry))
+            .collect(),
+        None => nrepl_classpath(connect, &root).unwrap_or_default(),
+    };
+
+    let mut files = project::source_files(&root);
+    let mut jars = vec![];
+    for entry in classpath {
+        // Classpath may list directories which weren't created
+        let entry = match entry.canonicalize() {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+
+        if entry.is_dir() {
+            files.extend(project::dir_source_files(&entry));
+        } else if entry.extension().is_some_and(|e| e == "jar") {
+            jars.push(entry);
+        }
+    }
+    files.sort();
+    files.dedup();
+    jars.sort();
+    jars.dedup();
+
+    let mut result = IndexResult::default();
+    config::with_db(|conn| {
+        let mut count = |status: Result<index::Status, StdError>| match status {
+            Ok(index::Status::Indexed) => result.indexed += 1,
+            Ok(index::Status::Unchanged) => result.unchanged += 1,
+            Err(_) => result.failed += 1,
+        };
+
+        for file in files.iter() {
+            count(index::index_file(conn, file));
+        }
+        for jar in jars.iter() {
+            count(index::index_jar(conn, jar));
+        }
+        // Lookups from files of the project see only what's indexed for it
+        let paths: Vec<PathBuf> = files.iter().chain(jars.iter()).cloned().collect();
+        index::set_root_paths(conn, &root.to_string_lossy(), &paths)?;
+        result.removed = index::prune(conn)?;
+
+        Ok(())
+    })?;
+
+    out.print(cmd::Format::Kv, &result, |r| {
+        format!(
+            "Indexed {}, unchanged {}, failed {}, removed {}",
+            r.indexed, r.unchanged, r.failed, r.removed
+        )
+    })
+}
@@ -0,0 +1,54 @@
+#![allow(non_local_definitions)]
+
+use crate::cmd;
+use crate::config;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use clap::{clap_app, App, ArgMatches};
+use failure::{Error as StdError, Fail};
+use serde_json::json;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "No evaluation was started in current session")]
+    NoEvalStarted,
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(interrupt =>
+        (about: "Interrupts the last evaluation started by `eval`")
+        (@arg any: -a --any "Interrupt whatever is running in the session")
+    )
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    nrepl_stream: &nrepl::NreplStream,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let session = session::get_existing_session_id(nrepl_stream)?;
+
+    let interrupt_id = if matches.is_present("any") {
+        None
+    } else {
+        match config::load_last_eval_id(&session)? {
+            Some(id) => Some(id),
+            None => return Err(Error::NoEvalStarted.into()),
+        }
+    };
+
+    let res = ops::Interrupt::new(session, interrupt_id).send(nrepl_stream)?;
+
+    let interrupted = res == ops::InterruptResult::Interrupted;
+
+    out.print(
+        cmd::Format::Kv,
+        &json!({ "interrupted": interrupted }),
+        |_| match interrupted {
+            true => "Interrupted".to_string(),
+            false => "Session is idle".to_string(),
+        },
+    )
+}
@@ -0,0 +1,61 @@
+use crate::cmd;
+use crate::cmd::status::{self, ServerStatus};
+use crate::server::{self, Tool};
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde::Serialize;
+use std::time::Duration;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(jack_in =>
+        (about: "Starts nrepl with cider-nrepl for project with its build tool and waits until it \
+                 writes port file. Nothing is started when nrepl of project is running already")
+        (alias: "jack-in")
+        (@arg tool: -t --tool +takes_value possible_values(&Tool::NAMES)
+            "Tool to start nrepl with, it's detected by project files by default")
+        (@arg timeout: --timeout +takes_value "Seconds to wait for nrepl to start, 300 by default")
+        (@arg DIR: "Directory of project, the current one by default")
+    )
+}
+
+#[derive(Debug, Serialize)]
+pub struct JackInResult {
+    /// `false` when nrepl was running already
+    pub started: bool,
+    #[serde(flatten)]
+    pub status: ServerStatus,
+}
+
+pub fn run(matches: &ArgMatches, out: &mut cmd::Output) -> Result<(), StdError> {
+    let root = status::project_root(matches, out.cwd())?;
+    let timeout = match matches.value_of("timeout") {
+        Some(secs) => secs.parse::<u64>().map_err(|_| cmd::Error::BadArg {
+            name: "timeout".to_string(),
+            value: secs.to_string(),
+        })?,
+        None => 300,
+    };
+
+    let mut started = false;
+    if !status::server_status(&root)?.running {
+        let tool = match matches.value_of("tool") {
+            Some(name) => Tool::from_name(name),
+            None => Tool::detect(&root),
+        }
+        .ok_or_else(|| server::Error::UnknownTool {
+            root: root.to_string_lossy().to_string(),
+        })?;
+
+        server::start(&root, tool, Duration::from_secs(timeout))?;
+        started = true;
+    }
+
+    let res = JackInResult {
+        started,
+        status: status::server_status(&root)?,
+    };
+
+    out.print(cmd::Format::Kv, &res, |res| {
+        status::status_text(&res.status)
+    })
+}
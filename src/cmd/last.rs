@@ -0,0 +1,30 @@
+use crate::cmd;
+use crate::cmd::eval;
+use crate::config;
+use crate::nrepl;
+use crate::nrepl::ops;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(last =>
+        (about: "Shows result of the last `eval` sent to nrepl, like `:Last` of fireplace")
+    )
+}
+
+pub fn run(
+    _matches: &ArgMatches,
+    nrepl_stream: &nrepl::NreplStream,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let res: Option<ops::EvalResult> =
+        match config::load_setting(&nrepl_stream.addr_string(), eval::LAST_RESULT_SETTING)? {
+            Some(json) => Some(serde_json::from_str(&json)?),
+            None => None,
+        };
+
+    out.print(cmd::Format::Text, &res, |res| match res {
+        Some(res) => eval::result_text(res),
+        None => String::new(),
+    })
+}
@@ -0,0 +1,116 @@
+use crate::analysis;
+use crate::cmd;
+use crate::config::{Session, SessionKind};
+use crate::lint;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde::Serialize;
+use std::io::Read;
+
+/// Diagnostic as printed by `lint` and `unused`
+#[derive(Debug, Serialize)]
+pub struct FileDiagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// Prints diagnostics of `file`, text format gives quickfix lines: FILE:LINE:COL: MESSAGE
+pub fn print_diagnostics(
+    out: &mut cmd::Output,
+    default: cmd::Format,
+    file: &str,
+    diagnostics: Vec<lint::Diagnostic>,
+) -> Result<(), StdError> {
+    let items = diagnostics
+        .into_iter()
+        .map(|d| FileDiagnostic {
+            file: file.to_string(),
+            line: d.pos.line,
+            column: d.pos.column,
+            message: d.message,
+        })
+        .collect::<Vec<FileDiagnostic>>();
+
+    print_file_diagnostics(out, default, &items)
+}
+
+/// Same as `print_diagnostics`, but items may be in different files
+pub fn print_file_diagnostics(
+    out: &mut cmd::Output,
+    default: cmd::Format,
+    items: &[FileDiagnostic],
+) -> Result<(), StdError> {
+    out.print(default, &items, |items| {
+        items
+            .iter()
+            .map(|d| format!("{}:{}:{}: {}", d.file, d.line, d.column, d.message))
+            .collect::<Vec<String>>()
+            .join("\n")
+    })
+}
+
+/// Answers lint questions with `ns-list`/`ns-vars` ops of the running REPL
+struct NreplRuntime<'a> {
+    session: Session,
+    nrepl_stream: &'a nrepl::NreplStream,
+}
+
+impl<'a> lint::Runtime for NreplRuntime<'a> {
+    fn ns_list(&mut self) -> Result<Vec<String>, StdError> {
+        ops::NsList::new(self.session.clone()).send(self.nrepl_stream)
+    }
+
+    fn ns_vars(&mut self, ns: &str, private: bool) -> Result<Vec<String>, StdError> {
+        ops::NsVars::new(self.session.clone(), ns.to_string())
+            .private(private)
+            .send(self.nrepl_stream)
+    }
+
+    fn core_ns(&self) -> &str {
+        match self.session.kind() {
+            SessionKind::Clj => "clojure.core",
+            SessionKind::Cljs => "cljs.core",
+        }
+    }
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(lint =>
+        (about: "Reports undefined vars and namespaces in FILE as quickfix lines: FILE:LINE:COL: MESSAGE")
+        (@arg FILE: +required "FILE to check, - to read it from stdin")
+    )
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    nrepl_stream: &nrepl::NreplStream,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let file = matches.value_of("FILE").unwrap();
+    let mut source = String::new();
+
+    if file == "-" {
+        std::io::stdin().read_to_string(&mut source)?;
+    } else {
+        std::fs::File::open(file)?.read_to_string(&mut source)?;
+    }
+
+    // ClojureScript files are checked against vars of ClojureScript runtime, `.cljc` ones are
+    // read with features of the session they're evaluated in
+    let session = session::get_file_session(nrepl_stream, Some(file))?;
+    let analysis = analysis::analyze_source_with(&source, &session.kind().features())?;
+    let mut runtime = NreplRuntime {
+        session,
+        nrepl_stream,
+    };
+
+    let diagnostics = lint::undefined(&analysis, &mut runtime)?;
+
+    print_diagnostics(out, cmd::Format::Text, file, diagnostics)
+}
@@ -0,0 +1,45 @@
+use crate::cmd;
+use crate::cmd::require;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use crate::project;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use std::io::Read;
+use std::path::Path;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(load_file =>
+        (about: "Loads FILE with `load-file` op, evaluating all of its forms")
+        (@arg stdin: -s --stdin "Read content of FILE from stdin, like unsaved buffer of editor")
+        (@arg FILE: +required "FILE to load")
+    )
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    nrepl_stream: &nrepl::NreplStream,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let file = Path::new(matches.value_of("FILE").unwrap()).canonicalize()?;
+    let file_str = file.to_string_lossy().to_string();
+    let session = session::get_file_session(nrepl_stream, Some(&file_str))?;
+
+    let content = if matches.is_present("stdin") {
+        let mut content = String::new();
+        std::io::stdin().read_to_string(&mut content)?;
+        content
+    } else {
+        std::fs::read_to_string(&file)?
+    };
+
+    // Vars get the same `:file` as when their namespace is required
+    let path = project::resource_path(&file).unwrap_or(file_str);
+    let res = ops::LoadFile::new(session, content)
+        .path(&path)
+        .send(nrepl_stream)?;
+
+    require::print_loaded(out, &file, res)
+}
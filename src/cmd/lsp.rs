@@ -0,0 +1,444 @@
+//! Language server talking LSP over stdio. Definitions inside of jars are given with
+//! `unrepl-jar:///path/to/lib.jar!/path/in/jar.clj` URIs, clients get their text with
+//! `unrepl/jarContent` request having `{"uri": ...}` params.
+
+#![allow(non_local_definitions)]
+
+use crate::cmd;
+use crate::config::Session;
+use crate::jar;
+use crate::location::{self, Location};
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use crate::project;
+use clap::{clap_app, App, ArgMatches};
+use failure::{Error as StdError, Fail};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+const JAR_SCHEME: &str = "unrepl-jar://";
+
+/// JSON-RPC error codes, see LSP specification
+const METHOD_NOT_FOUND: i64 = -32601;
+const INTERNAL_ERROR: i64 = -32603;
+
+#[derive(Debug, Fail)]
+enum Error {
+    #[fail(display = "bad LSP message header: {}", header)]
+    BadHeader { header: String },
+    #[fail(display = "missing param: {}", param)]
+    MissingParam { param: String },
+    #[fail(display = "unsupported URI: {}", uri)]
+    BadUri { uri: String },
+}
+
+struct Server<'a> {
+    nrepl_stream: &'a nrepl::NreplStream,
+    /// Text of opened documents by URI
+    documents: HashMap<String, String>,
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(lsp =>
+        (about: "Runs language server talking LSP over stdio")
+    )
+}
+
+/// Reads a single message, returns `None` when input is closed
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<Value>, StdError> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+
+    let length = match length {
+        Some(length) => length,
+        None => {
+            return Err(Error::BadHeader {
+                header: "no Content-Length".to_string(),
+            }
+            .into())
+        }
+    };
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(msg: &Value) -> Result<(), StdError> {
+    let body = serde_json::to_string(msg)?;
+    let mut stdout = std::io::stdout();
+
+    write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn param<'v>(params: &'v Value, pointer: &str) -> Result<&'v Value, Error> {
+    params.pointer(pointer).ok_or_else(|| Error::MissingParam {
+        param: pointer.to_string(),
+    })
+}
+
+fn str_param<'v>(params: &'v Value, pointer: &str) -> Result<&'v str, Error> {
+    param(params, pointer)?
+        .as_str()
+        .ok_or_else(|| Error::MissingParam {
+            param: pointer.to_string(),
+        })
+}
+
+fn uri_to_path(uri: &str) -> Result<String, Error> {
+    match Location::parse(uri) {
+        Ok(Location::File(path)) if uri.starts_with("file:") => Ok(path),
+        _ => Err(Error::BadUri {
+            uri: uri.to_string(),
+        }),
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    !c.is_whitespace() && !"()[]{}\"',;@^`~".contains(c)
+}
+
+/// Index of char `character` UTF-16 code units into `line`, as LSP counts positions in them
+fn char_index(line: &str, character: usize) -> usize {
+    let mut units = 0;
+
+    line.chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= character
+        })
+        .count()
+}
+
+/// Returns the symbol `character` points to and the part of it before `character`
+fn symbol_at(text: &str, line: usize, character: usize) -> Option<(String, String)> {
+    let line = text.lines().nth(line)?;
+    let chars: Vec<char> = line.chars().collect();
+    let character = char_index(line, character);
+
+    let start = chars[..character]
+        .iter()
+        .rposition(|c| !is_symbol_char(*c))
+        .map_or(0, |i| i + 1);
+    let end = chars[character..]
+        .iter()
+        .position(|c| !is_symbol_char(*c))
+        .map_or(chars.len(), |i| character + i);
+
+    let symbol: String = chars[start..end].iter().collect();
+    let prefix: String = chars[start..character].iter().collect();
+
+    Some((symbol, prefix))
+}
+
+fn completion_kind(kind: &str) -> u64 {
+    match kind {
+        "f" => 3,
+        "m" | "M" => 2,
+        "F" => 5,
+        "v" | "l" => 6,
+        "c" => 7,
+        "n" => 9,
+        "k" | "s" => 14,
+        _ => 1,
+    }
+}
+
+impl<'a> Server<'a> {
+    fn session(&mut self, file: &str) -> Result<Session, StdError> {
+        session::get_file_session(self.nrepl_stream, Some(file))
+    }
+
+    /// Path of the document, symbol at the position and its part before the cursor
+    fn position_params(&self, params: &Value) -> Result<(String, String, String), StdError> {
+        let uri = str_param(params, "/textDocument/uri")?;
+        let line = param(params, "/position/line")?
+            .as_u64()
+            .unwrap_or_default();
+        let character = param(params, "/position/character")?
+            .as_u64()
+            .unwrap_or_default();
+
+        let text = match self.documents.get(uri) {
+            Some(text) => text.to_string(),
+            None => std::fs::read_to_string(uri_to_path(uri)?)?,
+        };
+        let (symbol, prefix) =
+            symbol_at(&text, line as usize, character as usize).unwrap_or_default();
+
+        Ok((uri_to_path(uri)?, symbol, prefix))
+    }
+
+    fn definition(&mut self, params: &Value) -> Result<Value, StdError> {
+        let (file, symbol, _) = self.position_params(params)?;
+        if symbol.is_empty() {
+            return Ok(Value::Null);
+        }
+
+        let session = self.session(&file)?;
+        let def = match cmd::find_def::find_def(self.nrepl_stream, &session, &file, &symbol)? {
+            Some(def) => def,
+            None => return Ok(Value::Null),
+        };
+
+        let uri = match (&def.jar, &def.entry) {
+            (Some(jar), Some(entry)) => format!("{}{}!/{}", JAR_SCHEME, jar, entry),
+            _ => location::file_uri(&def.file),
+        };
+        let position = json!({"line": (def.line - 1).max(0), "character": (def.column - 1).max(0)});
+
+        Ok(json!({"uri": uri, "range": {"start": position, "end": position}}))
+    }
+
+    fn hover(&mut self, params: &Value) -> Result<Value, StdError> {
+        let (file, symbol, _) = self.position_params(params)?;
+        if symbol.is_empty() {
+            return Ok(Value::Null);
+        }
+
+        let session = self.session(&file)?;
+
+        Ok(
+            match cmd::doc::doc(self.nrepl_stream, &session, &file, &symbol)? {
+                Some(doc) => json!({"contents": {"kind": "plaintext", "value": doc}}),
+                None => Value::Null,
+            },
+        )
+    }
+
+    fn completion(&mut self, params: &Value) -> Result<Value, StdError> {
+        let (file, _, prefix) = self.position_params(params)?;
+        if prefix.is_empty() {
+            return Ok(json!([]));
+        }
+
+        let session = self.session(&file)?;
+        let items =
+            cmd::complete::complete_items(self.nrepl_stream, &session, &file, None, prefix, None)?;
+
+        Ok(Value::Array(
+            items
+                .into_iter()
+                .map(|item| {
+                    json!({
+                        "label": item.word,
+                        "kind": completion_kind(&item.kind),
+                        "detail": item.menu,
+                        "documentation": item.info,
+                    })
+                })
+                .collect(),
+        ))
+    }
+
+    /// Text of jar entry, it's served from the jar cache
+    fn jar_content(&mut self, params: &Value) -> Result<Value, StdError> {
+        let uri = str_param(params, "/uri")?;
+        let bad_uri = || Error::BadUri {
+            uri: uri.to_string(),
+        };
+
+        let (jar, entry) = uri
+            .strip_prefix(JAR_SCHEME)
+            .and_then(|path| path.split_once("!/"))
+            .ok_or_else(bad_uri)?;
+        let path = jar::extract_cached(jar, entry)?;
+
+        Ok(Value::from(std::fs::read_to_string(path)?))
+    }
+
+    /// Loads saved document into REPL and reports compiler errors as diagnostics
+    fn publish_diagnostics(&mut self, uri: &str) -> Result<(), StdError> {
+        let file = uri_to_path(uri)?;
+        let code = std::fs::read_to_string(&file)?;
+        let session = self.session(&file)?;
+
+        // Vars get the same `:file` as when their namespace is required
+        let path = project::resource_path(Path::new(&file)).unwrap_or(file);
+        let res = ops::LoadFile::new(session, code)
+            .path(&path)
+            .send(self.nrepl_stream)?;
+
+        // Columns of Clojure reader count Java chars, which are UTF-16 code units as in LSP
+        let diagnostic = match (
+            res.compile_error(),
+            res.root_ex.as_ref().or(res.ex.as_ref()),
+        ) {
+            (Some(error), _) => Some((error.line - 1, error.column - 1, error.message)),
+            (None, Some(ex)) => {
+                let err = res.err.concat();
+                let message = if err.is_empty() { ex.to_string() } else { err };
+                Some((0, 0, message))
+            }
+            (None, None) => None,
+        };
+
+        let diagnostics = diagnostic
+            .into_iter()
+            .map(|(line, column, message)| {
+                let position = json!({"line": line.max(0), "character": column.max(0)});
+
+                json!({
+                    "range": {"start": position, "end": position},
+                    "severity": 1,
+                    "source": "unrepl",
+                    "message": message.trim(),
+                })
+            })
+            .collect::<Vec<Value>>();
+
+        write_message(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {"uri": uri, "diagnostics": diagnostics},
+        }))
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Option<Result<Value, StdError>> {
+        Some(match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": {"openClose": true, "change": 1, "save": true},
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {"triggerCharacters": ["/"]},
+                },
+                "serverInfo": {"name": "unrepl"},
+            })),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            "unrepl/jarContent" => self.jar_content(params),
+            _ => return None,
+        })
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Result<(), StdError> {
+        match method {
+            "textDocument/didOpen" => {
+                let uri = str_param(params, "/textDocument/uri")?;
+                let text = str_param(params, "/textDocument/text")?;
+                self.documents.insert(uri.to_string(), text.to_string());
+            }
+            // Full sync, so the only change has the whole text
+            "textDocument/didChange" => {
+                let uri = str_param(params, "/textDocument/uri")?;
+                let text = str_param(params, "/contentChanges/0/text")?;
+                self.documents.insert(uri.to_string(), text.to_string());
+            }
+            "textDocument/didClose" => {
+                self.documents
+                    .remove(str_param(params, "/textDocument/uri")?);
+            }
+            "textDocument/didSave" => {
+                self.publish_diagnostics(str_param(params, "/textDocument/uri")?)?
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Handles a message, returns false after `exit`
+    fn handle(&mut self, msg: Value) -> Result<bool, StdError> {
+        let method = msg["method"].as_str().unwrap_or_default().to_string();
+        let params = msg.get("params").cloned().unwrap_or(Value::Null);
+
+        if method == "exit" {
+            return Ok(false);
+        }
+
+        // Responses to our requests never come, as we don't send any
+        let id = match msg.get("id") {
+            Some(id) => id.clone(),
+            None => {
+                self.notification(&method, &params)?;
+                return Ok(true);
+            }
+        };
+
+        let resp = match self.request(&method, &params) {
+            Some(Ok(result)) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Some(Err(e)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": INTERNAL_ERROR, "message": e.to_string()},
+            }),
+            None => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": METHOD_NOT_FOUND, "message": format!("Unknown method: {}", method)},
+            }),
+        };
+
+        write_message(&resp)?;
+
+        Ok(true)
+    }
+}
+
+pub fn run(_matches: &ArgMatches, nrepl_stream: &nrepl::NreplStream) {
+    let mut server = Server {
+        nrepl_stream,
+        documents: HashMap::new(),
+    };
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+
+    while let Some(msg) = cmd::die_if_err(read_message(&mut input)) {
+        match server.handle(msg) {
+            Ok(true) => {}
+            Ok(false) => break,
+            // Stdout belongs to the protocol, so errors go to stderr which clients log
+            Err(e) => eprintln!("ERROR: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbol_at_test() {
+        let text = "(ns foo)\n(str/join \", \" @xs)";
+
+        assert_eq!(
+            symbol_at(text, 1, 4),
+            Some(("str/join".to_string(), "str".to_string()))
+        );
+        assert_eq!(
+            symbol_at(text, 1, 16),
+            Some(("xs".to_string(), "".to_string()))
+        );
+        // The emoji takes two UTF-16 code units
+        assert_eq!(
+            symbol_at("(str \"😀\" xs)", 0, 11),
+            Some(("xs".to_string(), "x".to_string()))
+        );
+    }
+}
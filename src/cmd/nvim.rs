@@ -0,0 +1,271 @@
+//! Neovim remote plugin host talking msgpack-rpc over stdio, see `:help msgpack-rpc`.
+//! Unlike the other subcommands it keeps nrepl connection and sessions between requests.
+
+#![allow(non_local_definitions)]
+
+use crate::bencode as bc;
+use crate::cmd;
+use crate::config::Session;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use clap::{clap_app, App, ArgMatches};
+use failure::{Error as StdError, Fail};
+use rmpv::Value;
+use serde_json::Value as JsonValue;
+use std::io::Write;
+use std::sync::Mutex;
+
+const REQUEST: u64 = 0;
+const RESPONSE: u64 = 1;
+const NOTIFICATION: u64 = 2;
+
+/// Vim function receiving evaluation output: `unrepl#host#Output(kind, text)`
+const OUTPUT_FN: &str = "unrepl#host#Output";
+
+#[derive(Debug, Fail)]
+enum Error {
+    #[fail(display = "bad msgpack-rpc message: {}", msg)]
+    BadMessage { msg: String },
+    #[fail(display = "unknown method: {}", method)]
+    UnknownMethod { method: String },
+    #[fail(display = "argument {} of {} should be a string", index, method)]
+    BadArgument { method: String, index: usize },
+}
+
+struct Host<'a> {
+    nrepl_stream: &'a nrepl::NreplStream,
+    last_eval: Mutex<Option<(Session, String)>>,
+    stdout: Mutex<std::io::Stdout>,
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(nvim =>
+        (about: "Runs as neovim remote plugin, start it with jobstart([..., 'nvim'], {'rpc': v:true})")
+    )
+}
+
+fn json_to_msgpack(json: JsonValue) -> Value {
+    match json {
+        JsonValue::Null => Value::Nil,
+        JsonValue::Bool(b) => Value::from(b),
+        JsonValue::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => Value::from(i),
+            (None, Some(f)) => Value::from(f),
+            _ => Value::Nil,
+        },
+        JsonValue::String(s) => Value::from(s),
+        JsonValue::Array(items) => Value::Array(items.into_iter().map(json_to_msgpack).collect()),
+        JsonValue::Object(map) => Value::Map(
+            map.into_iter()
+                .map(|(k, v)| (Value::from(k), json_to_msgpack(v)))
+                .collect(),
+        ),
+    }
+}
+
+fn str_arg(method: &str, args: &[Value], index: usize) -> Result<String, Error> {
+    match args.get(index).and_then(|a| a.as_str()) {
+        Some(s) => Ok(s.to_string()),
+        None => Err(Error::BadArgument {
+            method: method.to_string(),
+            index,
+        }),
+    }
+}
+
+fn opt_int_arg(args: &[Value], index: usize) -> Option<i64> {
+    args.get(index).and_then(|a| a.as_i64())
+}
+
+impl<'a> Host<'a> {
+    fn write(&self, msg: Value) {
+        let mut stdout = self.stdout.lock().unwrap();
+
+        // Neovim is gone when stdout is closed, nobody to report it to
+        if rmpv::encode::write_value(&mut *stdout, &msg).is_ok() {
+            let _ = stdout.flush();
+        }
+    }
+
+    fn notify_output(&self, kind: &str, text: String) {
+        self.write(Value::Array(vec![
+            Value::from(NOTIFICATION),
+            Value::from("nvim_call_function"),
+            Value::Array(vec![
+                Value::from(OUTPUT_FN),
+                Value::Array(vec![Value::from(kind), Value::from(text)]),
+            ]),
+        ]));
+    }
+
+    /// Sessions are looked up in config and nrepl only once, then they're cached by connection
+    fn session(&self, file: &str) -> Result<Session, StdError> {
+        session::get_file_session(self.nrepl_stream, Some(file))
+    }
+
+    fn find_def(&self, file: String, symbol: String) -> Result<Value, StdError> {
+        let session = self.session(&file)?;
+        let def = cmd::find_def::find_def(self.nrepl_stream, &session, &file, &symbol)?;
+
+        Ok(json_to_msgpack(serde_json::to_value(def)?))
+    }
+
+    fn doc(&self, file: String, symbol: String) -> Result<Value, StdError> {
+        let session = self.session(&file)?;
+        let doc = cmd::doc::doc(self.nrepl_stream, &session, &file, &symbol)?;
+
+        Ok(doc.map(Value::from).unwrap_or(Value::Nil))
+    }
+
+    fn complete(&self, file: String, prefix: String) -> Result<Value, StdError> {
+        let session = self.session(&file)?;
+        let items =
+            cmd::complete::complete_items(self.nrepl_stream, &session, &file, None, prefix, None)?;
+
+        Ok(json_to_msgpack(serde_json::to_value(items)?))
+    }
+
+    /// Evaluates `code`, sending `out`, `err` and values to vim as they arrive
+    fn eval(
+        &self,
+        file: String,
+        code: String,
+        line: Option<i64>,
+        column: Option<i64>,
+    ) -> Result<Value, StdError> {
+        let session = self.session(&file)?;
+        let ns = cmd::file_ns(&file, &session, self.nrepl_stream)?;
+        let id = self.nrepl_stream.gen_id();
+
+        *self.last_eval.lock().unwrap() = Some((session.clone(), id.clone()));
+
+        let res = ops::Eval::new(session, code)
+            .id(id)
+            .ns(ns)
+            .file(Some(file))
+            .position(line, column)
+            .send_stream(self.nrepl_stream, |resp| {
+                for key in ["out", "err", "value"].iter() {
+                    if let Some(v) = resp.get(*key) {
+                        self.notify_output(key, bc::try_into_string(v.clone()).unwrap_or_default());
+                    }
+                }
+            })?;
+
+        if let Some(ex) = res.root_ex.as_ref().or(res.ex.as_ref()) {
+            self.notify_output("ex", ex.to_string());
+        }
+
+        Ok(json_to_msgpack(serde_json::to_value(res)?))
+    }
+
+    fn interrupt(&self) -> Result<Value, StdError> {
+        let last_eval = self.last_eval.lock().unwrap().clone();
+
+        let res = match last_eval {
+            Some((session, id)) => {
+                ops::Interrupt::new(session, Some(id)).send(self.nrepl_stream)?
+            }
+            None => ops::InterruptResult::SessionIdle,
+        };
+
+        Ok(Value::from(res == ops::InterruptResult::Interrupted))
+    }
+
+    fn call(&self, method: &str, args: &[Value]) -> Result<Value, StdError> {
+        match method {
+            "FindDef" => self.find_def(str_arg(method, args, 0)?, str_arg(method, args, 1)?),
+            "Doc" => self.doc(str_arg(method, args, 0)?, str_arg(method, args, 1)?),
+            "Complete" => self.complete(str_arg(method, args, 0)?, str_arg(method, args, 1)?),
+            "Eval" => self.eval(
+                str_arg(method, args, 0)?,
+                str_arg(method, args, 1)?,
+                opt_int_arg(args, 2),
+                opt_int_arg(args, 3),
+            ),
+            "Interrupt" => self.interrupt(),
+            _ => Err(Error::UnknownMethod {
+                method: method.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    /// Handles request `[0, msgid, method, args]` or notification `[2, method, args]`.
+    /// Results of notifications are dropped, errors are shown as output.
+    fn handle(&self, msg: Value) -> Result<(), StdError> {
+        let items = match msg {
+            Value::Array(items) => items,
+            msg => {
+                return Err(Error::BadMessage {
+                    msg: msg.to_string(),
+                }
+                .into())
+            }
+        };
+
+        match items.as_slice() {
+            [kind, msgid, method, Value::Array(args)] if kind.as_u64() == Some(REQUEST) => {
+                let method = method.as_str().unwrap_or_default();
+                let (error, result) = match self.call(method, args) {
+                    Ok(result) => (Value::Nil, result),
+                    Err(e) => (Value::from(e.to_string()), Value::Nil),
+                };
+
+                self.write(Value::Array(vec![
+                    Value::from(RESPONSE),
+                    msgid.clone(),
+                    error,
+                    result,
+                ]));
+            }
+
+            [kind, method, Value::Array(args)] if kind.as_u64() == Some(NOTIFICATION) => {
+                if let Err(e) = self.call(method.as_str().unwrap_or_default(), args) {
+                    self.notify_output("ex", e.to_string());
+                }
+            }
+
+            // Responses to our notifications never come, nothing else is expected
+            _ => {
+                return Err(Error::BadMessage {
+                    msg: Value::Array(items).to_string(),
+                }
+                .into())
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn run(_matches: &ArgMatches, nrepl_stream: &nrepl::NreplStream) {
+    let host = Host {
+        nrepl_stream,
+        last_eval: Mutex::new(None),
+        stdout: Mutex::new(std::io::stdout()),
+    };
+    let mut stdin = std::io::stdin();
+
+    // Every message is handled in its own thread, so long evaluation doesn't block completion
+    std::thread::scope(|scope| loop {
+        let msg = match rmpv::decode::read_value(&mut stdin) {
+            Ok(msg) => msg,
+            Err(rmpv::decode::Error::InvalidMarkerRead(e))
+                if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break
+            }
+            Err(e) => cmd::die_err(&format!("ERROR: failed to read message: {}", e)),
+        };
+
+        let host = &host;
+        scope.spawn(move || {
+            if let Err(e) = host.handle(msg) {
+                eprintln!("ERROR: {}", e);
+            }
+        });
+    });
+}
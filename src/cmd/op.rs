@@ -1,5 +1,7 @@
+use crate::cmd;
 use crate::nrepl;
 use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
 use serde_json::error as json_error;
 use serde_json::value::Value as JsonValue;
 use std::collections::HashMap;
@@ -26,10 +28,12 @@ impl fmt::Display for OptsParseError {
 struct Opts {
     op: String,
 
+    stream: bool,
+
     op_args: Vec<(String, String)>,
 }
 
-pub fn to_json_string(resp: &nrepl::Resp) -> Result<String, json_error::Error> {
+pub fn to_json_value(resp: &nrepl::Resp) -> JsonValue {
     let mut hm: HashMap<String, JsonValue> = HashMap::new();
 
     for (k, v) in resp.iter() {
@@ -39,7 +43,20 @@ pub fn to_json_string(resp: &nrepl::Resp) -> Result<String, json_error::Error> {
         );
     }
 
-    serde_json::to_string(&hm)
+    serde_json::to_value(hm).unwrap()
+}
+
+pub fn to_json_string(resp: &nrepl::Resp) -> Result<String, json_error::Error> {
+    serde_json::to_string(&to_json_value(resp))
+}
+
+/// Prints response as JSON line, in kv format responses are separated by empty lines
+fn print_resp(out: &mut cmd::Output, resp: &nrepl::Resp, first: bool) -> Result<(), StdError> {
+    if !first && out.format(cmd::Format::Json) == cmd::Format::Kv {
+        writeln!(out.out())?;
+    }
+
+    out.print(cmd::Format::Json, &to_json_value(resp), |v| v.to_string())
 }
 
 impl Opts {
@@ -61,6 +78,7 @@ impl Opts {
 
         let opts = Opts {
             op: op.to_string(),
+            stream: matches.is_present("stream"),
             op_args,
         };
 
@@ -71,20 +89,49 @@ impl Opts {
 pub fn app<'a, 'b>() -> App<'a, 'b> {
     clap_app!(op =>
         (about: "Sends OP to Nrepl and produces JSON output for response")
+        (@arg stream: -s --stream "Print each response as soon as it arrives")
         (@arg OP: +required "Op to send")
         (@arg OP_ARG: ... "Op Argument")
     )
 }
 
-pub fn run(matches: &ArgMatches, nrepl_stream: &nrepl::NreplStream) {
-    match Opts::parse(matches) {
-        Ok(opts) => {
-            let op = nrepl::Op::new(opts.op, opts.op_args);
+pub fn run(
+    matches: &ArgMatches,
+    nrepl_stream: &nrepl::NreplStream,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let opts = match Opts::parse(matches) {
+        Ok(opts) => opts,
+        Err(OptsParseError::BadOpArg(value)) => {
+            return Err(cmd::Error::BadArg {
+                name: "OP_ARG".to_string(),
+                value,
+            }
+            .into())
+        }
+    };
+    let op = nrepl::Op::new(opts.op, opts.op_args);
 
-            for resp in nrepl_stream.op(op).unwrap().into_resps() {
-                println!("{}", to_json_string(&resp).unwrap());
+    if opts.stream {
+        // Streamed ops like eval of long running code may not answer for a while
+        let mut first = true;
+        let mut print_err = None;
+        nrepl_stream.op_stream(op.timeout(None), |resp| {
+            // Once stdout is broken there's nobody to print to, the rest are only awaited
+            if print_err.is_none() {
+                print_err = print_resp(out, resp, first).err();
             }
+            first = false;
+        })?;
+
+        if let Some(e) = print_err {
+            return Err(e);
+        }
+    } else {
+        for (i, resp) in nrepl_stream.op(op)?.into_resps().iter().enumerate() {
+            print_resp(out, resp, i == 0)?;
         }
-        Err(e) => eprintln!("Parse error: {}", e),
     }
+
+    Ok(())
 }
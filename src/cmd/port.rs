@@ -0,0 +1,28 @@
+use crate::cmd;
+use crate::nrepl::port;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use std::path::Path;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(port =>
+        (about: "Shows nrepl port found for FILE and the file it's read from. Port files are \
+                 searched from the directory of FILE up to its project root, then from the \
+                 current directory, ports nobody listens on are skipped")
+        (@arg FILE: "FILE to find nrepl port for")
+    )
+}
+
+pub fn run(matches: &ArgMatches, out: &mut cmd::Output) -> Result<(), StdError> {
+    let found = port::discover(
+        matches.value_of("FILE").map(Path::new),
+        out.cwd(),
+        cmd::nrepl_host(matches),
+    );
+
+    out.print(cmd::Format::Kv, &found, |found| match found {
+        Some(found) if found.alive => format!("{} {}", found.port, found.file),
+        Some(found) => format!("{} {} (not listening)", found.port, found.file),
+        None => String::new(),
+    })
+}
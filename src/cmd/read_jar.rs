@@ -1,6 +1,8 @@
 use crate::cmd;
 use crate::jar;
 use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde_json::json;
 
 pub fn app<'a, 'b>() -> App<'a, 'b> {
     clap_app!(read_jar =>
@@ -10,11 +12,13 @@ pub fn app<'a, 'b>() -> App<'a, 'b> {
     )
 }
 
-pub fn run(matches: &ArgMatches) {
+pub fn run(matches: &ArgMatches, out: &mut cmd::Output) -> Result<(), StdError> {
     let jar = matches.value_of("JAR").unwrap().to_string();
     let file = matches.value_of("FILE").unwrap().to_string();
 
-    let contents = cmd::die_if_err(jar::read_jar_file(jar, file));
+    let contents = jar::read_jar_file(jar, file)?;
 
-    println!("{}", contents);
+    out.print(cmd::Format::Text, &json!({ "contents": contents }), |_| {
+        contents.clone()
+    })
 }
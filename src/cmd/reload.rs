@@ -0,0 +1,43 @@
+use crate::cmd;
+use crate::cmd::require;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use std::path::Path;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(reload =>
+        (about: "Reloads NS of FILE with `require :reload`, like `:Require` of fireplace")
+        (@arg all: -a --all "Reload namespaces it requires too with `:reload-all`, like `:Require!`")
+        (@arg FILE: +required "FILE with NS to reload")
+        (@arg NS: "NS to reload instead of NS of FILE")
+    )
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    nrepl_stream: &nrepl::NreplStream,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let file = Path::new(matches.value_of("FILE").unwrap()).canonicalize()?;
+    let file_str = file.to_string_lossy().to_string();
+    let session = session::get_file_session(nrepl_stream, Some(&file_str))?;
+
+    let ns = match matches.value_of("NS") {
+        Some(ns) => ns.to_string(),
+        None => cmd::file_ns(&file_str, &session, nrepl_stream)?.ok_or(cmd::Error::NoNsDecl)?,
+    };
+    let reload = match matches.is_present("all") {
+        true => ops::Reload::All,
+        false => ops::Reload::Reload,
+    };
+
+    let res = ops::Require::new(session, ns)?
+        .reload(reload)
+        .send(nrepl_stream)?;
+
+    require::print_loaded(out, &file, res)
+}
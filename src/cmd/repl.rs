@@ -0,0 +1,168 @@
+use crate::cmd;
+use crate::config;
+use crate::config::Session;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use crate::reader;
+use clap::{clap_app, App, ArgMatches};
+use rustyline::error::ReadlineError;
+use std::sync::{Arc, Mutex};
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(repl =>
+        (about: "Starts interactive REPL in the session shared with the editor")
+    )
+}
+
+fn history_path() -> std::path::PathBuf {
+    let mut path = config::config_path();
+    path.push("repl_history");
+    path
+}
+
+/// Tells if `code` has unclosed forms, so more lines should be read before evaluating it
+fn is_incomplete(code: &str) -> bool {
+    match reader::read_all(code) {
+        Err(e) => e.is_eof(),
+        Ok(_) => false,
+    }
+}
+
+/// Interrupts evaluation with `running` id on Ctrl-C. Readline handles Ctrl-C on its own,
+/// so the handler is called only while evaluation is running. Responses are dispatched by id,
+/// so interrupt goes through the same connection the evaluation waits on.
+fn set_interrupt_handler(
+    nrepl_stream: Arc<nrepl::NreplStream>,
+    session: Session,
+    running: Arc<Mutex<Option<String>>>,
+) -> Result<(), failure::Error> {
+    ctrlc::set_handler(move || {
+        let id = match running.lock().unwrap().clone() {
+            Some(id) => id,
+            None => return,
+        };
+
+        if let Err(e) = ops::Interrupt::new(session.clone(), Some(id)).send(&nrepl_stream) {
+            eprintln!("ERROR: failed to interrupt: {}", e);
+        }
+    })?;
+
+    Ok(())
+}
+
+/// Line typed by user for evaluated code reading `*in*`, `None` on Ctrl-D
+fn read_input() -> Option<String> {
+    let mut line = String::new();
+
+    match std::io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line),
+    }
+}
+
+fn eval(
+    nrepl_stream: &nrepl::NreplStream,
+    session: &Session,
+    running: &Mutex<Option<String>>,
+    code: String,
+) -> Result<ops::EvalResult, failure::Error> {
+    let id = nrepl_stream.gen_id();
+    config::save_last_eval_id(session, &id)?;
+    *running.lock().unwrap() = Some(id.clone());
+
+    let mut out = cmd::Output::stdio(None);
+    let res = ops::Eval::new(session.clone(), code).id(id).send_input(
+        nrepl_stream,
+        |resp| {
+            let _ = cmd::eval::print_output(&mut out, resp);
+        },
+        read_input,
+    );
+
+    *running.lock().unwrap() = None;
+    res
+}
+
+pub fn run(_matches: &ArgMatches, nrepl_stream: Arc<nrepl::NreplStream>) {
+    let session = cmd::die_if_err(session::get_existing_session_id(&nrepl_stream));
+    let running = Arc::new(Mutex::new(None));
+    cmd::die_if_err(set_interrupt_handler(
+        nrepl_stream.clone(),
+        session.clone(),
+        running.clone(),
+    ));
+
+    // Session keeps `*ns*` between evaluations, but asking for it would clobber `*1` and the
+    // last eval id, so it's learned from the first evaluation
+    let mut ns = "user".to_string();
+
+    let mut rl = rustyline::Editor::<()>::new();
+    let _ = rl.load_history(&history_path());
+    let mut code = String::new();
+
+    loop {
+        let prompt = if code.is_empty() {
+            format!("{}=> ", ns)
+        } else {
+            format!("{:>width$}", "#_=> ", width = ns.len() + 3)
+        };
+
+        match rl.readline(&prompt) {
+            Ok(line) => {
+                code.push_str(&line);
+                code.push('\n');
+
+                if is_incomplete(&code) {
+                    continue;
+                }
+
+                let form = std::mem::take(&mut code);
+                if form.trim().is_empty() {
+                    continue;
+                }
+                rl.add_history_entry(form.trim_end());
+                // Saved right away, evaluation may never return or unrepl could be killed
+                if let Err(e) = rl.save_history(&history_path()) {
+                    eprintln!("ERROR: failed to save history: {}", e);
+                }
+
+                match eval(&nrepl_stream, &session, &running, form) {
+                    Ok(res) => {
+                        if res.interrupted {
+                            eprintln!("Interrupted");
+                        } else if let Some(ex) = res.root_ex.or(res.ex) {
+                            eprintln!("ERROR: {}", ex);
+                        }
+                        if let Some(new_ns) = res.ns {
+                            ns = new_ns;
+                        }
+                    }
+                    Err(e) => eprintln!("ERROR: {}", e),
+                }
+
+                if nrepl_stream.is_closed() {
+                    cmd::die_err("Connection to nrepl is closed");
+                }
+            }
+            // Ctrl-C drops the form being typed
+            Err(ReadlineError::Interrupted) => code.clear(),
+            Err(ReadlineError::Eof) => break,
+            Err(e) => cmd::die_err(&format!("ERROR: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incomplete_forms_test() {
+        assert!(is_incomplete("(defn f [x]\n"));
+        assert!(is_incomplete("(str \"a\n"));
+        assert!(!is_incomplete("(inc 1)\n"));
+        assert!(!is_incomplete("(inc 1))\n"));
+    }
+}
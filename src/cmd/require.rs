@@ -0,0 +1,95 @@
+use crate::cmd;
+use crate::cmd::lint::FileDiagnostic;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use crate::project;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde::Serialize;
+use std::path::Path;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(require =>
+        (about: "Loads NS of FILE with `require`, it's not loaded again when it was loaded before, \
+                 see `reload`")
+        (@arg FILE: +required "FILE with NS to load")
+        (@arg NS: "NS to load instead of NS of FILE")
+    )
+}
+
+/// Result of loading code with the place of syntax error which failed it
+#[derive(Debug, Serialize)]
+pub struct LoadResult {
+    #[serde(flatten)]
+    pub eval: ops::EvalResult,
+    pub compile_error: Option<FileDiagnostic>,
+}
+
+/// Path of file with compile error, Clojure reports classpath resources when loading
+/// namespaces and the given path with `load-file`
+fn error_file(file: &Path, error_file: &str) -> String {
+    if file.ends_with(error_file) {
+        return file.to_string_lossy().to_string();
+    }
+    if Path::new(error_file).is_absolute() {
+        return error_file.to_string();
+    }
+
+    file.parent()
+        .and_then(project::find_root)
+        .and_then(|root| project::find_resource(&root, error_file))
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_else(|| error_file.to_string())
+}
+
+/// Prints result of loading code of `file`, text format gives compile error as
+/// `FILE:LINE:COL: MESSAGE` error message
+pub fn print_loaded(
+    out: &mut cmd::Output,
+    file: &Path,
+    eval: ops::EvalResult,
+) -> Result<(), StdError> {
+    let compile_error = eval.compile_error().map(|e| FileDiagnostic {
+        file: error_file(file, &e.file),
+        line: e.line as usize,
+        column: e.column as usize,
+        message: e.message,
+    });
+
+    if out.format(cmd::Format::Text) != cmd::Format::Text {
+        let res = LoadResult {
+            eval,
+            compile_error,
+        };
+        return out.print(cmd::Format::Text, &res, |_| String::new());
+    }
+
+    cmd::eval::print_result(out, &eval).map_err(|e| match compile_error {
+        Some(d) => cmd::Error::EvalFailed {
+            ex: format!("{}:{}:{}: {}", d.file, d.line, d.column, d.message),
+        }
+        .into(),
+        None => e,
+    })
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    nrepl_stream: &nrepl::NreplStream,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let file = Path::new(matches.value_of("FILE").unwrap()).canonicalize()?;
+    let file_str = file.to_string_lossy().to_string();
+    let session = session::get_file_session(nrepl_stream, Some(&file_str))?;
+
+    let ns = match matches.value_of("NS") {
+        Some(ns) => ns.to_string(),
+        None => cmd::file_ns(&file_str, &session, nrepl_stream)?.ok_or(cmd::Error::NoNsDecl)?,
+    };
+
+    let res = ops::Require::new(session, ns)?.send(nrepl_stream)?;
+
+    print_loaded(out, &file, res)
+}
@@ -0,0 +1,193 @@
+use crate::cmd;
+use crate::cmd::lint::FileDiagnostic;
+use crate::nrepl;
+use crate::nrepl::ops;
+use crate::nrepl::session;
+use crate::nrepl::NreplOp;
+use crate::project;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(run_tests =>
+        (about: "Reloads and runs clojure.test tests of NS of FILE and of its `-test` namespace, \
+                 like `:RunTests` of fireplace")
+        (@arg FILE: +required "FILE with NS to test")
+        (@arg NS: +multiple "NSs to test instead of NS of FILE")
+    )
+}
+
+/// Summary of test run, failures have their files found in the project when it's possible
+#[derive(Debug, Serialize)]
+pub struct TestReport {
+    #[serde(flatten)]
+    pub summary: ops::TestSummary,
+    pub failures: Vec<FileDiagnostic>,
+    /// Report printed by `clojure.test`
+    pub out: String,
+    /// Exception which stopped tests from running
+    pub ex: Option<String>,
+}
+
+/// `ns` and its test namespace, tests of `foo.core` are in `foo.core-test`
+fn test_namespaces(ns: &str) -> Vec<String> {
+    if ns.ends_with("-test") {
+        vec![ns.to_string()]
+    } else {
+        vec![ns.to_string(), format!("{}-test", ns)]
+    }
+}
+
+/// Parses `FAIL in (test) (core_test.clj:10)` and `ERROR in ...` entries of `clojure.test`
+/// report, the lines following them up to an empty line are a part of the message.
+/// Files are only names of files, as `clojure.test` takes them from stack frames.
+fn parse_failures(report: &str) -> Vec<FileDiagnostic> {
+    let mut failures = vec![];
+    let mut lines = report.lines().peekable();
+    let is_start = |l: &str| l.starts_with("FAIL in ") || l.starts_with("ERROR in ");
+
+    while let Some(line) = lines.next() {
+        if !is_start(line) {
+            continue;
+        }
+
+        let (test, location) = match line.rsplit_once(" (") {
+            Some((test, location)) => (test, location.trim_end_matches(')')),
+            None => continue,
+        };
+        let (file, line_num) = match location.rsplit_once(':') {
+            Some((file, n)) => (file, n.parse::<usize>().unwrap_or(1)),
+            None => (location, 1),
+        };
+
+        let mut details = vec![test.to_string()];
+        while let Some(next) = lines.peek() {
+            if next.trim().is_empty() || is_start(next) {
+                break;
+            }
+            details.push(next.trim().to_string());
+            lines.next();
+        }
+
+        failures.push(FileDiagnostic {
+            file: file.to_string(),
+            line: line_num,
+            column: 1,
+            // Exceptions are followed by stacktraces
+            message: details
+                .into_iter()
+                .take(4)
+                .collect::<Vec<String>>()
+                .join(" "),
+        });
+    }
+
+    failures
+}
+
+/// Finds file named `name` among source `files` of project
+fn resolve_file(files: &[PathBuf], name: &str) -> Option<String> {
+    files
+        .iter()
+        .find(|f| f.file_name().is_some_and(|n| n == name))
+        .map(|f| f.to_string_lossy().to_string())
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    nrepl_stream: &nrepl::NreplStream,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let file = Path::new(matches.value_of("FILE").unwrap()).canonicalize()?;
+    let file_str = file.to_string_lossy().to_string();
+    let session = session::get_file_session(nrepl_stream, Some(&file_str))?;
+    let ns = cmd::file_ns(&file_str, &session, nrepl_stream)?;
+
+    let namespaces = match (matches.values_of("NS"), &ns) {
+        (Some(nss), _) => nss.map(String::from).collect(),
+        (None, Some(ns)) => test_namespaces(ns),
+        (None, None) => return Err(cmd::Error::NoNsDecl.into()),
+    };
+
+    let res = ops::RunTests::new(session, namespaces)?
+        .ns(ns)
+        .send(nrepl_stream)?;
+
+    let report = res.eval.out.concat();
+    let dir = file.parent().unwrap_or_else(|| Path::new("."));
+    let files = project::source_files(&project::find_root(dir).unwrap_or_else(|| dir.into()));
+    let failures = parse_failures(&report)
+        .into_iter()
+        .map(|mut f| {
+            if let Some(path) = resolve_file(&files, &f.file) {
+                f.file = path;
+            }
+            f
+        })
+        .collect();
+
+    let ex = res.eval.root_ex.clone().or_else(|| res.eval.ex.clone());
+    let report = TestReport {
+        summary: res.summary.unwrap_or_default(),
+        failures,
+        out: report,
+        ex,
+    };
+
+    if out.format(cmd::Format::Text) != cmd::Format::Text {
+        return out.print(cmd::Format::Text, &report, |_| String::new());
+    }
+
+    write!(out.out(), "{}", report.out)?;
+    write!(out.err(), "{}", res.eval.err.concat())?;
+
+    match report.ex {
+        Some(ex) => Err(cmd::Error::EvalFailed { ex }.into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_failures_test() {
+        let report = "
+Testing foo.core-test
+
+FAIL in (adds-test) (core_test.clj:8)
+with small numbers
+expected: (= 3 (add 1 1))
+  actual: (not (= 3 2))
+
+ERROR in (fails-test) (Numbers.java:188)
+expected: (= 1 (/ 1 0))
+  actual: java.lang.ArithmeticException: Divide by zero
+ at clojure.lang.Numbers.divide (Numbers.java:188)
+    clojure.lang.Numbers.divide (Numbers.java:3901)
+
+Ran 2 tests containing 2 assertions.
+1 failures, 1 errors.
+";
+        let failures = parse_failures(report)
+            .into_iter()
+            .map(|f| format!("{}:{}: {}", f.file, f.line, f.message))
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            failures,
+            vec![
+                "core_test.clj:8: FAIL in (adds-test) with small numbers expected: (= 3 (add 1 1)) actual: (not (= 3 2))",
+                "Numbers.java:188: ERROR in (fails-test) expected: (= 1 (/ 1 0)) actual: java.lang.ArithmeticException: Divide by zero at clojure.lang.Numbers.divide (Numbers.java:188)",
+            ]
+        );
+        assert_eq!(
+            test_namespaces("foo.core"),
+            vec!["foo.core", "foo.core-test"]
+        );
+        assert_eq!(test_namespaces("foo.core-test"), vec!["foo.core-test"]);
+    }
+}
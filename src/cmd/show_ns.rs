@@ -0,0 +1,32 @@
+use crate::cmd;
+use crate::nrepl::session;
+use crate::ns;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde_json::json;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(show_ns => (@arg FILE: +takes_value "File"))
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    connect: &cmd::Connect,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let file = matches.value_of("FILE").unwrap();
+
+    // Reading NS doesn't need nrepl unless the file is too tricky for our reader
+    let ns = match ns::read_file_ns_decl(file) {
+        Ok(Some(decl)) => Some(decl.name),
+        _ => {
+            let n = connect()?;
+            let session = session::get_existing_session_id(&n)?;
+            cmd::file_ns(file, &session, &n)?
+        }
+    };
+
+    out.print(cmd::Format::Text, &json!({ "ns": ns }), |_| {
+        format!("NS: {}", ns.as_deref().unwrap_or_default())
+    })
+}
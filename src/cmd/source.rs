@@ -0,0 +1,64 @@
+use crate::cmd;
+use crate::cmd::find_def::{self, Definition, DefinitionKind};
+use crate::java;
+use crate::reader;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde::Serialize;
+use std::path::Path;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(source =>
+        (about: "Shows source of definition of SYMBOL, works without nrepl when project is indexed")
+        (@arg FILE: +required "FILE with NS containing SYMBOL")
+        (@arg SYMBOL: +required "SYMBOL")
+    )
+}
+
+/// Source of definition together with its place
+#[derive(Debug, Serialize)]
+pub struct DefinitionSource {
+    pub file: String,
+    pub line: i64,
+    pub source: String,
+}
+
+/// Reads the top level form defining `def`, or the declaration of Java class or member
+pub fn definition_source(def: &Definition) -> Result<String, StdError> {
+    let source = std::fs::read_to_string(&def.file)?;
+
+    if matches!(def.kind, DefinitionKind::Class | DefinitionKind::Member) {
+        return Ok(java::declaration_source(&source, def.line as usize));
+    }
+
+    let nodes = reader::read_all(&source)?;
+
+    Ok(
+        reader::form_at(&nodes, def.line as usize, def.column as usize, true)
+            .map(|node| source[node.start.offset..node.end.offset].to_string())
+            .unwrap_or_default(),
+    )
+}
+
+pub fn run(
+    matches: &ArgMatches,
+    connect: &cmd::Connect,
+    out: &mut cmd::Output,
+) -> Result<(), StdError> {
+    let file = Path::new(matches.value_of("FILE").unwrap()).canonicalize()?;
+    let symbol = matches.value_of("SYMBOL").unwrap();
+
+    let source = match find_def::connect_find_def(connect, &file.to_string_lossy(), symbol)? {
+        Some(def) => Some(DefinitionSource {
+            source: definition_source(&def)?,
+            file: def.file,
+            line: def.line,
+        }),
+        None => None,
+    };
+
+    out.print(cmd::Format::Text, &source, |source| match source {
+        Some(source) => source.source.to_string(),
+        None => String::new(),
+    })
+}
@@ -0,0 +1,87 @@
+use crate::cmd;
+use crate::config;
+use crate::nrepl::port;
+use crate::project;
+use crate::server;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(status =>
+        (about: "Shows if nrepl of project is running and if it was started by `jack_in`")
+        (@arg DIR: "Directory of project, the current one by default")
+    )
+}
+
+/// nrepl of project, `tool`, `pid` and `log` are known only for servers started by `jack_in`
+#[derive(Debug, Serialize)]
+pub struct ServerStatus {
+    pub root: String,
+    pub running: bool,
+    pub port: Option<u16>,
+    pub port_file: Option<String>,
+    pub tool: Option<String>,
+    pub pid: Option<u32>,
+    pub log: Option<String>,
+}
+
+/// Root of project containing DIR argument, `cwd` by default
+pub fn project_root(matches: &ArgMatches, cwd: &Path) -> Result<PathBuf, StdError> {
+    let dir = cwd
+        .join(matches.value_of("DIR").unwrap_or("."))
+        .canonicalize()?;
+    Ok(project::find_root(&dir).unwrap_or(dir))
+}
+
+/// Status of nrepl of project in `root`, servers which exited are forgotten
+pub fn server_status(root: &Path) -> Result<ServerStatus, StdError> {
+    let root_str = root.to_string_lossy().to_string();
+    let server = match config::load_server(&root_str)? {
+        Some(server) if !server::is_running(server.pid) => {
+            config::delete_server(&root_str)?;
+            None
+        }
+        server => server,
+    };
+    let found = port::discover_in(&[root.to_path_buf()], server::LOCAL_HOST);
+
+    Ok(ServerStatus {
+        root: root_str,
+        running: found.as_ref().is_some_and(|f| f.alive),
+        port: found.as_ref().map(|f| f.port),
+        port_file: found.map(|f| f.file),
+        tool: server.as_ref().map(|s| s.tool.clone()),
+        pid: server.as_ref().map(|s| s.pid),
+        log: server.map(|s| s.log),
+    })
+}
+
+/// Describes status for humans
+pub fn status_text(status: &ServerStatus) -> String {
+    let mut text = match (status.running, status.port, &status.port_file) {
+        (true, Some(port), Some(file)) => {
+            format!("nrepl is running on port {} from {}", port, file)
+        }
+        (false, Some(port), Some(file)) => {
+            format!("nrepl isn't running, port {} in {} is stale", port, file)
+        }
+        _ => "nrepl isn't running".to_string(),
+    };
+
+    if let (Some(tool), Some(pid)) = (&status.tool, status.pid) {
+        text.push_str(&format!("\nstarted by jack_in with {}, pid {}", tool, pid));
+    }
+    if let Some(log) = &status.log {
+        text.push_str(&format!("\nlog: {}", log));
+    }
+
+    text
+}
+
+pub fn run(matches: &ArgMatches, out: &mut cmd::Output) -> Result<(), StdError> {
+    let status = server_status(&project_root(matches, out.cwd())?)?;
+
+    out.print(cmd::Format::Text, &status, status_text)
+}
@@ -0,0 +1,35 @@
+use crate::cmd;
+use crate::cmd::status;
+use crate::config;
+use crate::server;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use serde_json::json;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(stop =>
+        (about: "Stops nrepl of project started by `jack_in`")
+        (@arg DIR: "Directory of project, the current one by default")
+    )
+}
+
+pub fn run(matches: &ArgMatches, out: &mut cmd::Output) -> Result<(), StdError> {
+    let root = status::project_root(matches, out.cwd())?;
+
+    let stopped = match config::load_server(&root.to_string_lossy())? {
+        Some(server) => {
+            server::stop(&server)?;
+            true
+        }
+        None => false,
+    };
+
+    out.print(
+        cmd::Format::Kv,
+        &json!({ "stopped": stopped }),
+        |_| match stopped {
+            true => "Stopped".to_string(),
+            false => "No nrepl was started by jack_in".to_string(),
+        },
+    )
+}
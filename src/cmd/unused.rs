@@ -0,0 +1,27 @@
+use crate::analysis;
+use crate::cmd;
+use crate::lint;
+use clap::{clap_app, App, ArgMatches};
+use failure::Error as StdError;
+use std::io::Read;
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    clap_app!(unused =>
+        (about: "Reports unused private vars, aliases and refers in FILE, doesn't need nrepl")
+        (@arg FILE: +required "FILE to check, - to read it from stdin")
+    )
+}
+
+pub fn run(matches: &ArgMatches, out: &mut cmd::Output) -> Result<(), StdError> {
+    let file = matches.value_of("FILE").unwrap();
+    let mut source = String::new();
+
+    if file == "-" {
+        std::io::stdin().read_to_string(&mut source)?;
+    } else {
+        std::fs::File::open(file)?.read_to_string(&mut source)?;
+    }
+
+    let analysis = analysis::analyze_source(&source, file)?;
+    cmd::lint::print_diagnostics(out, cmd::Format::Kv, file, lint::unused(&analysis))
+}
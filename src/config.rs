@@ -1,3 +1,7 @@
+//! Configuration-related facilities
+
+#![allow(non_local_definitions)]
+
 use failure::Error as StdError;
 use lazy_static::lazy_static;
 use rusqlite::{params, Connection, OptionalExtension, NO_PARAMS};
@@ -7,9 +11,10 @@ use std::convert::From;
 use std::path::PathBuf;
 
 lazy_static! {
-    static ref MIGRATIONS: Vec<(&'static str, &'static str)> = vec![(
-        "v1",
-        "
+    static ref MIGRATIONS: Vec<(&'static str, &'static str)> = vec![
+        (
+            "v1",
+            "
 CREATE TABLE IF NOT EXISTS sessions(
   addr TEXT PRIMARY KEY,
   session_id TEXT,
@@ -17,15 +22,106 @@ CREATE TABLE IF NOT EXISTS sessions(
 )
 
          "
-    )];
+        ),
+        (
+            "v2",
+            "
+ALTER TABLE sessions ADD COLUMN last_eval_id TEXT
+         "
+        ),
+        (
+            "v3",
+            "
+CREATE TABLE sessions_v3(
+  addr TEXT,
+  kind TEXT NOT NULL DEFAULT 'clj',
+  session_id TEXT,
+  ops_list TEXT,
+  last_eval_id TEXT,
+  upgrade_code TEXT,
+  PRIMARY KEY (addr, kind)
+);
+INSERT INTO sessions_v3 (addr, session_id, ops_list, last_eval_id)
+  SELECT addr, session_id, ops_list, last_eval_id FROM sessions;
+DROP TABLE sessions;
+ALTER TABLE sessions_v3 RENAME TO sessions;
+CREATE TABLE IF NOT EXISTS settings(
+  addr TEXT,
+  name TEXT,
+  value TEXT,
+  PRIMARY KEY (addr, name)
+);
+         "
+        ),
+        (
+            "v4",
+            "
+CREATE TABLE IF NOT EXISTS index_files(
+  path TEXT PRIMARY KEY,
+  mtime INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS index_namespaces(
+  name TEXT NOT NULL,
+  path TEXT NOT NULL,
+  entry TEXT,
+  line INTEGER NOT NULL,
+  col INTEGER NOT NULL,
+  doc TEXT
+);
+CREATE INDEX IF NOT EXISTS index_namespaces_name ON index_namespaces(name);
+CREATE INDEX IF NOT EXISTS index_namespaces_path ON index_namespaces(path);
+CREATE TABLE IF NOT EXISTS index_vars(
+  ns TEXT NOT NULL,
+  name TEXT NOT NULL,
+  kind TEXT NOT NULL,
+  arglists TEXT NOT NULL,
+  doc TEXT,
+  private INTEGER NOT NULL,
+  path TEXT NOT NULL,
+  entry TEXT,
+  line INTEGER NOT NULL,
+  col INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS index_vars_ns_name ON index_vars(ns, name);
+CREATE INDEX IF NOT EXISTS index_vars_path ON index_vars(path);
+         "
+        ),
+        (
+            "v5",
+            "
+CREATE TABLE IF NOT EXISTS servers(
+  root TEXT PRIMARY KEY,
+  tool TEXT,
+  pid INTEGER,
+  port_file TEXT,
+  log TEXT
+);
+         "
+        ),
+        (
+            "v6",
+            "
+UPDATE sessions SET addr = 'nrepl://' || addr WHERE addr NOT LIKE 'nrepl%';
+UPDATE settings SET addr = 'nrepl://' || addr WHERE addr NOT LIKE 'nrepl%';
+         "
+        ),
+        (
+            "v7",
+            "
+CREATE TABLE IF NOT EXISTS index_roots(
+  root TEXT NOT NULL,
+  path TEXT NOT NULL,
+  PRIMARY KEY (root, path)
+);
+         "
+        )
+    ];
 }
 
 thread_local! {
     static DB: RefCell<Connection> = RefCell::new(open_db_connection().unwrap());
 }
 
-///! Configuration-related facilities
-
 #[derive(Debug, failure::Fail)]
 pub enum Error {
     #[fail(display = "failed to parse sessions: {}", error)]
@@ -86,40 +182,37 @@ fn ensure_migrations_table(conn: &Connection) -> Result<(), StdError> {
     Ok(())
 }
 
-fn run_migrations(conn: &Connection, migration: Option<String>) -> Result<(), StdError> {
-    let mut run = migration.is_none();
-
-    for (mig_name, mig_sql) in MIGRATIONS.iter() {
-        if run {
-            conn.execute(mig_sql, params!())?;
-            conn.execute("INSERT INTO migrations VALUES (?)", params![mig_name])?;
-        }
-
-        if migration.is_some() && migration.as_ref().unwrap() == mig_name {
-            run = true;
-        }
+/// Brings schema of `conn` up to date, each migration is applied in its own transaction
+pub fn migrate(conn: &mut Connection) -> Result<(), StdError> {
+    ensure_migrations_table(conn)?;
+
+    let applied = conn
+        .prepare("SELECT name FROM migrations")?
+        .query_map(NO_PARAMS, |row| row.get(0))?
+        .collect::<Result<HashSet<String>, _>>()?;
+    // Names like "v10" don't sort as strings, so the order is the one of `MIGRATIONS`
+    let pending = MIGRATIONS
+        .iter()
+        .rposition(|(name, _)| applied.contains(*name))
+        .map_or(0, |i| i + 1);
+
+    for (mig_name, mig_sql) in MIGRATIONS[pending..].iter() {
+        let tx = conn.transaction()?;
+        tx.execute_batch(mig_sql)?;
+        tx.execute("INSERT INTO migrations VALUES (?)", params![mig_name])?;
+        tx.commit()?;
     }
 
     Ok(())
 }
 
 pub fn ensure_migrations() -> Result<(), StdError> {
-    DB.with(|conn| {
-        let conn = conn.borrow();
-        ensure_migrations_table(&conn)?;
-
-        let latest_migration: Option<String> = conn
-            .query_row(
-                "SELECT name FROM migrations ORDER BY name DESC LIMIT 1",
-                params!(),
-                |row| row.get(0),
-            )
-            .optional()?;
-
-        run_migrations(&conn, latest_migration)?;
+    DB.with(|conn| migrate(&mut conn.borrow_mut()))
+}
 
-        Ok(())
-    })
+/// Runs `f` with the database connection of this thread, for modules keeping their own tables
+pub fn with_db<T>(f: impl FnOnce(&mut Connection) -> Result<T, StdError>) -> Result<T, StdError> {
+    DB.with(|conn| f(&mut conn.borrow_mut()))
 }
 
 pub fn save_session(session: &Session) -> Result<(), StdError> {
@@ -128,17 +221,19 @@ pub fn save_session(session: &Session) -> Result<(), StdError> {
 
         conn.execute(
             "INSERT OR REPLACE
-            INTO sessions (addr, session_id, ops_list)
-            VALUES (?1, ?2, ?3)",
+            INTO sessions (addr, kind, session_id, ops_list, upgrade_code)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
             params![
                 session.addr,
+                session.kind.name(),
                 session.session,
                 session
                     .ops
                     .clone()
                     .into_iter()
                     .collect::<Vec<String>>()
-                    .join(",")
+                    .join(","),
+                session.upgrade_code
             ],
         )?;
 
@@ -146,15 +241,15 @@ pub fn save_session(session: &Session) -> Result<(), StdError> {
     })
 }
 
-pub fn load_session(addr: String) -> Result<Option<Session>, StdError> {
+pub fn load_session(addr: String, kind: SessionKind) -> Result<Option<Session>, StdError> {
     DB.with(|conn| {
         let conn = conn.borrow();
 
         conn.query_row(
-            "SELECT addr, session_id, ops_list
+            "SELECT addr, session_id, ops_list, upgrade_code
             FROM sessions
-            WHERE addr = ?",
-            params![addr],
+            WHERE addr = ?1 AND kind = ?2",
+            params![addr, kind.name()],
             |row| {
                 Ok(Session::new(
                     row.get(0)?,
@@ -163,7 +258,120 @@ pub fn load_session(addr: String) -> Result<Option<Session>, StdError> {
                         .split(",")
                         .map(|s| s.to_string())
                         .collect(),
-                ))
+                )
+                .with_kind(kind)
+                .with_upgrade_code(row.get(3)?))
+            },
+        )
+        .optional()
+        .map_err(|e| e.into())
+    })
+}
+
+/// Remembers `id` of the latest `eval` sent within `session`, so it could be interrupted
+pub fn save_last_eval_id(session: &Session, id: &str) -> Result<(), StdError> {
+    DB.with(|conn| {
+        let conn = conn.borrow();
+
+        conn.execute(
+            "UPDATE sessions SET last_eval_id = ?1 WHERE addr = ?2 AND session_id = ?3",
+            params![id, session.addr, session.session],
+        )?;
+
+        Ok(())
+    })
+}
+
+pub fn load_last_eval_id(session: &Session) -> Result<Option<String>, StdError> {
+    DB.with(|conn| {
+        let conn = conn.borrow();
+
+        conn.query_row(
+            "SELECT last_eval_id FROM sessions WHERE addr = ?1 AND session_id = ?2",
+            params![session.addr, session.session],
+            |row| row.get(0),
+        )
+        .optional()
+        .map(|id| id.flatten())
+        .map_err(|e| e.into())
+    })
+}
+
+/// Saves per-address setting `name`
+pub fn save_setting(addr: &str, name: &str, value: &str) -> Result<(), StdError> {
+    DB.with(|conn| {
+        let conn = conn.borrow();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO settings (addr, name, value) VALUES (?1, ?2, ?3)",
+            params![addr, name, value],
+        )?;
+
+        Ok(())
+    })
+}
+
+pub fn load_setting(addr: &str, name: &str) -> Result<Option<String>, StdError> {
+    DB.with(|conn| {
+        let conn = conn.borrow();
+
+        conn.query_row(
+            "SELECT value FROM settings WHERE addr = ?1 AND name = ?2",
+            params![addr, name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.into())
+    })
+}
+
+/// nrepl server started by `jack_in` for project in `root`
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Server {
+    pub root: String,
+    pub tool: String,
+    /// Also id of process group of the server, as tools start more processes
+    pub pid: u32,
+    pub port_file: String,
+    /// File with output of the server
+    pub log: String,
+}
+
+pub fn save_server(server: &Server) -> Result<(), StdError> {
+    DB.with(|conn| {
+        let conn = conn.borrow();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO servers (root, tool, pid, port_file, log)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                server.root,
+                server.tool,
+                server.pid,
+                server.port_file,
+                server.log
+            ],
+        )?;
+
+        Ok(())
+    })
+}
+
+pub fn load_server(root: &str) -> Result<Option<Server>, StdError> {
+    DB.with(|conn| {
+        let conn = conn.borrow();
+
+        conn.query_row(
+            "SELECT root, tool, pid, port_file, log FROM servers WHERE root = ?1",
+            params![root],
+            |row| {
+                Ok(Server {
+                    root: row.get(0)?,
+                    tool: row.get(1)?,
+                    pid: row.get(2)?,
+                    port_file: row.get(3)?,
+                    log: row.get(4)?,
+                })
             },
         )
         .optional()
@@ -171,16 +379,84 @@ pub fn load_session(addr: String) -> Result<Option<Session>, StdError> {
     })
 }
 
+pub fn delete_server(root: &str) -> Result<(), StdError> {
+    DB.with(|conn| {
+        conn.borrow()
+            .execute("DELETE FROM servers WHERE root = ?1", params![root])?;
+
+        Ok(())
+    })
+}
+
+/// Language evaluated by a session, each address can have one session of each kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SessionKind {
+    Clj,
+    Cljs,
+}
+
+impl SessionKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Clj => "clj",
+            Self::Cljs => "cljs",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clj" => Some(Self::Clj),
+            "cljs" => Some(Self::Cljs),
+            _ => None,
+        }
+    }
+
+    /// Reader conditional features of the dialect
+    pub fn features(&self) -> [&'static str; 2] {
+        match self {
+            Self::Clj => ["clj", "default"],
+            Self::Cljs => ["cljs", "default"],
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Session {
     addr: String,
     session: String,
     ops: HashSet<String>,
+    kind: SessionKind,
+    /// Code turning a fresh clone into ClojureScript session
+    upgrade_code: Option<String>,
 }
 
 impl Session {
     pub fn new(addr: String, session: String, ops: HashSet<String>) -> Self {
-        Self { addr, session, ops }
+        Self {
+            addr,
+            session,
+            ops,
+            kind: SessionKind::Clj,
+            upgrade_code: None,
+        }
+    }
+
+    pub fn with_kind(mut self, kind: SessionKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_upgrade_code(mut self, upgrade_code: Option<String>) -> Self {
+        self.upgrade_code = upgrade_code;
+        self
+    }
+
+    pub fn kind(&self) -> SessionKind {
+        self.kind
+    }
+
+    pub fn upgrade_code(&self) -> Option<&str> {
+        self.upgrade_code.as_deref()
     }
 
     pub fn id(&self) -> String {
@@ -191,3 +467,23 @@ impl Session {
         self.ops.contains(op)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_test() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        // Applied migrations aren't run again, `ALTER TABLE` would fail otherwise
+        migrate(&mut conn).unwrap();
+
+        let count: u32 = conn
+            .query_row("SELECT count(*) FROM migrations", NO_PARAMS, |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count as usize, MIGRATIONS.len());
+    }
+}
@@ -0,0 +1,595 @@
+//! Index of namespaces and vars defined in project sources and JARs on classpath. It's kept in
+//! the database, so definitions, docs and completions are found without running nrepl. Files and
+//! JARs are shared between projects, lookups see only the ones indexed for the project root.
+
+use crate::analysis::{self, Analysis};
+use crate::nrepl::ops;
+use crate::ns::Refer;
+use crate::project;
+use failure::Error as StdError;
+use rusqlite::{params, Connection, OptionalExtension, Row, NO_PARAMS};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Namespace declared in indexed file, `entry` is set for files inside of JAR `path`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IndexedNs {
+    pub name: String,
+    pub path: String,
+    pub entry: Option<String>,
+    pub line: i64,
+    pub column: i64,
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IndexedVar {
+    pub ns: String,
+    pub name: String,
+    /// Defining form, like `defn` or `defmacro`
+    pub kind: String,
+    pub arglists: Vec<String>,
+    pub doc: Option<String>,
+    pub private: bool,
+    pub path: String,
+    pub entry: Option<String>,
+    pub line: i64,
+    pub column: i64,
+}
+
+/// What symbol written in a file stands for
+#[derive(Debug, Clone, PartialEq)]
+pub enum Indexed {
+    Ns(IndexedNs),
+    Var(IndexedVar),
+}
+
+impl Indexed {
+    /// Doc formatted the same way as doc of `info` op
+    pub fn doc(&self) -> String {
+        let lines = match self {
+            Self::Ns(ns) => vec![ns.name.to_string(), ns.doc.clone().unwrap_or_default()],
+            Self::Var(var) => vec![
+                String::from(if var.kind == "defmacro" { "macro" } else { "" }),
+                format!("{}/{}", var.ns, var.name),
+                ops::format_arglists(Some(var.arglists.join("\n"))),
+                var.doc.clone().unwrap_or_default(),
+            ],
+        };
+
+        lines
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// How indexing of single file or JAR went
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Indexed,
+    /// Not modified since it was indexed last time
+    Unchanged,
+}
+
+/// Source analyzed for index, `entry` is its path inside of JAR
+struct Source {
+    entry: Option<String>,
+    analysis: Analysis,
+}
+
+const NS_COLUMNS: &str = "name, path, entry, line, col, doc";
+
+const VAR_COLUMNS: &str = "ns, name, kind, arglists, doc, private, path, entry, line, col";
+
+fn ns_from_row(row: &Row) -> rusqlite::Result<IndexedNs> {
+    Ok(IndexedNs {
+        name: row.get(0)?,
+        path: row.get(1)?,
+        entry: row.get(2)?,
+        line: row.get(3)?,
+        column: row.get(4)?,
+        doc: row.get(5)?,
+    })
+}
+
+fn var_from_row(row: &Row) -> rusqlite::Result<IndexedVar> {
+    Ok(IndexedVar {
+        ns: row.get(0)?,
+        name: row.get(1)?,
+        kind: row.get(2)?,
+        arglists: serde_json::from_str(&row.get::<usize, String>(3)?).unwrap_or_default(),
+        doc: row.get(4)?,
+        private: row.get(5)?,
+        path: row.get(6)?,
+        entry: row.get(7)?,
+        line: row.get(8)?,
+        column: row.get(9)?,
+    })
+}
+
+fn mtime(path: &Path) -> Result<i64, StdError> {
+    let mtime = std::fs::metadata(path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)?;
+    Ok(mtime.as_nanos() as i64)
+}
+
+/// Sources which can't be read or don't declare a namespace are not indexed
+fn analyze(source: &str, path: &str) -> Option<Analysis> {
+    analysis::analyze_source(source, path)
+        .ok()
+        .filter(|a| a.ns.is_some())
+}
+
+fn remove(conn: &Connection, path: &str) -> Result<(), StdError> {
+    conn.execute("DELETE FROM index_files WHERE path = ?1", params![path])?;
+    conn.execute(
+        "DELETE FROM index_namespaces WHERE path = ?1",
+        params![path],
+    )?;
+    conn.execute("DELETE FROM index_vars WHERE path = ?1", params![path])?;
+    Ok(())
+}
+
+/// Replaces everything indexed for `path` with `sources`
+fn store(
+    conn: &mut Connection,
+    path: &str,
+    mtime: i64,
+    sources: &[Source],
+) -> Result<(), StdError> {
+    let tx = conn.transaction()?;
+    remove(&tx, path)?;
+    tx.execute(
+        "INSERT INTO index_files (path, mtime) VALUES (?1, ?2)",
+        params![path, mtime],
+    )?;
+
+    for source in sources {
+        let decl = source.analysis.ns.as_ref().unwrap();
+        tx.execute(
+            "INSERT INTO index_namespaces (name, path, entry, line, col, doc)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                decl.name,
+                path,
+                source.entry,
+                decl.pos.line as i64,
+                decl.pos.column as i64,
+                decl.doc
+            ],
+        )?;
+
+        for def in source.analysis.defs.iter() {
+            tx.execute(
+                "INSERT INTO index_vars (ns, name, kind, arglists, doc, private, path, entry, line, col)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    decl.name,
+                    def.name,
+                    def.kind,
+                    serde_json::to_string(&def.arglists)?,
+                    def.doc,
+                    def.private,
+                    path,
+                    source.entry,
+                    def.pos.line as i64,
+                    def.pos.column as i64
+                ],
+            )?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Indexes `path` with sources returned by `read` unless it's unchanged since the last time
+fn index_path(
+    conn: &mut Connection,
+    path: &Path,
+    read: impl FnOnce(&Path) -> Result<Vec<Source>, StdError>,
+) -> Result<Status, StdError> {
+    let path_str = path.to_string_lossy().to_string();
+    let mtime = mtime(path)?;
+    let indexed: Option<i64> = conn
+        .query_row(
+            "SELECT mtime FROM index_files WHERE path = ?1",
+            params![path_str],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    if indexed == Some(mtime) {
+        return Ok(Status::Unchanged);
+    }
+
+    store(conn, &path_str, mtime, &read(path)?)?;
+    Ok(Status::Indexed)
+}
+
+/// Indexes Clojure source file
+pub fn index_file(conn: &mut Connection, path: &Path) -> Result<Status, StdError> {
+    index_path(conn, path, |path| {
+        let path_str = path.to_string_lossy();
+        let source = std::fs::read_to_string(path).unwrap_or_default();
+
+        Ok(analyze(&source, &path_str)
+            .map(|analysis| Source {
+                entry: None,
+                analysis,
+            })
+            .into_iter()
+            .collect())
+    })
+}
+
+/// Indexes every Clojure source inside of JAR
+pub fn index_jar(conn: &mut Connection, path: &Path) -> Result<Status, StdError> {
+    index_path(conn, path, |path| {
+        let mut zip = zip::ZipArchive::new(File::open(path)?)?;
+        let mut sources = vec![];
+
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            let entry = file.name().to_string();
+            if !project::is_source_file(Path::new(&entry)) {
+                continue;
+            }
+
+            let mut source = String::new();
+            if file.read_to_string(&mut source).is_err() {
+                continue;
+            }
+            if let Some(analysis) = analyze(&source, &entry) {
+                sources.push(Source {
+                    entry: Some(entry),
+                    analysis,
+                });
+            }
+        }
+
+        Ok(sources)
+    })
+}
+
+/// Replaces files and JARs which belong to project in `root`
+pub fn set_root_paths(
+    conn: &mut Connection,
+    root: &str,
+    paths: &[PathBuf],
+) -> Result<(), StdError> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM index_roots WHERE root = ?1", params![root])?;
+
+    for path in paths {
+        tx.execute(
+            "INSERT OR IGNORE INTO index_roots (root, path) VALUES (?1, ?2)",
+            params![root, path.to_string_lossy()],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Forgets files and JARs which don't exist anymore, returns their number
+pub fn prune(conn: &Connection) -> Result<usize, StdError> {
+    let paths: Vec<String> = conn
+        .prepare("SELECT path FROM index_files")?
+        .query_map(NO_PARAMS, |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let missing: Vec<&String> = paths.iter().filter(|p| !Path::new(p).exists()).collect();
+
+    for path in missing.iter() {
+        remove(conn, path)?;
+        conn.execute("DELETE FROM index_roots WHERE path = ?1", params![path])?;
+    }
+
+    Ok(missing.len())
+}
+
+/// Tells if nothing was indexed yet
+pub fn is_empty(conn: &Connection) -> Result<bool, StdError> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM index_files", NO_PARAMS, |row| {
+        row.get(0)
+    })?;
+    Ok(count == 0)
+}
+
+// Lookups are limited to paths of project in `root`. Project files come first, JARs may have
+// older versions of the same namespaces.
+
+const IN_ROOT: &str = "path IN (SELECT path FROM index_roots WHERE root = ?1)";
+
+pub fn find_ns(conn: &Connection, root: &str, name: &str) -> Result<Option<IndexedNs>, StdError> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM index_namespaces WHERE {} AND name = ?2
+            ORDER BY entry IS NOT NULL LIMIT 1",
+            NS_COLUMNS, IN_ROOT
+        ),
+        params![root, name],
+        ns_from_row,
+    )
+    .optional()
+    .map_err(|e| e.into())
+}
+
+pub fn find_var(
+    conn: &Connection,
+    root: &str,
+    ns: &str,
+    name: &str,
+) -> Result<Option<IndexedVar>, StdError> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM index_vars WHERE {} AND ns = ?2 AND name = ?3
+            ORDER BY entry IS NOT NULL LIMIT 1",
+            VAR_COLUMNS, IN_ROOT
+        ),
+        params![root, ns, name],
+        var_from_row,
+    )
+    .optional()
+    .map_err(|e| e.into())
+}
+
+/// Vars of `ns` with names starting with `prefix`
+pub fn ns_vars(
+    conn: &Connection,
+    root: &str,
+    ns: &str,
+    prefix: &str,
+) -> Result<Vec<IndexedVar>, StdError> {
+    let mut vars: Vec<IndexedVar> = conn
+        .prepare(&format!(
+            "SELECT {} FROM index_vars WHERE {} AND ns = ?2 AND substr(name, 1, length(?3)) = ?3
+            ORDER BY name, entry IS NOT NULL",
+            VAR_COLUMNS, IN_ROOT
+        ))?
+        .query_map(params![root, ns, prefix], var_from_row)?
+        .collect::<Result<_, _>>()?;
+
+    vars.dedup_by(|a, b| a.name == b.name);
+    Ok(vars)
+}
+
+/// Names of namespaces starting with `prefix`
+pub fn ns_names(conn: &Connection, root: &str, prefix: &str) -> Result<Vec<String>, StdError> {
+    conn.prepare(&format!(
+        "SELECT DISTINCT name FROM index_namespaces WHERE {} AND substr(name, 1, length(?2)) = ?2
+        ORDER BY name",
+        IN_ROOT
+    ))?
+    .query_map(params![root, prefix], |row| row.get(0))?
+    .collect::<Result<_, _>>()
+    .map_err(|e| e.into())
+}
+
+/// Finds what `symbol` written in file of project in `root` with `analysis` stands for
+pub fn resolve(
+    conn: &Connection,
+    root: &str,
+    analysis: &Analysis,
+    symbol: &str,
+) -> Result<Option<Indexed>, StdError> {
+    let (ns, name) = analysis::split_symbol(symbol);
+
+    if let Some(var_ns) = analysis.resolve(ns, name) {
+        if let Some(var) = find_var(conn, root, &var_ns, name)? {
+            return Ok(Some(Indexed::Var(var)));
+        }
+    }
+
+    if ns.is_some() {
+        return Ok(None);
+    }
+
+    // Names referred with `:refer :all` are known only to the index
+    let requires = analysis.ns.iter().flat_map(|decl| decl.requires.iter());
+    for require in requires.filter(|r| r.refer == Some(Refer::All)) {
+        if let Some(var) = find_var(conn, root, &require.ns, name)? {
+            return Ok(Some(Indexed::Var(var)));
+        }
+    }
+
+    let ns = analysis
+        .ns
+        .as_ref()
+        .and_then(|decl| decl.resolve_alias(symbol))
+        .unwrap_or(symbol);
+
+    Ok(find_ns(conn, root, ns)?.map(Indexed::Ns))
+}
+
+fn candidate_type(kind: &str) -> &'static str {
+    match kind {
+        "defmacro" => "macro",
+        "defn" | "defn-" | "definline" | "defmulti" => "function",
+        _ => "var",
+    }
+}
+
+fn var_candidate(var: IndexedVar, qualifier: Option<&str>) -> ops::Candidate {
+    ops::Candidate {
+        candidate: match qualifier {
+            Some(q) => format!("{}/{}", q, var.name),
+            None => var.name,
+        },
+        candidate_type: Some(candidate_type(&var.kind).to_string()),
+        ns: Some(var.ns),
+        arglists: var.arglists,
+        doc: var.doc,
+    }
+}
+
+fn ns_candidate(name: &str, ns: &str) -> ops::Candidate {
+    ops::Candidate {
+        candidate: name.to_string(),
+        candidate_type: Some("namespace".to_string()),
+        ns: Some(ns.to_string()),
+        arglists: vec![],
+        doc: None,
+    }
+}
+
+/// Completes `prefix` in `ns` of file of project in `root` with `analysis`: its own vars, referred
+/// vars, aliases and namespaces. `alias/prefix` is completed with public vars of the namespace.
+pub fn complete(
+    conn: &Connection,
+    root: &str,
+    analysis: &Analysis,
+    ns: &str,
+    prefix: &str,
+) -> Result<Vec<ops::Candidate>, StdError> {
+    let decl = analysis.ns.as_ref();
+    let mut candidates = vec![];
+
+    // Unlike symbols, prefixes may end with `/`
+    if let Some((qualifier, name)) = prefix.rsplit_once('/').filter(|(q, _)| !q.is_empty()) {
+        let target = decl
+            .and_then(|d| d.resolve_alias(qualifier))
+            .unwrap_or(qualifier);
+
+        for var in ns_vars(conn, root, target, name)? {
+            if !var.private {
+                candidates.push(var_candidate(var, Some(qualifier)));
+            }
+        }
+        return Ok(candidates);
+    }
+
+    candidates.extend(
+        ns_vars(conn, root, ns, prefix)?
+            .into_iter()
+            .map(|var| var_candidate(var, None)),
+    );
+
+    for require in decl.iter().flat_map(|d| d.requires.iter()) {
+        let referred = |name: &str| match &require.refer {
+            Some(Refer::All) => true,
+            Some(Refer::Only(names)) => names.iter().any(|n| n == name),
+            None => false,
+        };
+
+        for var in ns_vars(conn, root, &require.ns, prefix)? {
+            if !var.private && referred(&var.name) {
+                candidates.push(var_candidate(var, None));
+            }
+        }
+
+        if let Some(alias) = require.alias.as_deref().filter(|a| a.starts_with(prefix)) {
+            candidates.push(ns_candidate(alias, &require.ns));
+        }
+    }
+
+    if ns != "clojure.core" {
+        for var in ns_vars(conn, root, "clojure.core", prefix)? {
+            if !var.private && analysis.is_core_referred(&var.name) {
+                candidates.push(var_candidate(var, None));
+            }
+        }
+    }
+
+    for name in ns_names(conn, root, prefix)? {
+        candidates.push(ns_candidate(&name, &name));
+    }
+
+    let mut seen = HashSet::new();
+    candidates.retain(|c| seen.insert(c.candidate.clone()));
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use std::io::Write;
+
+    #[test]
+    fn index_test() {
+        let dir = std::env::temp_dir().join(format!("unrepl-index-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let jar = dir.join("util.jar");
+        let mut zip = zip::ZipWriter::new(File::create(&jar).unwrap());
+        zip.start_file("foo/util.clj", Default::default()).unwrap();
+        zip.write_all(b"(ns foo.util)\n(defn helper \"Helps\" [x] x)\n(defn- hidden [])")
+            .unwrap();
+        zip.finish().unwrap();
+
+        let file = dir.join("core.clj");
+        let source = "(ns foo.core\n  (:require [foo.util :as u]))\n(defmacro m [& body])\n";
+        std::fs::write(&file, source).unwrap();
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        config::migrate(&mut conn).unwrap();
+        assert!(is_empty(&conn).unwrap());
+
+        assert_eq!(index_jar(&mut conn, &jar).unwrap(), Status::Indexed);
+        assert_eq!(index_file(&mut conn, &file).unwrap(), Status::Indexed);
+        assert_eq!(index_file(&mut conn, &file).unwrap(), Status::Unchanged);
+
+        // Another project with its own `foo.core`, which isn't seen from the first one
+        let other = dir.join("other").join("core.clj");
+        std::fs::create_dir_all(other.parent().unwrap()).unwrap();
+        std::fs::write(&other, "(ns foo.core)\n(defn other [])\n").unwrap();
+        assert_eq!(index_file(&mut conn, &other).unwrap(), Status::Indexed);
+
+        let root = dir.to_string_lossy().to_string();
+        set_root_paths(&mut conn, &root, &[file.clone(), jar.clone()]).unwrap();
+        set_root_paths(&mut conn, "other", &[other]).unwrap();
+
+        let analysis = analysis::analyze_source(source, "core.clj").unwrap();
+        let resolve = |symbol| resolve(&conn, &root, &analysis, symbol).unwrap();
+
+        match resolve("u/helper") {
+            Some(Indexed::Var(var)) => {
+                assert_eq!(var.entry.as_deref(), Some("foo/util.clj"));
+                assert_eq!((var.line, var.column), (2, 7));
+                assert_eq!(Indexed::Var(var).doc(), "foo.util/helper\n([x])\nHelps");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(
+            matches!(resolve("m"), Some(Indexed::Var(IndexedVar { ref kind, .. })) if kind == "defmacro")
+        );
+        assert!(
+            matches!(resolve("u"), Some(Indexed::Ns(IndexedNs { ref name, .. })) if name == "foo.util")
+        );
+        assert_eq!(resolve("missing"), None);
+        assert_eq!(resolve("other"), None);
+        assert_eq!(
+            find_ns(&conn, &root, "foo.core").unwrap().unwrap().path,
+            file.to_string_lossy()
+        );
+        assert!(find_var(&conn, "other", "foo.core", "other")
+            .unwrap()
+            .is_some());
+        assert_eq!(find_ns(&conn, "unknown", "foo.core").unwrap(), None);
+
+        let complete = |prefix| {
+            complete(&conn, &root, &analysis, "foo.core", prefix)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.candidate)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(complete("u/"), vec!["u/helper"]);
+        assert_eq!(complete("foo."), vec!["foo.core", "foo.util"]);
+        assert_eq!(complete("m"), vec!["m"]);
+
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(prune(&conn).unwrap(), 1);
+        assert_eq!(resolve("m"), None);
+
+        std::fs::remove_dir_all(&dir).unwrap_or_default();
+    }
+}
@@ -1,11 +1,22 @@
-use failure::Error;
+//! Helpers for dealing with JAR
+
+#![allow(non_local_definitions)]
+
+use crate::config;
+use failure::{Error as StdError, Fail};
 use std::fs::File;
 use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
-///! Helpers for dealing with JAR
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "JAR entry path points outside of JAR: {}", path)]
+    BadEntryPath { path: String },
+}
 
 /// Reads single file from JAR package
-pub fn read_jar_file(jar_path: String, file: String) -> Result<String, Error> {
+pub fn read_jar_file(jar_path: String, file: String) -> Result<String, StdError> {
     let mut out = String::new();
 
     let f = File::open(jar_path)?;
@@ -18,3 +29,100 @@ pub fn read_jar_file(jar_path: String, file: String) -> Result<String, Error> {
 
     Ok(out)
 }
+
+/// Directory with files extracted by `extract_cached`
+pub fn cache_path() -> PathBuf {
+    let mut path = config::config_path();
+    path.push("jars");
+    path
+}
+
+/// FNV-1a, unlike `DefaultHasher` it gives the same key in every build
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Name of cache directory for the current version of `jar`, new version of JAR gets a new one
+fn cache_key(jar: &Path) -> Result<String, StdError> {
+    let mtime = std::fs::metadata(jar)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)?;
+    let name = jar.file_stem().unwrap_or_default().to_string_lossy();
+    let key = format!("{}\0{}", jar.to_string_lossy(), mtime.as_nanos());
+
+    Ok(format!("{}-{:016x}", name, fnv1a(key.as_bytes())))
+}
+
+/// Entry paths come from JAR, so they must not escape the cache directory
+fn entry_path(file: &str) -> Result<PathBuf, Error> {
+    let path = Path::new(file.trim_start_matches('/'));
+
+    if path.components().all(|c| matches!(c, Component::Normal(_))) {
+        Ok(path.to_path_buf())
+    } else {
+        Err(Error::BadEntryPath {
+            path: file.to_string(),
+        })
+    }
+}
+
+/// Same as `extract_cached`, but extracts into `cache_dir`
+pub fn extract_to(cache_dir: &Path, jar: &str, file: &str) -> Result<PathBuf, StdError> {
+    let jar = Path::new(jar).canonicalize()?;
+    let path = cache_dir.join(cache_key(&jar)?).join(entry_path(file)?);
+
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let contents = read_jar_file(jar.to_string_lossy().to_string(), file.to_string())?;
+    std::fs::create_dir_all(path.parent().unwrap())?;
+
+    // Other process could be reading the same file, so it appears at once
+    let tmp_path = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&tmp_path, contents)?;
+    let mut perms = std::fs::metadata(&tmp_path)?.permissions();
+    perms.set_readonly(true);
+    std::fs::set_permissions(&tmp_path, perms)?;
+    std::fs::rename(&tmp_path, &path)?;
+
+    Ok(path)
+}
+
+/// Extracts `file` of `jar` into cache under `config_path()` and returns its path.
+/// Extracted file keeps its path inside of JAR and is reused until JAR is modified.
+pub fn extract_cached(jar: &str, file: &str) -> Result<PathBuf, StdError> {
+    extract_to(&cache_path(), jar, file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn extract_to_test() {
+        let dir = std::env::temp_dir().join(format!("unrepl-jar-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let jar = dir.join("foo.jar");
+        let mut zip = zip::ZipWriter::new(File::create(&jar).unwrap());
+        zip.start_file("foo/core.clj", Default::default()).unwrap();
+        zip.write_all(b"(ns foo.core)").unwrap();
+        zip.finish().unwrap();
+
+        let cache = dir.join("cache");
+        let jar = jar.to_string_lossy().to_string();
+        let path = extract_to(&cache, &jar, "foo/core.clj").unwrap();
+
+        assert!(path.starts_with(&cache));
+        assert!(path.ends_with("foo/core.clj"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "(ns foo.core)");
+        assert_eq!(extract_to(&cache, &jar, "/foo/core.clj").unwrap(), path);
+        assert!(extract_to(&cache, &jar, "../core.clj").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap_or_default();
+    }
+}
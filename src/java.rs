@@ -0,0 +1,277 @@
+//! Finds sources of Java classes: `-sources.jar` next to library JARs or `src.zip` of JDK
+
+use failure::Error as StdError;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Path of class source inside of sources JAR, nested classes live in the file of outer one
+pub fn source_entry(class: &str) -> String {
+    let outer = class.split('$').next().unwrap_or(class);
+    format!("{}.java", outer.replace('.', "/"))
+}
+
+/// `foo-1.0-sources.jar` for `foo-1.0.jar` when it exists
+pub fn sources_jar(jar: &Path) -> Option<PathBuf> {
+    let stem = jar.file_stem()?.to_string_lossy();
+    let path = jar.with_file_name(format!("{}-sources.jar", stem));
+
+    if path.exists() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// `src.zip` of JDK, its place differs between JDK 8 and later ones
+pub fn jdk_sources(java_home: &Path) -> Option<PathBuf> {
+    let candidates = vec![
+        java_home.join("lib").join("src.zip"),
+        java_home.join("src.zip"),
+        java_home.join("..").join("src.zip"),
+    ];
+
+    candidates.into_iter().find(|p| p.exists())
+}
+
+/// Finds `entry` in `zip`. JDK 9+ keeps sources in module directories, so
+/// `java/lang/String.java` is found as `java.base/java/lang/String.java`.
+pub fn find_entry(zip: &Path, entry: &str) -> Result<Option<String>, StdError> {
+    let archive = zip::ZipArchive::new(File::open(zip)?)?;
+    let suffix = format!("/{}", entry);
+    let mut names = archive.file_names();
+
+    Ok(names
+        .find(|name| *name == entry || name.ends_with(&suffix))
+        .map(|name| name.to_string()))
+}
+
+const MODIFIERS: [&str; 10] = [
+    "public",
+    "protected",
+    "private",
+    "static",
+    "final",
+    "abstract",
+    "synchronized",
+    "native",
+    "default",
+    "transient",
+];
+
+const TYPE_KINDS: [&str; 5] = ["class ", "interface ", "enum ", "record ", "@interface "];
+
+/// Tells if `line` looks like a declaration rather than a call or a comment. Lines right inside
+/// of a class or interface body are declarations too, like `int size();` or package-private
+/// members without modifiers.
+fn is_declaration(line: &str, in_type_body: bool) -> bool {
+    let first = line.split_whitespace().next().unwrap_or_default();
+    MODIFIERS.contains(&first) || first.starts_with('<') || in_type_body
+}
+
+/// Tracks braces to tell if lines are right inside of a type body rather than in a method body
+/// or an initializer. Braces in strings and comments are not told apart.
+#[derive(Default)]
+struct TypeBodies {
+    /// For each open brace, whether it opens a type body
+    braces: Vec<bool>,
+    /// Type header was seen, but its body wasn't opened yet
+    in_header: bool,
+}
+
+impl TypeBodies {
+    fn in_type_body(&self) -> bool {
+        self.braces.last() == Some(&true)
+    }
+
+    fn push_line(&mut self, line: &str) {
+        if TYPE_KINDS.iter().any(|k| line.contains(k)) {
+            self.in_header = true;
+        }
+
+        for c in line.chars() {
+            match c {
+                '{' => {
+                    self.braces.push(self.in_header);
+                    self.in_header = false;
+                }
+                '}' => {
+                    self.braces.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Column of `name` in `line` when it's a whole word and the rest of line is `followed`
+fn word_column(line: &str, name: &str, followed: impl Fn(&str) -> bool) -> Option<usize> {
+    let mut from = 0;
+
+    while let Some(i) = line[from..].find(name).map(|i| i + from) {
+        let before = line[..i].chars().next_back();
+
+        if !before.is_some_and(is_ident_char) && followed(&line[i + name.len()..]) {
+            return Some(line[..i].chars().count() + 1);
+        }
+        from = i + name.len();
+    }
+
+    None
+}
+
+/// Finds 1-based line and column of declaration of `member` or of the class itself.
+/// Constructors are members named after the class, as Clojure calls them `new`.
+pub fn find_declaration(source: &str, class: &str, member: Option<&str>) -> Option<(usize, usize)> {
+    let simple_class = class.rsplit(['.', '$']).next().unwrap_or(class);
+    let mut bodies = TypeBodies::default();
+
+    for (i, line) in source.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("//") || trimmed.starts_with('*') || trimmed.starts_with("/*") {
+            continue;
+        }
+
+        let in_type_body = bodies.in_type_body();
+        bodies.push_line(line);
+
+        let column = match member {
+            Some(member) if is_declaration(line, in_type_body) => {
+                let name = if member == "new" {
+                    simple_class
+                } else {
+                    member
+                };
+                word_column(line, name, |rest| {
+                    rest.trim_start().starts_with(['(', '=', ';', ','])
+                })
+            }
+            Some(_) => None,
+            None if TYPE_KINDS
+                .iter()
+                .any(|k| line.contains(&format!("{}{}", k, simple_class))) =>
+            {
+                word_column(line, simple_class, |rest| !rest.starts_with(is_ident_char))
+            }
+            None => None,
+        };
+
+        if let Some(column) = column {
+            return Some((i + 1, column));
+        }
+    }
+
+    None
+}
+
+/// Lines of declaration starting at 1-based `line` up to the end of its body, or up to `;`
+/// when it doesn't have one. Braces in strings and comments are not told apart.
+pub fn declaration_source(source: &str, line: usize) -> String {
+    let mut depth = 0;
+    let mut opened = false;
+    let mut lines = vec![];
+
+    for l in source.lines().skip(line.saturating_sub(1)) {
+        lines.push(l);
+
+        for c in l.chars() {
+            match c {
+                '{' => {
+                    depth += 1;
+                    opened = true;
+                }
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+
+        if (opened && depth <= 0) || (!opened && l.trim_end().ends_with(';')) {
+            break;
+        }
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+package java.lang;
+
+/**
+ * The {@code String} class represents character strings. Call getBytes() to...
+ */
+public final class String
+    implements java.io.Serializable, Comparable<String>, CharSequence {
+
+    private final byte[] value;
+
+    public String(byte[] bytes) {
+        this.value = bytes.getBytes();
+    }
+
+    public byte[] getBytes() {
+        return value;
+    }
+
+    public static final class Entry<K> {
+    }
+}
+"#;
+
+    const INTERFACE_SOURCE: &str = r#"
+package java.util;
+
+public interface List<E> extends Collection<E> {
+    int size();
+
+    default void sort(Comparator<? super E> c) {
+        Object[] a = this.toArray();
+        a.length;
+    }
+
+    E get(int index);
+}
+"#;
+
+    #[test]
+    fn source_entry_test() {
+        assert_eq!(source_entry("java.lang.String"), "java/lang/String.java");
+        assert_eq!(source_entry("java.util.Map$Entry"), "java/util/Map.java");
+    }
+
+    #[test]
+    fn find_declaration_test() {
+        let find = |class, member| find_declaration(SOURCE, class, member);
+
+        assert_eq!(find("java.lang.String", None), Some((7, 20)));
+        assert_eq!(find("java.lang.String", Some("getBytes")), Some((16, 19)));
+        assert_eq!(find("java.lang.String", Some("value")), Some((10, 26)));
+        assert_eq!(find("java.lang.String", Some("new")), Some((12, 12)));
+        assert_eq!(find("java.lang.String$Entry", None), Some((20, 31)));
+        assert_eq!(find("java.lang.String", Some("missing")), None);
+
+        let find = |member| find_declaration(INTERFACE_SOURCE, "java.util.List", Some(member));
+
+        assert_eq!(find("size"), Some((5, 9)));
+        assert_eq!(find("get"), Some((12, 7)));
+        assert_eq!(find("toArray"), None);
+    }
+
+    #[test]
+    fn declaration_source_test() {
+        assert_eq!(
+            declaration_source(SOURCE, 16),
+            "    public byte[] getBytes() {\n        return value;\n    }"
+        );
+        assert_eq!(
+            declaration_source(SOURCE, 10),
+            "    private final byte[] value;"
+        );
+    }
+}
@@ -1,5 +1,14 @@
-pub mod nrepl;
-pub mod cmd;
+pub mod analysis;
 pub mod bencode;
+pub mod cmd;
 pub mod config;
+pub mod index;
 pub mod jar;
+pub mod java;
+pub mod lint;
+pub mod location;
+pub mod nrepl;
+pub mod ns;
+pub mod project;
+pub mod reader;
+pub mod server;
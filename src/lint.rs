@@ -0,0 +1,356 @@
+//! Checks analyzed source for references which can't be resolved
+
+use crate::analysis::{self, Analysis, Usage};
+use crate::ns::Refer;
+use crate::reader::Pos;
+use failure::Error as StdError;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Knowledge about namespaces and vars loaded in running REPL
+pub trait Runtime {
+    /// Names of all loaded namespaces
+    fn ns_list(&mut self) -> Result<Vec<String>, StdError>;
+
+    /// Names of vars in `ns`, private vars are included when `private` is true
+    fn ns_vars(&mut self, ns: &str, private: bool) -> Result<Vec<String>, StdError>;
+
+    /// Namespace referred into every namespace, `cljs.core` in ClojureScript
+    fn core_ns(&self) -> &str {
+        "clojure.core"
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub pos: Pos,
+    pub message: String,
+}
+
+/// Caches runtime answers, so each namespace is asked for once
+struct Resolver<'a> {
+    runtime: &'a mut dyn Runtime,
+    loaded: Option<HashSet<String>>,
+    vars: HashMap<(String, bool), HashSet<String>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn is_loaded(&mut self, ns: &str) -> Result<bool, StdError> {
+        if self.loaded.is_none() {
+            self.loaded = Some(self.runtime.ns_list()?.into_iter().collect());
+        }
+        Ok(self.loaded.as_ref().unwrap().contains(ns))
+    }
+
+    fn has_var(&mut self, ns: &str, name: &str, private: bool) -> Result<bool, StdError> {
+        let key = (ns.to_string(), private);
+
+        if !self.vars.contains_key(&key) {
+            let vars = self.runtime.ns_vars(ns, private)?.into_iter().collect();
+            self.vars.insert(key.clone(), vars);
+        }
+        Ok(self.vars[&key].contains(name))
+    }
+}
+
+/// Java classes and interop forms: `String`, `java.io.File`, `.method`, `Class.`
+fn is_class_like(name: &str) -> bool {
+    name.starts_with('.')
+        || name.ends_with('.')
+        || name.contains('.')
+        || name.chars().next().is_some_and(|c| c.is_uppercase())
+}
+
+fn check_unqualified(
+    analysis: &Analysis,
+    r: &mut Resolver,
+    usage: &Usage,
+) -> Result<Option<String>, StdError> {
+    let name = usage.name.as_str();
+
+    if analysis::SPECIAL_FORMS.contains(&name)
+        || is_class_like(name)
+        || analysis.find_def(name).is_some()
+    {
+        return Ok(None);
+    }
+
+    let requires = analysis.ns.iter().flat_map(|decl| decl.requires.iter());
+    for require in requires {
+        match &require.refer {
+            Some(Refer::Only(names)) if names.iter().any(|n| n == name) => return Ok(None),
+            Some(Refer::All) if r.has_var(&require.ns, name, false)? => return Ok(None),
+            _ => {}
+        }
+    }
+
+    let core_ns = r.runtime.core_ns().to_string();
+    if analysis.is_core_referred(name) && r.has_var(&core_ns, name, false)? {
+        return Ok(None);
+    }
+
+    // Vars defined in REPL or by other files loaded into the namespace
+    if let Some(ns) = analysis.ns_name() {
+        if r.has_var(ns, name, true)? {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(format!("Undefined var: {}", name)))
+}
+
+fn check_qualified(
+    analysis: &Analysis,
+    r: &mut Resolver,
+    usage: &Usage,
+    qualifier: &str,
+) -> Result<Option<String>, StdError> {
+    let decl = analysis.ns.as_ref();
+    let alias_ns = decl.and_then(|d| d.resolve_alias(qualifier));
+
+    if usage.keyword {
+        return Ok(match alias_ns {
+            Some(_) => None,
+            None => Some(format!("Undefined namespace alias: {}", qualifier)),
+        });
+    }
+
+    let is_imported = decl.is_some_and(|d| {
+        d.imports
+            .iter()
+            .any(|i| i == qualifier || i.ends_with(&format!(".{}", qualifier)))
+    });
+    if is_class_like(qualifier) || is_imported || qualifier == "js" {
+        return Ok(None);
+    }
+
+    let ns = match alias_ns {
+        Some(ns) => ns.to_string(),
+        None => qualifier.to_string(),
+    };
+    let is_required = decl.is_some_and(|d| d.requires.iter().any(|r| r.ns == ns));
+    let is_current = analysis.ns_name() == Some(ns.as_str());
+
+    if alias_ns.is_none() && !is_required && !is_current && !r.is_loaded(&ns)? {
+        return Ok(Some(format!("Undefined namespace: {}", qualifier)));
+    }
+
+    // Vars of namespaces which are not loaded yet can't be checked
+    if !r.is_loaded(&ns)? {
+        return Ok(None);
+    }
+
+    if is_current && analysis.find_def(&usage.name).is_some()
+        || r.has_var(&ns, &usage.name, is_current)?
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(format!("Undefined var: {}", usage.full_name())))
+}
+
+/// Reports usages of vars and namespaces which are neither defined in analyzed source nor
+/// loaded in runtime. Syntax-quoted symbols are skipped, as they are resolved on expansion.
+pub fn undefined(
+    analysis: &Analysis,
+    runtime: &mut dyn Runtime,
+) -> Result<Vec<Diagnostic>, StdError> {
+    let mut r = Resolver {
+        runtime,
+        loaded: None,
+        vars: HashMap::new(),
+    };
+    let mut diagnostics = vec![];
+
+    for usage in analysis.usages.iter().filter(|u| !u.syntax_quoted) {
+        let message = match &usage.ns {
+            Some(qualifier) => check_qualified(analysis, &mut r, usage, qualifier)?,
+            None if usage.keyword => None,
+            None => check_unqualified(analysis, &mut r, usage)?,
+        };
+
+        if let Some(message) = message {
+            diagnostics.push(Diagnostic {
+                pos: usage.pos,
+                message,
+            });
+        }
+    }
+
+    Ok(diagnostics)
+}
+
+fn is_used(analysis: &Analysis, f: impl Fn(&Usage) -> bool) -> bool {
+    analysis.usages.iter().any(f)
+}
+
+/// Reports private vars, aliases and referred vars which are never used in analyzed source.
+/// It doesn't need runtime, so vars referred with `:refer :all` are not checked.
+pub fn unused(analysis: &Analysis) -> Vec<Diagnostic> {
+    let ns_name = analysis.ns_name();
+    let mut diagnostics = vec![];
+
+    for def in analysis.defs.iter().filter(|d| d.private) {
+        let used = is_used(analysis, |u| {
+            !u.keyword && u.name == def.name && (u.ns.is_none() || u.ns.as_deref() == ns_name)
+        });
+
+        if !used {
+            diagnostics.push(Diagnostic {
+                pos: def.pos,
+                message: format!("Unused private var: {}", def.name),
+            });
+        }
+    }
+
+    for require in analysis.ns.iter().flat_map(|decl| decl.requires.iter()) {
+        if let Some(alias) = &require.alias {
+            if !is_used(analysis, |u| u.ns.as_ref() == Some(alias)) {
+                diagnostics.push(Diagnostic {
+                    pos: require.pos,
+                    message: format!("Unused alias: {} ({})", alias, require.ns),
+                });
+            }
+        }
+
+        if let Some(Refer::Only(names)) = &require.refer {
+            for name in names {
+                if !is_used(analysis, |u| {
+                    !u.keyword && u.ns.is_none() && &u.name == name
+                }) {
+                    diagnostics.push(Diagnostic {
+                        pos: require.pos,
+                        message: format!("Unused refer: {} ({})", name, require.ns),
+                    });
+                }
+            }
+        }
+    }
+
+    diagnostics.sort_by_key(|d| d.pos);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runtime of Clojure or, with `cljs`, of ClojureScript
+    #[derive(Default)]
+    struct FakeRuntime {
+        cljs: bool,
+    }
+
+    impl Runtime for FakeRuntime {
+        fn ns_list(&mut self) -> Result<Vec<String>, StdError> {
+            Ok(
+                vec!["clojure.core", "cljs.core", "clojure.string", "foo.util"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            )
+        }
+
+        fn ns_vars(&mut self, ns: &str, _private: bool) -> Result<Vec<String>, StdError> {
+            let vars = match ns {
+                "clojure.core" => vec!["defn", "let", "inc", "map", "str"],
+                "cljs.core" => vec!["defn", "let", "inc", "str", "js-obj"],
+                "clojure.string" => vec!["join", "split"],
+                "foo.util" => vec!["helper"],
+                _ => vec![],
+            };
+            Ok(vars.into_iter().map(String::from).collect())
+        }
+
+        fn core_ns(&self) -> &str {
+            match self.cljs {
+                true => "cljs.core",
+                false => "clojure.core",
+            }
+        }
+    }
+
+    fn messages(src: &str) -> Vec<String> {
+        runtime_messages(src, &mut FakeRuntime::default())
+    }
+
+    fn runtime_messages(src: &str, runtime: &mut FakeRuntime) -> Vec<String> {
+        let analysis = analysis::analyze_source(src, "test.clj").unwrap();
+        undefined(&analysis, runtime)
+            .unwrap()
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn undefined_vars_test() {
+        let src = r#"
+(ns foo.core
+  (:require [clojure.string :as str :refer [split]]
+            [foo.util :refer :all]))
+
+(defn f [x]
+  (let [y (inc x)]
+    (str (str/join y) (split y) (helper x) (g x) (str/trim x) (String. "a"))))
+"#;
+
+        assert_eq!(
+            messages(src),
+            vec!["Undefined var: g", "Undefined var: str/trim"]
+        );
+    }
+
+    #[test]
+    fn cljs_core_test() {
+        let src = "(ns foo.core) (defn f [x] (js-obj \"a\" (map inc x)))";
+
+        assert_eq!(
+            runtime_messages(src, &mut FakeRuntime { cljs: true }),
+            vec!["Undefined var: map"]
+        );
+        assert_eq!(messages(src), vec!["Undefined var: js-obj"]);
+    }
+
+    #[test]
+    fn undefined_namespaces_test() {
+        let src = "(ns foo.core (:refer-clojure :exclude [map]))
+                   (bar/baz (map inc [1]) ::u/kw Math/PI (clojure.string/join []))";
+
+        assert_eq!(
+            messages(src),
+            vec![
+                "Undefined namespace: bar",
+                "Undefined var: map",
+                "Undefined namespace alias: u"
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_test() {
+        let src = r#"
+(ns foo.core
+  (:require [clojure.string :as str :refer [split join]]
+            [clojure.set :as set]
+            [foo.util :refer :all]))
+
+(defn- helper [x] (split x #","))
+(def ^:private secret 1)
+(defn f [] (foo.core/secret) (helper ::set/kw))
+"#;
+        let analysis = analysis::analyze_source(src, "test.clj").unwrap();
+        let diagnostics = unused(&analysis)
+            .into_iter()
+            .map(|d| (d.pos.line, d.message))
+            .collect::<Vec<(usize, String)>>();
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                (3, "Unused alias: str (clojure.string)".to_string()),
+                (3, "Unused refer: join (clojure.string)".to_string()),
+            ]
+        );
+    }
+}
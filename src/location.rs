@@ -0,0 +1,251 @@
+//! Locations of source files as nrepl reports them in `file` of `info` responses
+
+#![allow(non_local_definitions)]
+
+use failure::Fail;
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum Error {
+    #[fail(display = "Location is empty")]
+    Empty,
+    #[fail(display = "Unsupported location scheme {}: {}", scheme, location)]
+    UnsupportedScheme { scheme: String, location: String },
+    #[fail(display = "JAR location doesn't have entry path: {}", location)]
+    NoJarEntry { location: String },
+    #[fail(display = "Bad percent-encoding in location: {}", location)]
+    BadPercentEncoding { location: String },
+}
+
+/// Source file on disk or inside of JAR
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    /// Absolute path or a path relative to the project, like `resource` values
+    File(String),
+    Jar {
+        jar: String,
+        entry: String,
+    },
+}
+
+/// Scheme of `s` when it's an URL, one letter schemes are Windows drives
+fn scheme(s: &str) -> Option<&str> {
+    let (scheme, _) = s.split_once(':')?;
+    let mut chars = scheme.chars();
+    let is_scheme = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+
+    if is_scheme && scheme.len() > 1 {
+        Some(scheme)
+    } else {
+        None
+    }
+}
+
+fn hex_digit(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+/// Decodes `%XX` sequences of URL
+pub fn percent_decode(s: &str) -> Result<String, Error> {
+    let bad = || Error::BadPercentEncoding {
+        location: s.to_string(),
+    };
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hi = bytes
+                .get(i + 1)
+                .copied()
+                .and_then(hex_digit)
+                .ok_or_else(bad)?;
+            let lo = bytes
+                .get(i + 2)
+                .copied()
+                .and_then(hex_digit)
+                .ok_or_else(bad)?;
+            out.push(hi << 4 | lo);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(out).map_err(|_| bad())
+}
+
+/// Encodes `path` as `file://` URL
+pub fn file_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+
+    uri
+}
+
+/// Path of `file:` URL: `file:/a`, `file:///a` and `file://localhost/a` are all `/a`
+fn file_url_path(url: &str, location: &str) -> Result<String, Error> {
+    let path = url.strip_prefix("file:").unwrap();
+    let path = match path.strip_prefix("//") {
+        Some(rest) => &rest[rest.find('/').unwrap_or(rest.len())..],
+        None => path,
+    };
+
+    let path = percent_decode(path).map_err(|_| Error::BadPercentEncoding {
+        location: location.to_string(),
+    })?;
+
+    // `file:/C:/Users` on Windows
+    match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => Ok(path[1..].to_string()),
+        _ => Ok(path),
+    }
+}
+
+impl Location {
+    /// Parses `file:`, `jar:file:...!/...` and `zip:...!/...` URLs or a bare path
+    pub fn parse(location: &str) -> Result<Self, Error> {
+        let location = location.trim();
+        if location.is_empty() {
+            return Err(Error::Empty);
+        }
+
+        match scheme(location) {
+            None => Ok(Self::File(location.to_string())),
+            Some("file") => Ok(Self::File(file_url_path(location, location)?)),
+            Some("jar") | Some("zip") => {
+                let url = location.split_once(':').unwrap().1;
+                let no_entry = || Error::NoJarEntry {
+                    location: location.to_string(),
+                };
+                let (jar, entry) = url
+                    .split_once("!/")
+                    .or_else(|| url.split_once('!'))
+                    .ok_or_else(no_entry)?;
+                let entry = percent_decode(entry.trim_start_matches('/')).map_err(|_| {
+                    Error::BadPercentEncoding {
+                        location: location.to_string(),
+                    }
+                })?;
+
+                if entry.is_empty() {
+                    return Err(no_entry());
+                }
+
+                // `zip:` locations have bare jar paths
+                let jar = match scheme(jar) {
+                    Some("file") => file_url_path(jar, location)?,
+                    None => jar.to_string(),
+                    Some(scheme) => {
+                        return Err(Error::UnsupportedScheme {
+                            scheme: scheme.to_string(),
+                            location: location.to_string(),
+                        })
+                    }
+                };
+
+                Ok(Self::Jar { jar, entry })
+            }
+            Some(scheme) => Err(Error::UnsupportedScheme {
+                scheme: scheme.to_string(),
+                location: location.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jar(jar: &str, entry: &str) -> Location {
+        Location::Jar {
+            jar: jar.to_string(),
+            entry: entry.to_string(),
+        }
+    }
+
+    fn file(path: &str) -> Location {
+        Location::File(path.to_string())
+    }
+
+    #[test]
+    fn parse_test() {
+        let cases = vec![
+            (
+                "file:/home/me/proj/src/foo/core.clj",
+                file("/home/me/proj/src/foo/core.clj"),
+            ),
+            (
+                "file:///home/me/my%20proj/src/foo/core.clj",
+                file("/home/me/my proj/src/foo/core.clj"),
+            ),
+            ("file://localhost/tmp/a.clj", file("/tmp/a.clj")),
+            ("file:/C:/Users/me/core.clj", file("C:/Users/me/core.clj")),
+            (
+                "jar:file:/home/me/.m2/repository/org/clojure/clojure/1.10.1/clojure-1.10.1.jar!/clojure/core.clj",
+                jar(
+                    "/home/me/.m2/repository/org/clojure/clojure/1.10.1/clojure-1.10.1.jar",
+                    "clojure/core.clj",
+                ),
+            ),
+            (
+                "jar:file:/home/me/.m2/repository/a%2Bb/x.jar!/a/b%20c.clj",
+                jar("/home/me/.m2/repository/a+b/x.jar", "a/b c.clj"),
+            ),
+            (
+                "zip:/home/me/.m2/repository/x.jar!/x/core.clj",
+                jar("/home/me/.m2/repository/x.jar", "x/core.clj"),
+            ),
+            (
+                "jar:file:/tmp/a!b/x.jar!/x/core.clj",
+                jar("/tmp/a!b/x.jar", "x/core.clj"),
+            ),
+            ("/tmp/a:b/core.clj", file("/tmp/a:b/core.clj")),
+            ("foo/core.clj", file("foo/core.clj")),
+            ("C:\\src\\core.clj", file("C:\\src\\core.clj")),
+        ];
+
+        for (location, expected) in cases {
+            assert_eq!(Location::parse(location), Ok(expected), "{}", location);
+        }
+    }
+
+    #[test]
+    fn parse_errors_test() {
+        assert_eq!(Location::parse(" "), Err(Error::Empty));
+        assert!(matches!(
+            Location::parse("jar:file:/x.jar"),
+            Err(Error::NoJarEntry { .. })
+        ));
+        assert!(matches!(
+            Location::parse("jar:file:/x.jar!/"),
+            Err(Error::NoJarEntry { .. })
+        ));
+        assert!(matches!(
+            Location::parse("file:/a%2"),
+            Err(Error::BadPercentEncoding { .. })
+        ));
+        assert!(matches!(
+            Location::parse("http://example.com/core.clj"),
+            Err(Error::UnsupportedScheme { .. })
+        ));
+    }
+
+    #[test]
+    fn file_uri_test() {
+        let path = "/home/me/my proj/core.clj";
+
+        assert_eq!(file_uri(path), "file:///home/me/my%20proj/core.clj");
+        assert_eq!(Location::parse(&file_uri(path)), Ok(file(path)));
+    }
+}
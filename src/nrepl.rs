@@ -1,7 +1,12 @@
+#![allow(non_local_definitions)]
+
 pub mod ops;
+pub mod port;
 pub mod session;
+pub mod transport;
 
 use crate::bencode;
+use crate::config::{Session, SessionKind};
 use failure::Fail;
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Serialize, Serializer};
@@ -9,10 +14,14 @@ use serde_bencode::value::Value as BencodeValue;
 use std::collections::HashMap;
 use std::convert::{From, Into, TryFrom};
 use std::fmt;
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufReader, Read, Write};
 use std::iter::FromIterator;
-use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
+use transport::{Addr, Transport};
 
 #[derive(Debug, Fail)]
 pub enum Error {
@@ -28,6 +37,10 @@ pub enum Error {
     BencodeFormatError(RespError),
     #[fail(display = "Nrepl returned unsuccessful status: {}", status)]
     ResponseStatusError { status: String },
+    #[fail(display = "nrepl connection is closed")]
+    ConnectionClosed,
+    #[fail(display = "timed out waiting for response to op `{}`", id)]
+    Timeout { id: String },
 }
 
 #[derive(Debug)]
@@ -37,6 +50,8 @@ pub enum Status {
     NoInfo(Vec<Resp>),
     UnknownOp(String, Vec<Resp>),
     EvalError(Vec<Resp>),
+    Interrupted(Vec<Resp>),
+    SessionIdle(Vec<Resp>),
     UnknownStatus(Vec<String>, Vec<Resp>),
 }
 
@@ -47,6 +62,8 @@ impl Status {
             Self::State(_) => "state (cider.nrepl.middleware.track-state)".to_string(),
             Self::NoInfo(_) => "no-info".to_string(),
             Self::EvalError(_) => "eval-error".to_string(),
+            Self::Interrupted(_) => "interrupted".to_string(),
+            Self::SessionIdle(_) => "session-idle".to_string(),
             Self::UnknownStatus(statuses, _) => statuses.join(","),
             Self::UnknownOp(_op, _) => "Unknown Op".to_string(),
         }
@@ -58,6 +75,8 @@ impl Status {
             Self::State(resps) => resps,
             Self::NoInfo(resps) => resps,
             Self::EvalError(resps) => resps,
+            Self::Interrupted(resps) => resps,
+            Self::SessionIdle(resps) => resps,
             Self::UnknownStatus(_, resps) => resps,
             Self::UnknownOp(_, resps) => resps,
         }
@@ -86,11 +105,55 @@ impl From<RespError> for Error {
 pub struct Op {
     name: String,
     args: Vec<(String, String)>,
+    value_args: Vec<(String, BencodeValue)>,
+    id: Option<String>,
+    timeout: Option<Duration>,
 }
 
 impl Op {
     pub fn new(name: String, args: Vec<(String, String)>) -> Op {
-        Op { name, args }
+        Op {
+            name,
+            args,
+            value_args: vec![],
+            id: None,
+            timeout: Some(Duration::new(5, 0)),
+        }
+    }
+
+    /// Sets `id` upfront, when caller has to know it (e.g. for interrupting), otherwise `id` is
+    /// assigned by `NreplStream` right before the op is sent
+    pub fn with_id(mut self, id: String) -> Op {
+        self.id = Some(id);
+        self
+    }
+
+    /// How long to wait for each response, `None` means waiting until nrepl answers
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Op {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Adds an argument which nrepl expects to be an integer, like `line` of `eval`
+    pub fn int_arg(mut self, name: &str, value: i64) -> Op {
+        self.value_args
+            .push((name.to_string(), BencodeValue::Int(value)));
+        self
+    }
+
+    /// Adds an argument which nrepl expects to be a list of strings
+    pub fn list_arg(mut self, name: &str, values: Vec<String>) -> Op {
+        let values = values
+            .into_iter()
+            .map(|v| BencodeValue::Bytes(v.into_bytes()))
+            .collect();
+        self.value_args
+            .push((name.to_string(), BencodeValue::List(values)));
+        self
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
     }
 }
 
@@ -99,11 +162,19 @@ impl Serialize for Op {
     where
         S: Serializer,
     {
-        let mut state = s.serialize_map(Some(1 + self.args.len()))?;
+        let mut state = s.serialize_map(None)?;
 
         state.serialize_entry("op", &self.name)?;
 
-        for (k, v) in self.args.iter() {
+        if let Some(id) = &self.id {
+            state.serialize_entry("id", id)?;
+        }
+
+        for (k, v) in self.args.iter().filter(|(k, _)| k != "id") {
+            state.serialize_entry(k, v)?;
+        }
+
+        for (k, v) in self.value_args.iter() {
             state.serialize_entry(k, v)?;
         }
 
@@ -175,7 +246,7 @@ impl TryFrom<BencodeValue> for Resp {
             BencodeValue::Dict(map) => {
                 let pairs = map
                     .into_iter()
-                    .map(|(k, v)| (String::from_utf8(k).unwrap(), TryFrom::try_from(v).unwrap()));
+                    .map(|(k, v)| (String::from_utf8(k).unwrap(), v));
                 Ok(Self(HashMap::from_iter(pairs)))
             }
             v => Err(Self::Error::ExpectedMap(v)),
@@ -183,142 +254,291 @@ impl TryFrom<BencodeValue> for Resp {
     }
 }
 
+const NEED_INPUT: &str = "need-input";
+
 fn is_final_resp(resp: &Resp) -> bool {
     resp.contains_key("status")
 }
 
 fn get_status(resp: &Resp) -> Option<Vec<String>> {
-    if let Some(status) = resp.get("status") {
-        Some(bencode::try_into_str_vec(status.clone()).unwrap())
+    resp.get("status")
+        .map(|status| bencode::try_into_str_vec(status.clone()).unwrap())
+}
+
+fn is_done_resp(resp: &Resp) -> bool {
+    get_status(resp).is_some_and(|status| status.iter().any(|s| s == "done"))
+}
+
+/// Evaluated code reads `*in*`, nrepl waits for `stdin` op before it goes on
+fn is_need_input_resp(resp: &Resp) -> bool {
+    get_status(resp).is_some_and(|status| status.iter().any(|s| s == NEED_INPUT))
+}
+
+fn parse_resps(resps: Vec<Resp>) -> Result<Status, Error> {
+    let status: Vec<String> = resps
+        .iter()
+        .filter(|resp| is_final_resp(resp))
+        .flat_map(|resp| get_status(resp).unwrap())
+        .filter(|s| s != NEED_INPUT)
+        .collect();
+    let has_status = |name: &str| status.iter().any(|s| s == name);
+
+    if status.is_empty() {
+        unreachable!()
+    } else if has_status("unknown-op") {
+        Ok(Status::UnknownOp(status.join(","), resps))
+    } else if has_status("interrupted") {
+        Ok(Status::Interrupted(resps))
+    } else if has_status("session-idle") {
+        Ok(Status::SessionIdle(resps))
+    } else if has_status("eval-error") {
+        Ok(Status::EvalError(resps))
+    } else if has_status("no-info") {
+        Ok(Status::NoInfo(resps))
+    } else if has_status("state") {
+        Ok(Status::State(resps))
+    } else if status.iter().all(|s| s == "done") {
+        Ok(Status::Done(resps))
     } else {
-        None
+        Ok(Status::UnknownStatus(status, resps))
     }
 }
 
-fn parse_resps(resps: Vec<Resp>) -> Result<Status, Error> {
-    for resp in resps.iter() {
-        if is_final_resp(&resp) {
-            let status = get_status(&resp).unwrap();
-
-            if status == ["done"] {
-                return Ok(Status::Done(resps));
-            } else if status == ["state"] {
-                return Ok(Status::State(resps));
-            } else if status == ["eval-error"] {
-                return Ok(Status::EvalError(resps));
-            } else if status == ["done", "no-info"] {
-                return Ok(Status::NoInfo(resps));
-            } else if status.iter().any(|s| s == "unknown-op") {
-                return Ok(Status::UnknownOp(status.join(","), resps));
-            } else {
-                return Ok(Status::UnknownStatus(status, resps));
+/// Reader adapter that keeps reading until the whole buffer is filled
+///
+/// `serde_bencode` expects a single `read` call to return a whole bencode string, which doesn't
+/// hold for sockets when the string is bigger than a single TCP segment
+struct FullReader<R: Read>(R);
+
+impl<R: Read> Read for FullReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            match self.0.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
             }
         }
+
+        Ok(filled)
     }
-    unreachable!()
 }
 
+type Pending = Arc<Mutex<HashMap<String, Sender<Resp>>>>;
+
 /// It is responsible for communication with nrepl bencode socket
 ///
-/// 2020-03-24 Decided to open tcp stream for each OP because it proved to work more reliable
-/// But for sure there are some problems on "nrepl" side
-
+/// Single connection is shared by all ops: every outgoing op is tagged with unique `id` and a
+/// background thread routes incoming responses back to the caller waiting for that `id`
 pub struct NreplStream {
-    // tcp: TcpStream,
-    socket_addr: SocketAddr,
+    addr: Addr,
+    writer: Mutex<Transport>,
+    pending: Pending,
+    closed: Arc<AtomicBool>,
+    next_id: AtomicUsize,
+    /// Sessions known to be alive, so long living processes don't check them for each op
+    sessions: Mutex<HashMap<SessionKind, Session>>,
 }
 
 impl NreplStream {
-    // pub fn connect_timeout(addr: &SocketAddr) -> Result<NreplStream, Error> {
-    //     let conn = TcpStream::connect_timeout(addr, Duration::new(3, 0))
-    //         .and_then(|t| {
-    //             t.set_nonblocking(false)?;
-    //             t.set_read_timeout(Some(Duration::new(5, 0)))?;
-    //             Ok(t)
-    //         })
-    //         .map(|s| NreplStream {
-    //             tcp: s,
-    //             socket_addr: addr.clone(),
-    //         })?;
-    //     Ok(conn)
-    // }
-
-    pub fn new(addr: &SocketAddr) -> Result<NreplStream, Error> {
+    pub fn new(addr: &Addr) -> Result<NreplStream, Error> {
+        let transport = Transport::connect(addr)?;
+
+        let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+        let reader = transport.try_clone()?;
+        let closed = Arc::new(AtomicBool::new(false));
+        let (reader_pending, reader_closed) = (pending.clone(), closed.clone());
+
+        thread::spawn(move || read_loop(reader, reader_pending, reader_closed));
+
         Ok(NreplStream {
-            socket_addr: addr.clone()
+            addr: addr.clone(),
+            writer: Mutex::new(transport),
+            pending,
+            closed,
+            next_id: AtomicUsize::new(1),
+            sessions: Mutex::new(HashMap::new()),
         })
     }
 
-    fn socket_timeout(&self) -> Result<TcpStream, Error> {
-        TcpStream::connect_timeout(&self.socket_addr, Duration::new(3, 0))
-            .and_then(|t| {
-                t.set_nonblocking(false)?;
-                t.set_read_timeout(Some(Duration::new(5, 0)))?;
-                Ok(t)
-            })
-            .map_err(|e| e.into())
+    /// Generates `id` for an op which is unique across processes talking to the same nrepl
+    pub fn gen_id(&self) -> String {
+        format!(
+            "unrepl-{}-{}",
+            std::process::id(),
+            self.next_id.fetch_add(1, Ordering::SeqCst)
+        )
     }
 
-    fn send_op<T: Into<Op>>(&self, tcp: &TcpStream, op: T) -> Result<(), Error> {
-        let mut bw = BufWriter::new(tcp);
-        let bencode = serde_bencode::to_bytes(&op.into())?;
-        bw.write(&bencode)?;
+    fn send_op(&self, op: &Op) -> Result<(), Error> {
+        let bencode = serde_bencode::to_bytes(op)?;
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_all(&bencode)?;
+        writer.flush()?;
         Ok(())
     }
 
-    fn read_resp(&self, tcp: &TcpStream) -> Result<Resp, Error> {
-        let mut r = BufReader::new(tcp);
+    /// Serializes given `op` and sends it to Nrepl, waits for all responses having the op's `id`
+    pub fn op<T: Into<Op>>(&self, op: T) -> Result<Status, Error> {
+        self.op_stream(op, |_| {})
+    }
 
-        let mut deser = serde_bencode::de::Deserializer::new(&mut r);
+    /// Same as `op`, but calls `on_resp` for each response as soon as it arrives,
+    /// useful for showing `out`/`err` of long running ops. Reading of `*in*` gets end of input
+    pub fn op_stream<T, F>(&self, op: T, on_resp: F) -> Result<Status, Error>
+    where
+        T: Into<Op>,
+        F: FnMut(&Resp),
+    {
+        self.op_stream_input(op, on_resp, || None)
+    }
 
-        let val: BencodeValue = serde::Deserialize::deserialize(&mut deser)?;
+    /// Same as `op_stream`, but `need-input` is answered with the text `on_input` reads,
+    /// `None` is the end of input
+    pub fn op_stream_input<T, F, I>(
+        &self,
+        op: T,
+        mut on_resp: F,
+        mut on_input: I,
+    ) -> Result<Status, Error>
+    where
+        T: Into<Op>,
+        F: FnMut(&Resp),
+        I: FnMut() -> Option<String>,
+    {
+        let mut op = op.into();
+        let id = op.id.clone().unwrap_or_else(|| self.gen_id());
+        op.id = Some(id.clone());
 
-        Ok(TryFrom::try_from(val)?)
-    }
+        let (tx, rx) = mpsc::channel();
+        self.pending.lock().unwrap().insert(id.clone(), tx);
 
-    /// Serializes given `op` and sends to Nrepl socket using given transport
-    pub fn op<T: Into<Op>>(&self, op: T) -> Result<Status, Error> {
-        let mut resps: Vec<Resp> = vec![];
+        if self.is_closed() {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(Error::ConnectionClosed);
+        }
 
-        let tcp = self.socket_timeout()?;
+        let res = self.send_op(&op).and_then(|_| {
+            let mut resps: Vec<Resp> = vec![];
+
+            loop {
+                let resp = match op.timeout {
+                    Some(timeout) => rx.recv_timeout(timeout).map_err(|e| match e {
+                        RecvTimeoutError::Timeout => Error::Timeout { id: id.clone() },
+                        RecvTimeoutError::Disconnected => Error::ConnectionClosed,
+                    })?,
+                    None => rx.recv().map_err(|_| Error::ConnectionClosed)?,
+                };
+                // `eval-error` status comes before `err` output, so wait for `done`
+                let is_final = is_done_resp(&resp);
+
+                on_resp(&resp);
+                if is_need_input_resp(&resp) {
+                    self.send_input(&resp, on_input())?;
+                }
+                resps.push(resp);
 
-        self.send_op(&tcp, op.into())?;
+                if is_final {
+                    return Ok(resps);
+                }
+            }
+        });
 
-        loop {
-            let resp = self.read_resp(&tcp)?;
-            let is_final = is_final_resp(&resp);
+        self.pending.lock().unwrap().remove(&id);
 
-            resps.push(resp);
+        parse_resps(res?)
+    }
 
-            if is_final {
-                break;
-            }
+    /// Sends `stdin` op to the session of `resp`, empty text is the end of input. Its answer
+    /// isn't awaited, so it's dropped by the reader
+    fn send_input(&self, resp: &Resp, input: Option<String>) -> Result<(), Error> {
+        let mut args = vec![("stdin".to_string(), input.unwrap_or_default())];
+
+        if let Some(session) = resp
+            .get("session")
+            .cloned()
+            .and_then(|s| bencode::try_into_string(s).ok())
+        {
+            args.push(("session".to_string(), session));
         }
 
-        parse_resps(resps)
+        self.send_op(&Op::new("stdin".to_string(), args).with_id(self.gen_id()))
+    }
+
+    /// Tells if the connection was closed by nrepl, after that all ops fail
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    pub fn addr(&self) -> &Addr {
+        &self.addr
     }
 
+    /// URI of nrepl, sessions and settings are stored for it
     pub fn addr_string(&self) -> String {
-        self.socket_addr.to_string()
+        self.addr.to_string()
     }
-}
 
-pub trait NreplOp<T> {
-    type Error;
+    pub fn cached_session(&self, kind: SessionKind) -> Option<Session> {
+        self.sessions.lock().unwrap().get(&kind).cloned()
+    }
 
-    fn send(&self, nrepl: &NreplStream) -> Result<T, Self::Error>;
+    pub fn cache_session(&self, session: Session) {
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session.kind(), session);
+    }
 }
 
-/// Check if there's already a port file written by `lein repl` for example
-pub fn default_nrepl_port() -> Option<u32> {
-    std::fs::read_to_string(".nrepl-port")
-        .ok()
-        .and_then(|s| s.parse::<u32>().ok())
+/// Reader thread holds a clone of the connection, so it's closed explicitly, which also ends
+/// the thread
+impl Drop for NreplStream {
+    fn drop(&mut self) {
+        let writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writer.shutdown().unwrap_or_default();
+    }
+}
+
+/// Reads responses from the connection until it gets closed and routes them by `id`
+fn read_loop(transport: Transport, pending: Pending, closed: Arc<AtomicBool>) {
+    let mut deser = serde_bencode::de::Deserializer::new(FullReader(BufReader::new(transport)));
+
+    loop {
+        let resp = BencodeValue::deserialize(&mut deser)
+            .map_err(Error::from)
+            .and_then(|val| Resp::try_from(val).map_err(Error::from));
+
+        match resp {
+            Ok(resp) => {
+                let id = resp
+                    .get("id")
+                    .cloned()
+                    .and_then(|id| bencode::try_into_string(id).ok());
+
+                if let Some(id) = id {
+                    if let Some(tx) = pending.lock().unwrap().get(&id) {
+                        let _ = tx.send(resp);
+                    }
+                }
+            }
+            // Dropping senders wakes up everyone who waits for a response
+            Err(_) => {
+                closed.store(true, Ordering::SeqCst);
+                pending.lock().unwrap().clear();
+                return;
+            }
+        }
+    }
 }
 
-/// Returns address of nrepl socket
-pub fn port_addr(port: u32) -> SocketAddr {
-    format!("127.0.0.1:{}", port).parse().unwrap()
+pub trait NreplOp<T> {
+    type Error;
+
+    fn send(&self, nrepl: &NreplStream) -> Result<T, Self::Error>;
 }
 
 #[cfg(test)]
@@ -329,6 +549,7 @@ mod tests {
     use std::iter::FromIterator;
 
     #[test]
+    #[allow(clippy::useless_conversion)]
     fn final_resp_test() {
         let final_resp = Resp(HashMap::from_iter(
             vec![("status".to_string(), BencodeValue::Bytes(vec![]))].into_iter(),
@@ -341,4 +562,140 @@ mod tests {
         assert!(is_final_resp(&final_resp));
         assert!(!is_final_resp(&not_final_resp));
     }
+
+    #[test]
+    fn op_serializes_id_test() {
+        let mut op = Op::new(
+            "eval".to_string(),
+            vec![
+                ("code".to_string(), "1".to_string()),
+                ("id".to_string(), "ignored".to_string()),
+            ],
+        );
+        op.id = Some("42".to_string());
+
+        assert_eq!(
+            String::from_utf8(serde_bencode::to_bytes(&op).unwrap()).unwrap(),
+            "d4:code1:12:id2:422:op4:evale"
+        );
+    }
+
+    #[test]
+    fn routes_responses_by_id_test() {
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = Addr::tcp("127.0.0.1", listener.local_addr().unwrap().port());
+
+        // Fake server answers the second op first, then the first one
+        thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let mut deser =
+                serde_bencode::de::Deserializer::new(FullReader(tcp.try_clone().unwrap()));
+            let mut ids = vec![];
+
+            for _ in 0..2 {
+                let op: HashMap<String, String> = Deserialize::deserialize(&mut deser).unwrap();
+                ids.push(op["id"].clone());
+            }
+
+            let mut tcp = tcp;
+            for id in ids.iter().rev() {
+                let mut resp = HashMap::new();
+                resp.insert("id", BencodeValue::Bytes(id.clone().into_bytes()));
+                resp.insert("value", BencodeValue::Bytes(id.clone().into_bytes()));
+                resp.insert(
+                    "status",
+                    BencodeValue::List(vec![BencodeValue::Bytes(b"done".to_vec())]),
+                );
+                tcp.write_all(&serde_bencode::to_bytes(&resp).unwrap())
+                    .unwrap();
+            }
+        });
+
+        let n = Arc::new(NreplStream::new(&addr).unwrap());
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let n = n.clone();
+                thread::spawn(move || n.op(Op::new("eval".to_string(), vec![])).unwrap())
+            })
+            .collect();
+
+        for h in handles {
+            for resp in h.join().unwrap().into_resps() {
+                assert_eq!(resp.get("id"), resp.get("value"));
+            }
+        }
+    }
+
+    #[test]
+    fn need_input_test() {
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = Addr::tcp("127.0.0.1", listener.local_addr().unwrap().port());
+
+        // Fake server asks for input and echoes it as the value
+        thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let mut deser =
+                serde_bencode::de::Deserializer::new(FullReader(tcp.try_clone().unwrap()));
+            let mut tcp = tcp;
+            let status = |s: &str| BencodeValue::List(vec![BencodeValue::Bytes(s.into())]);
+            let mut send = |resp: HashMap<&str, BencodeValue>| {
+                tcp.write_all(&serde_bencode::to_bytes(&resp).unwrap())
+                    .unwrap()
+            };
+
+            let op: HashMap<String, String> = Deserialize::deserialize(&mut deser).unwrap();
+            let id = BencodeValue::Bytes(op["id"].clone().into_bytes());
+            send(HashMap::from([
+                ("id", id.clone()),
+                ("status", status(NEED_INPUT)),
+            ]));
+
+            let stdin: HashMap<String, String> = Deserialize::deserialize(&mut deser).unwrap();
+            assert_eq!(stdin["op"], "stdin");
+            send(HashMap::from([
+                ("id", id.clone()),
+                (
+                    "value",
+                    BencodeValue::Bytes(stdin["stdin"].clone().into_bytes()),
+                ),
+            ]));
+            send(HashMap::from([("id", id), ("status", status("done"))]));
+        });
+
+        let n = NreplStream::new(&addr).unwrap();
+        let status = n
+            .op_stream_input(
+                Op::new("eval".to_string(), vec![]),
+                |_| {},
+                || Some("hi\n".to_string()),
+            )
+            .unwrap();
+
+        match status {
+            Status::Done(resps) => assert_eq!(
+                resps[1].get("value"),
+                Some(&BencodeValue::Bytes(b"hi\n".to_vec()))
+            ),
+            status => panic!("unexpected status {}", status.name()),
+        }
+    }
+
+    #[test]
+    fn drop_closes_connection_test() {
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = Addr::tcp("127.0.0.1", listener.local_addr().unwrap().port());
+
+        drop(NreplStream::new(&addr).unwrap());
+
+        // Server sees the end of stream instead of a connection left open
+        let (mut tcp, _) = listener.accept().unwrap();
+        tcp.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(tcp.read(&mut [0; 1]).unwrap(), 0);
+    }
 }
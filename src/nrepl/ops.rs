@@ -1,11 +1,14 @@
+#![allow(non_local_definitions)]
+
 use crate::bencode as bc;
-use crate::config::Session;
+use crate::config::{Session, SessionKind};
 use crate::nrepl;
+use crate::reader;
 use failure::{Error as StdError, Fail};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_bencode::value::Value as BencodeValue;
 use std::collections::HashSet;
-use std::convert::From;
+use std::convert::{From, TryFrom};
 
 #[derive(Debug, Fail)]
 pub enum Error {
@@ -24,6 +27,12 @@ pub enum Error {
     DuplicatedOpsInResponse,
     #[fail(display = "'info' op is not available")]
     InfoOpUnavailable,
+    #[fail(display = "neither 'complete' nor 'completions' op is available")]
+    CompleteOpUnavailable,
+    #[fail(display = "'{}' op is not available", op)]
+    XrefOpUnavailable { op: String },
+    #[fail(display = "`{}` is not a namespace name", ns)]
+    BadNsName { ns: String },
 }
 
 pub struct CloneSession {
@@ -59,10 +68,10 @@ impl nrepl::NreplOp<String> for CloneSession {
                         return Ok(bc::try_into_string(session_id)?);
                     }
                 }
-                return Err(Error::NoSessionIdInResponse {
+                Err(Error::NoSessionIdInResponse {
                     op: "clone".to_string(),
                 }
-                .into());
+                .into())
             }
             status => Err(Error::BadStatus {
                 status: status.name(),
@@ -72,6 +81,7 @@ impl nrepl::NreplOp<String> for CloneSession {
     }
 }
 
+#[derive(Default)]
 pub struct LsSessions {}
 
 impl LsSessions {
@@ -97,10 +107,10 @@ impl nrepl::NreplOp<Vec<String>> for LsSessions {
                         return Ok(bc::try_into_str_vec(sessions)?);
                     }
                 }
-                return Err(Error::NoSessionsInResponse {
+                Err(Error::NoSessionsInResponse {
                     op: "ls-sessions".to_string(),
                 }
-                .into());
+                .into())
             }
 
             status => Err(Error::BadStatus {
@@ -126,16 +136,29 @@ pub struct InfoResponse {
     pub doc: String,
 }
 
+/// Info of Java class or its member
+#[derive(Debug, Serialize)]
+pub struct JavaInfo {
+    pub class: String,
+    pub member: Option<String>,
+    pub javadoc: Option<String>,
+    /// Source file when runtime knows it, often it's only a path inside of sources JAR
+    pub file: Option<String>,
+    pub line: Option<i64>,
+    pub doc: String,
+}
+
 pub enum InfoResponseType {
     Ns(InfoResponse),
     Symbol(InfoResponse),
+    Java(JavaInfo),
 }
 
 impl InfoResponseType {
-    pub fn into_resp(self) -> InfoResponse {
+    pub fn into_doc(self) -> String {
         match self {
-            Self::Ns(r) => r,
-            Self::Symbol(r) => r,
+            Self::Ns(r) | Self::Symbol(r) => r.doc,
+            Self::Java(r) => r.doc,
         }
     }
 }
@@ -204,6 +227,71 @@ fn get_str_list_bencode(resp: &mut nrepl::Resp, k: &str) -> Result<Option<Vec<St
         Ok(None)
     }
 }
+
+/// Joins arglists as `(a b)` lines, `arglists-str` has them as `[a b]` lines
+pub fn format_arglists(arglists: Option<String>) -> String {
+    arglists
+        .unwrap_or_default()
+        .split('\n')
+        .filter(|s| !s.is_empty())
+        .map(|s| format!("({})", s))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Parses info of Java class or member. Ambiguous `.member` has info of each class
+/// having it in `candidates`, the first class is taken then.
+fn parse_java_info(mut resp: nrepl::Resp) -> Result<Option<JavaInfo>, StdError> {
+    if let Some(BencodeValue::Dict(candidates)) = resp.remove("candidates") {
+        return match candidates.into_iter().min_by(|(a, _), (b, _)| a.cmp(b)) {
+            Some((_, candidate)) => parse_java_info(
+                nrepl::Resp::try_from(candidate).map_err(nrepl::Error::BencodeFormatError)?,
+            ),
+            None => Ok(None),
+        };
+    }
+
+    let class = match get_str_bencode(&mut resp, "class")? {
+        Some(class) => class,
+        None => return Ok(None),
+    };
+    let member = get_str_bencode(&mut resp, "member")?;
+    let javadoc = get_str_bencode(&mut resp, "javadoc")?;
+    // Missing file is sent as empty list
+    let file = match resp.get("file") {
+        Some(BencodeValue::Bytes(_)) => get_str_bencode(&mut resp, "file")?,
+        _ => None,
+    };
+    let line = get_int_bencode(&mut resp, "line")?;
+    let arglists = get_str_bencode(&mut resp, "arglists-str")?;
+    let returns = get_str_bencode(&mut resp, "returns")?;
+
+    let doc = vec![
+        match &member {
+            Some(member) => format!("{}/{}", class, member),
+            None => class.clone(),
+        },
+        format_arglists(arglists),
+        returns
+            .map(|r| format!("Returns: {}", r))
+            .unwrap_or_default(),
+        get_str_bencode(&mut resp, "doc")?.unwrap_or_default(),
+        javadoc.clone().unwrap_or_default(),
+    ]
+    .into_iter()
+    .filter(|s| !s.is_empty())
+    .collect::<Vec<String>>()
+    .join("\n");
+
+    Ok(Some(JavaInfo {
+        class,
+        member,
+        javadoc,
+        file,
+        line,
+        doc,
+    }))
+}
 impl nrepl::NreplOp<Option<InfoResponseType>> for Info {
     type Error = StdError;
 
@@ -218,17 +306,18 @@ impl nrepl::NreplOp<Option<InfoResponseType>> for Info {
         match n.op(self)? {
             nrepl::Status::Done(mut resps) | nrepl::Status::State(mut resps) => {
                 let mut resp = resps.pop().unwrap();
+
+                if resp.contains_key("class") || resp.contains_key("candidates") {
+                    return Ok(parse_java_info(resp)?.map(InfoResponseType::Java));
+                }
+
                 // "line" is required for symbols, but not namespace, TODO: Improve this
                 let line: Option<i64> = get_int_bencode(&mut resp, "line")?;
                 let column: Option<i64> = get_int_bencode(&mut resp, "column")?;
 
-                // It's weird, but valid:
-                // When we received {file: [...]} it means that given given symbol was a java class,
-                // and we have nothing to do with Java Class here.
-                if let Some(v) = resp.get("file") {
-                    if let serde_bencode::value::Value::List(_) = v {
-                        return Ok(None);
-                    }
+                // Missing file is sent as empty list, like for vars defined in REPL
+                if let Some(BencodeValue::List(_)) = resp.get("file") {
+                    return Ok(None);
                 }
 
                 // This is required field, we can't skip it
@@ -258,19 +347,15 @@ impl nrepl::NreplOp<Option<InfoResponseType>> for Info {
 
                 // There's only single way to distinguish NS from SYMBOL is by absence of
                 // column/name/arglist
-                if line.is_some() && column.is_none() && name.is_none() && arglist.is_none() {
+                if let (Some(line), None, None, None) = (line, column, &name, &arglist) {
                     docstr = vec![ns, doc]
                         .into_iter()
-                        .flat_map(|v| v)
+                        .flatten()
                         .collect::<Vec<String>>()
                         .join("\n");
 
                     Ok(Some(InfoResponseType::Ns(InfoResponse::new(
-                        line.unwrap(),
-                        column,
-                        file,
-                        resource,
-                        docstr,
+                        line, column, file, resource, docstr,
                     ))))
                 // Otherwise it's SYMBOL
                 } else {
@@ -278,17 +363,12 @@ impl nrepl::NreplOp<Option<InfoResponseType>> for Info {
                         String::from(if is_macro.is_some() { "macro" } else { "" }),
                         vec![ns, name]
                             .into_iter()
-                            .flat_map(|v| v)
+                            .flatten()
                             .collect::<Vec<String>>()
                             .join("/"),
-                        arglist
-                            .unwrap_or("".to_string())
-                            .split("\n")
-                            .map(|s| format!("({})", s))
-                            .collect::<Vec<String>>()
-                            .join("\n"),
-                        doc.unwrap_or(String::new()),
-                        spec.unwrap_or(String::new()),
+                        format_arglists(arglist),
+                        doc.unwrap_or_default(),
+                        spec.unwrap_or_default(),
                     ]
                     .into_iter()
                     .filter(|s| !s.is_empty())
@@ -425,18 +505,16 @@ impl nrepl::NreplOp<DescribeResp> for Describe {
                 let mut ops: Option<HashSet<String>> = None;
 
                 for mut resp in resps {
-                    if let Some(json_val) = resp.remove("ops") {
-                        if let BencodeValue::Dict(ops_map) = json_val {
-                            if ops.is_some() {
-                                return Err(Error::DuplicatedOpsInResponse.into());
-                            }
-                            ops = Some(
-                                ops_map
-                                    .into_iter()
-                                    .map(|(k, _)| String::from_utf8(k).map_err(|e| e.into()))
-                                    .collect::<Result<HashSet<String>, Self::Error>>()?,
-                            );
+                    if let Some(BencodeValue::Dict(ops_map)) = resp.remove("ops") {
+                        if ops.is_some() {
+                            return Err(Error::DuplicatedOpsInResponse.into());
                         }
+                        ops = Some(
+                            ops_map
+                                .into_keys()
+                                .map(|k| String::from_utf8(k).map_err(|e| e.into()))
+                                .collect::<Result<HashSet<String>, Self::Error>>()?,
+                        );
                     }
                 }
 
@@ -450,3 +528,1236 @@ impl nrepl::NreplOp<DescribeResp> for Describe {
         }
     }
 }
+
+/// Evaluates `code`, optionally in context of `ns` and position in `file`
+pub struct Eval {
+    id: Option<String>,
+    code: String,
+    session: Session,
+    ns: Option<String>,
+    file: Option<String>,
+    line: Option<i64>,
+    column: Option<i64>,
+}
+
+/// Everything `eval` produced: printed values, output chunks and exception classes
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EvalResult {
+    pub values: Vec<String>,
+    pub out: Vec<String>,
+    pub err: Vec<String>,
+    pub ex: Option<String>,
+    pub root_ex: Option<String>,
+    pub ns: Option<String>,
+    pub interrupted: bool,
+}
+
+impl EvalResult {
+    pub fn is_error(&self) -> bool {
+        self.ex.is_some()
+    }
+
+    fn from_resps(resps: Vec<nrepl::Resp>, interrupted: bool) -> Result<Self, StdError> {
+        let mut result = EvalResult {
+            interrupted,
+            ..Default::default()
+        };
+
+        for mut resp in resps {
+            result.push_resp(&mut resp)?;
+        }
+
+        Ok(result)
+    }
+
+    fn from_status(status: nrepl::Status) -> Result<Self, StdError> {
+        match status {
+            nrepl::Status::Done(resps)
+            | nrepl::Status::State(resps)
+            | nrepl::Status::EvalError(resps) => EvalResult::from_resps(resps, false),
+            nrepl::Status::Interrupted(resps) => EvalResult::from_resps(resps, true),
+
+            status => Err(Error::BadStatus {
+                status: status.name(),
+            }
+            .into()),
+        }
+    }
+
+    /// Place of the syntax error which failed loading of code
+    pub fn compile_error(&self) -> Option<CompileError> {
+        self.ex.as_ref()?;
+        parse_compile_error(&self.err.concat())
+    }
+
+    fn push_resp(&mut self, resp: &mut nrepl::Resp) -> Result<(), StdError> {
+        if let Some(value) = get_str_bencode(resp, "value")? {
+            self.values.push(value);
+        }
+        if let Some(out) = get_str_bencode(resp, "out")? {
+            self.out.push(out);
+        }
+        if let Some(err) = get_str_bencode(resp, "err")? {
+            self.err.push(err);
+        }
+        if let Some(ex) = get_str_bencode(resp, "ex")? {
+            self.ex = Some(ex);
+        }
+        if let Some(root_ex) = get_str_bencode(resp, "root-ex")? {
+            self.root_ex = Some(root_ex);
+        }
+        if let Some(ns) = get_str_bencode(resp, "ns")? {
+            self.ns = Some(ns);
+        }
+        Ok(())
+    }
+}
+
+/// Syntax error reported by Clojure compiler, `file` is a path of the loaded file or a classpath
+/// resource like `foo/core.clj`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CompileError {
+    pub file: String,
+    pub line: i64,
+    pub column: i64,
+    pub message: String,
+}
+
+/// Splits `foo/core.clj:3:1` or `foo/core.clj:3`
+fn parse_file_position(location: &str) -> Option<(String, i64, i64)> {
+    let (rest, last) = location.rsplit_once(':')?;
+    let last = last.parse::<i64>().ok()?;
+
+    match rest.rsplit_once(':') {
+        Some((file, line)) if line.parse::<i64>().is_ok() => {
+            Some((file.to_string(), line.parse().ok()?, last))
+        }
+        _ => Some((rest.to_string(), last, 1)),
+    }
+}
+
+/// Reads `Syntax error compiling at (foo/core.clj:3:1).` followed by the cause on the next line,
+/// as Clojure 1.10 prints it, or `CompilerException ..., compiling:(foo/core.clj:3:1)` of the
+/// older ones
+fn parse_compile_error(err: &str) -> Option<CompileError> {
+    let mut lines = err.lines();
+
+    while let Some(line) = lines.next() {
+        let (location, message) = if let Some((_, location)) = line
+            .strip_prefix("Syntax error ")
+            .and_then(|l| l.rsplit_once(" at ("))
+        {
+            let message = lines.next().map(str::trim).filter(|m| !m.is_empty());
+            (
+                location.trim_end_matches('.'),
+                message.unwrap_or(line).to_string(),
+            )
+        } else if let Some((message, location)) = line.rsplit_once(", compiling:(") {
+            let message = message.trim_start_matches("CompilerException ");
+            (location, message.to_string())
+        } else {
+            continue;
+        };
+
+        let (file, line, column) = parse_file_position(location.trim_end_matches(')'))?;
+        return Some(CompileError {
+            file,
+            line,
+            column,
+            message,
+        });
+    }
+
+    None
+}
+
+impl Eval {
+    pub fn new(session: Session, code: String) -> Self {
+        Self {
+            id: None,
+            code,
+            session,
+            ns: None,
+            file: None,
+            line: None,
+            column: None,
+        }
+    }
+
+    /// Op `id` to be used as `interrupt-id` later
+    pub fn id(mut self, id: String) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn ns(mut self, ns: Option<String>) -> Self {
+        self.ns = ns;
+        self
+    }
+
+    pub fn file(mut self, file: Option<String>) -> Self {
+        self.file = file;
+        self
+    }
+
+    pub fn position(mut self, line: Option<i64>, column: Option<i64>) -> Self {
+        self.line = line;
+        self.column = column;
+        self
+    }
+
+    /// Sends `eval` and calls `on_resp` for each response as it arrives,
+    /// so `out`/`err` could be shown while evaluation is still running
+    pub fn send_stream<F: FnMut(&nrepl::Resp)>(
+        &self,
+        n: &nrepl::NreplStream,
+        on_resp: F,
+    ) -> Result<EvalResult, StdError> {
+        EvalResult::from_status(n.op_stream(self, on_resp)?)
+    }
+
+    /// Same as `send_stream`, but code reading `*in*` gets the text `on_input` reads
+    pub fn send_input<F, I>(
+        &self,
+        n: &nrepl::NreplStream,
+        on_resp: F,
+        on_input: I,
+    ) -> Result<EvalResult, StdError>
+    where
+        F: FnMut(&nrepl::Resp),
+        I: FnMut() -> Option<String>,
+    {
+        EvalResult::from_status(n.op_stream_input(self, on_resp, on_input)?)
+    }
+}
+
+impl From<&Eval> for nrepl::Op {
+    fn from(
+        Eval {
+            id,
+            code,
+            session,
+            ns,
+            file,
+            line,
+            column,
+        }: &Eval,
+    ) -> nrepl::Op {
+        let mut args = vec![
+            ("code".to_string(), code.to_string()),
+            ("session".to_string(), session.id()),
+        ];
+
+        if let Some(ns) = ns {
+            args.push(("ns".to_string(), ns.to_string()));
+        }
+        if let Some(file) = file {
+            args.push(("file".to_string(), file.to_string()));
+        }
+
+        // Evaluation could take any time, it's up to user to interrupt it
+        let mut op = nrepl::Op::new("eval".to_string(), args).timeout(None);
+
+        if let Some(id) = id {
+            op = op.with_id(id.to_string());
+        }
+
+        if let Some(line) = line {
+            op = op.int_arg("line", *line);
+        }
+        if let Some(column) = column {
+            op = op.int_arg("column", *column);
+        }
+
+        op
+    }
+}
+
+impl nrepl::NreplOp<EvalResult> for Eval {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<EvalResult, Self::Error> {
+        self.send_stream(n, |_| {})
+    }
+}
+
+/// Interrupts evaluation running in `session`, only the one with `interrupt_id` if it's given
+pub struct Interrupt {
+    session: Session,
+    interrupt_id: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InterruptResult {
+    Interrupted,
+    /// There was nothing to interrupt
+    SessionIdle,
+}
+
+impl Interrupt {
+    pub fn new(session: Session, interrupt_id: Option<String>) -> Self {
+        Self {
+            session,
+            interrupt_id,
+        }
+    }
+}
+
+impl From<&Interrupt> for nrepl::Op {
+    fn from(
+        Interrupt {
+            session,
+            interrupt_id,
+        }: &Interrupt,
+    ) -> nrepl::Op {
+        let mut args = vec![("session".to_string(), session.id())];
+
+        if let Some(interrupt_id) = interrupt_id {
+            args.push(("interrupt-id".to_string(), interrupt_id.to_string()));
+        }
+
+        nrepl::Op::new("interrupt".to_string(), args)
+    }
+}
+
+impl nrepl::NreplOp<InterruptResult> for Interrupt {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<InterruptResult, Self::Error> {
+        match n.op(self)? {
+            nrepl::Status::Done(_) | nrepl::Status::Interrupted(_) => {
+                Ok(InterruptResult::Interrupted)
+            }
+            nrepl::Status::SessionIdle(_) => Ok(InterruptResult::SessionIdle),
+
+            status => Err(Error::BadStatus {
+                status: status.name(),
+            }
+            .into()),
+        }
+    }
+}
+
+/// Completes `prefix` in `ns` using cider's `complete` op or nrepl's own `completions` op
+pub struct Complete {
+    session: Session,
+    ns: Option<String>,
+    prefix: String,
+    context: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Candidate {
+    pub candidate: String,
+    #[serde(rename = "type")]
+    pub candidate_type: Option<String>,
+    pub ns: Option<String>,
+    pub arglists: Vec<String>,
+    pub doc: Option<String>,
+}
+
+impl Candidate {
+    fn from_bencode(val: BencodeValue) -> Result<Self, StdError> {
+        let mut resp = nrepl::Resp::try_from(val).map_err(nrepl::Error::from)?;
+
+        // cider sends arglists as a list, while `arglists-str` is a single string
+        let arglists = match resp.remove("arglists") {
+            Some(BencodeValue::List(arglists)) => {
+                bc::try_into_str_vec(BencodeValue::List(arglists))?
+            }
+            Some(arglists) => vec![bc::try_into_string(arglists)?],
+            None => vec![],
+        };
+
+        Ok(Candidate {
+            candidate: get_str_bencode(&mut resp, "candidate")?.ok_or(Error::FieldNotFound {
+                op: "complete".to_string(),
+                field: "candidate".to_string(),
+            })?,
+            candidate_type: get_str_bencode(&mut resp, "type")?,
+            ns: get_str_bencode(&mut resp, "ns")?,
+            arglists,
+            doc: get_str_bencode(&mut resp, "doc")?,
+        })
+    }
+}
+
+impl Complete {
+    pub fn new(session: Session, ns: Option<String>, prefix: String) -> Self {
+        Self {
+            session,
+            ns,
+            prefix,
+            context: None,
+        }
+    }
+
+    /// Form surrounding the prefix with `__prefix__` in place of it, used by cider to complete
+    /// locals and keys
+    pub fn context(mut self, context: Option<String>) -> Self {
+        self.context = context;
+        self
+    }
+
+    fn op_name(&self) -> Option<&'static str> {
+        if self.session.is_op_available("complete") {
+            Some("complete")
+        } else if self.session.is_op_available("completions") {
+            Some("completions")
+        } else {
+            None
+        }
+    }
+}
+
+impl From<&Complete> for nrepl::Op {
+    fn from(complete: &Complete) -> nrepl::Op {
+        let op_name = complete.op_name().unwrap_or("complete");
+        let mut args = vec![
+            ("prefix".to_string(), complete.prefix.to_string()),
+            ("session".to_string(), complete.session.id()),
+        ];
+
+        if let Some(ns) = &complete.ns {
+            args.push(("ns".to_string(), ns.to_string()));
+        }
+
+        if op_name == "complete" {
+            if let Some(context) = &complete.context {
+                args.push(("context".to_string(), context.to_string()));
+            }
+
+            nrepl::Op::new(op_name.to_string(), args).list_arg(
+                "extra-metadata",
+                vec!["arglists".to_string(), "doc".to_string()],
+            )
+        } else {
+            nrepl::Op::new(op_name.to_string(), args)
+        }
+    }
+}
+
+impl nrepl::NreplOp<Vec<Candidate>> for Complete {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<Vec<Candidate>, Self::Error> {
+        if self.op_name().is_none() {
+            return Err(Error::CompleteOpUnavailable.into());
+        }
+
+        match n.op(self)? {
+            nrepl::Status::Done(resps) | nrepl::Status::State(resps) => {
+                let mut candidates = vec![];
+
+                for mut resp in resps {
+                    if let Some(BencodeValue::List(items)) = resp.remove("completions") {
+                        for item in items {
+                            candidates.push(Candidate::from_bencode(item)?);
+                        }
+                    }
+                }
+
+                Ok(candidates)
+            }
+
+            status => Err(Error::BadStatus {
+                status: status.name(),
+            }
+            .into()),
+        }
+    }
+}
+
+/// Evaluates `code` which returns a collection of strings and reads it back
+fn eval_str_list(
+    n: &nrepl::NreplStream,
+    session: &Session,
+    code: String,
+) -> Result<Vec<String>, StdError> {
+    let res = nrepl::NreplOp::send(&Eval::new(session.clone(), code), n)?;
+
+    if let Some(ex) = res.ex {
+        return Err(Error::BadStatus { status: ex }.into());
+    }
+
+    let value = match res.values.last().map(|v| reader::read_str(v)) {
+        Some(value) => value?,
+        None => None,
+    };
+
+    Ok(value
+        .as_ref()
+        .and_then(|v| v.children())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|i| i.as_str())
+                .map(|s| s.to_string())
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Lists namespaces loaded in runtime, using cider's `ns-list` op or `eval` when it's missing
+pub struct NsList {
+    session: Session,
+}
+
+impl NsList {
+    pub fn new(session: Session) -> Self {
+        Self { session }
+    }
+}
+
+impl From<&NsList> for nrepl::Op {
+    fn from(NsList { session }: &NsList) -> nrepl::Op {
+        nrepl::Op::new(
+            "ns-list".to_string(),
+            vec![("session".to_string(), session.id())],
+        )
+    }
+}
+
+impl nrepl::NreplOp<Vec<String>> for NsList {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<Vec<String>, Self::Error> {
+        if !self.session.is_op_available("ns-list") {
+            return eval_str_list(n, &self.session, "(map str (all-ns))".to_string());
+        }
+
+        match n.op(self)? {
+            nrepl::Status::Done(resps) | nrepl::Status::State(resps) => {
+                for mut resp in resps {
+                    if let Some(list) = get_str_list_bencode(&mut resp, "ns-list")? {
+                        return Ok(list);
+                    }
+                }
+                Err(Error::FieldNotFound {
+                    op: "ns-list".to_string(),
+                    field: "ns-list".to_string(),
+                }
+                .into())
+            }
+
+            status => Err(Error::BadStatus {
+                status: status.name(),
+            }
+            .into()),
+        }
+    }
+}
+
+/// Lists names of public vars in `ns` using cider's `ns-vars` op, private vars are included
+/// with `ns-interns` evaluation
+pub struct NsVars {
+    session: Session,
+    ns: String,
+    private: bool,
+}
+
+impl NsVars {
+    pub fn new(session: Session, ns: String) -> Self {
+        Self {
+            session,
+            ns,
+            private: false,
+        }
+    }
+
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    /// Code evaluated when the op is missing or private vars are asked for
+    fn code(&self) -> String {
+        let f = if self.private {
+            "ns-interns"
+        } else {
+            "ns-publics"
+        };
+
+        match self.session.kind() {
+            SessionKind::Clj => format!("(some->> (find-ns '{}) {} keys (map str))", self.ns, f),
+            // ClojureScript has them as macros which take quoted symbol, not a namespace
+            SessionKind::Cljs => format!("(->> ({} '{}) keys (map str))", f, self.ns),
+        }
+    }
+}
+
+impl From<&NsVars> for nrepl::Op {
+    fn from(NsVars { session, ns, .. }: &NsVars) -> nrepl::Op {
+        nrepl::Op::new(
+            "ns-vars".to_string(),
+            vec![
+                ("ns".to_string(), ns.to_string()),
+                ("session".to_string(), session.id()),
+            ],
+        )
+    }
+}
+
+impl nrepl::NreplOp<Vec<String>> for NsVars {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<Vec<String>, Self::Error> {
+        if self.private || !self.session.is_op_available("ns-vars") {
+            return eval_str_list(n, &self.session, self.code());
+        }
+
+        match n.op(self)? {
+            nrepl::Status::Done(resps) | nrepl::Status::State(resps) => {
+                for mut resp in resps {
+                    if let Some(list) = get_str_list_bencode(&mut resp, "ns-vars")? {
+                        return Ok(list);
+                    }
+                }
+                Ok(vec![])
+            }
+
+            status => Err(Error::BadStatus {
+                status: status.name(),
+            }
+            .into()),
+        }
+    }
+}
+
+/// Where JVM loaded `class` from, found by evaluation as there's no op for it
+pub struct ClassSource {
+    session: Session,
+    class: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ClassSourceResult {
+    /// URL of JAR or directory, `None` for JDK classes
+    pub location: Option<String>,
+    pub java_home: Option<String>,
+}
+
+impl ClassSource {
+    pub fn new(session: Session, class: String) -> Self {
+        Self { session, class }
+    }
+
+    fn code(&self) -> String {
+        format!(
+            "[(or (some-> (Class/forName \"{}\" false (clojure.lang.RT/baseLoader))
+                          .getProtectionDomain .getCodeSource .getLocation str) \"\")
+              (or (System/getProperty \"java.home\") \"\")]",
+            self.class
+        )
+    }
+}
+
+impl From<&ClassSource> for nrepl::Op {
+    fn from(op: &ClassSource) -> nrepl::Op {
+        nrepl::Op::from(&Eval::new(op.session.clone(), op.code()))
+    }
+}
+
+impl nrepl::NreplOp<ClassSourceResult> for ClassSource {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<ClassSourceResult, Self::Error> {
+        let mut values = eval_str_list(n, &self.session, self.code())?
+            .into_iter()
+            .map(|v| if v.is_empty() { None } else { Some(v) });
+
+        Ok(ClassSourceResult {
+            location: values.next().flatten(),
+            java_home: values.next().flatten(),
+        })
+    }
+}
+
+/// Entries of JVM classpath: JARs and directories, found by evaluation
+pub struct Classpath {
+    session: Session,
+}
+
+impl Classpath {
+    pub fn new(session: Session) -> Self {
+        Self { session }
+    }
+
+    fn code(&self) -> String {
+        "(seq (.split (System/getProperty \"java.class.path\") java.io.File/pathSeparator))"
+            .to_string()
+    }
+}
+
+impl From<&Classpath> for nrepl::Op {
+    fn from(op: &Classpath) -> nrepl::Op {
+        nrepl::Op::from(&Eval::new(op.session.clone(), op.code()))
+    }
+}
+
+impl nrepl::NreplOp<Vec<String>> for Classpath {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<Vec<String>, Self::Error> {
+        eval_str_list(n, &self.session, self.code())
+    }
+}
+
+/// Clojure string literal of `s`, escapes of JSON strings are valid in Clojure
+fn clj_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_default()
+}
+
+/// Checks that `ns` reads as a single symbol, so it can be quoted in evaluated code
+fn check_ns_name(ns: &str) -> Result<(), Error> {
+    let bad_char = |c: char| c.is_whitespace() || "()[]{}\"'`~^@;,\\#".contains(c);
+
+    if ns.is_empty()
+        || ns.starts_with(|c: char| c.is_ascii_digit() || c == ':')
+        || ns.contains(bad_char)
+    {
+        return Err(Error::BadNsName { ns: ns.to_string() });
+    }
+
+    Ok(())
+}
+
+/// How `require` treats namespace which is already loaded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reload {
+    /// Loads namespace only when it isn't loaded yet
+    No,
+    /// `:reload`
+    Reload,
+    /// `:reload-all`, namespaces it requires are reloaded too
+    All,
+}
+
+/// Loads `ns` with `clojure.core/require`, evaluated as there's no op for it
+pub struct Require {
+    session: Session,
+    ns: String,
+    reload: Reload,
+}
+
+impl Require {
+    pub fn new(session: Session, ns: String) -> Result<Self, Error> {
+        check_ns_name(&ns)?;
+
+        Ok(Self {
+            session,
+            ns,
+            reload: Reload::No,
+        })
+    }
+
+    pub fn reload(mut self, reload: Reload) -> Self {
+        self.reload = reload;
+        self
+    }
+
+    fn code(&self) -> String {
+        let flag = match self.reload {
+            Reload::No => "",
+            Reload::Reload => " :reload",
+            Reload::All => " :reload-all",
+        };
+        format!("(clojure.core/require '{}{})", self.ns, flag)
+    }
+}
+
+impl From<&Require> for nrepl::Op {
+    fn from(op: &Require) -> nrepl::Op {
+        nrepl::Op::from(&Eval::new(op.session.clone(), op.code()))
+    }
+}
+
+impl nrepl::NreplOp<EvalResult> for Require {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<EvalResult, Self::Error> {
+        nrepl::NreplOp::send(&Eval::new(self.session.clone(), self.code()), n)
+    }
+}
+
+/// Loads `file` content with `load-file` op, `path` and `name` of the file are used in
+/// locations of its vars and errors
+pub struct LoadFile {
+    session: Session,
+    file: String,
+    path: Option<String>,
+    name: Option<String>,
+}
+
+impl LoadFile {
+    pub fn new(session: Session, file: String) -> Self {
+        Self {
+            session,
+            file,
+            path: None,
+            name: None,
+        }
+    }
+
+    /// Sets `file-path` and `file-name` of `path`
+    pub fn path(mut self, path: &str) -> Self {
+        self.name = std::path::Path::new(path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string());
+        self.path = Some(path.to_string());
+        self
+    }
+}
+
+impl From<&LoadFile> for nrepl::Op {
+    fn from(op: &LoadFile) -> nrepl::Op {
+        let mut args = vec![
+            ("file".to_string(), op.file.to_string()),
+            ("session".to_string(), op.session.id()),
+        ];
+
+        if let Some(path) = &op.path {
+            args.push(("file-path".to_string(), path.to_string()));
+        }
+        if let Some(name) = &op.name {
+            args.push(("file-name".to_string(), name.to_string()));
+        }
+
+        // Loading runs top level forms of the file, they could take any time
+        nrepl::Op::new("load-file".to_string(), args).timeout(None)
+    }
+}
+
+impl nrepl::NreplOp<EvalResult> for LoadFile {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<EvalResult, Self::Error> {
+        EvalResult::from_status(n.op(self)?)
+    }
+}
+
+/// Public vars with names matching `query` regex, using cider's `apropos` op or evaluation
+/// when it's missing
+pub struct Apropos {
+    session: Session,
+    query: String,
+    docs: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct AproposMatch {
+    /// Qualified name of var
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub doc: Option<String>,
+}
+
+impl Apropos {
+    pub fn new(session: Session, query: String) -> Self {
+        Self {
+            session,
+            query,
+            docs: false,
+        }
+    }
+
+    /// Matches docstrings too
+    pub fn docs(mut self, docs: bool) -> Self {
+        self.docs = docs;
+        self
+    }
+
+    fn code(&self) -> String {
+        format!(
+            "(let [re (re-pattern (str \"(?i)\" {}))]
+               (sort (for [ns (all-ns) [sym v] (ns-publics ns)
+                           :when (or (re-find re (str sym))
+                                     (and {} (some->> (:doc (meta v)) (re-find re))))]
+                       (str (ns-name ns) \"/\" sym))))",
+            clj_string(&self.query),
+            self.docs
+        )
+    }
+}
+
+impl From<&Apropos> for nrepl::Op {
+    fn from(op: &Apropos) -> nrepl::Op {
+        let mut args = vec![
+            ("query".to_string(), op.query.to_string()),
+            ("session".to_string(), op.session.id()),
+        ];
+
+        // Any value is taken as true
+        if op.docs {
+            args.push(("docs?".to_string(), "true".to_string()));
+        }
+
+        nrepl::Op::new("apropos".to_string(), args)
+    }
+}
+
+fn parse_apropos_match(val: BencodeValue) -> Result<AproposMatch, StdError> {
+    let mut resp = nrepl::Resp::try_from(val).map_err(nrepl::Error::BencodeFormatError)?;
+
+    Ok(AproposMatch {
+        name: get_str_bencode(&mut resp, "name")?.unwrap_or_default(),
+        kind: get_str_bencode(&mut resp, "type")?,
+        doc: get_str_bencode(&mut resp, "doc")?,
+    })
+}
+
+impl nrepl::NreplOp<Vec<AproposMatch>> for Apropos {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<Vec<AproposMatch>, Self::Error> {
+        if !self.session.is_op_available("apropos") {
+            return Ok(eval_str_list(n, &self.session, self.code())?
+                .into_iter()
+                .map(|name| AproposMatch {
+                    name,
+                    kind: None,
+                    doc: None,
+                })
+                .collect());
+        }
+
+        match n.op(self)? {
+            nrepl::Status::Done(resps) | nrepl::Status::State(resps) => {
+                for mut resp in resps {
+                    if let Some(BencodeValue::List(matches)) = resp.remove("apropos-matches") {
+                        return matches.into_iter().map(parse_apropos_match).collect();
+                    }
+                }
+                Ok(vec![])
+            }
+
+            status => Err(Error::BadStatus {
+                status: status.name(),
+            }
+            .into()),
+        }
+    }
+}
+
+/// Reloads `namespaces` and runs their `clojure.test` tests. It's evaluated, so the report
+/// is printed to `out` the same way it's printed in REPL. Namespaces which don't exist are
+/// skipped, as test namespace of a namespace is guessed.
+pub struct RunTests {
+    session: Session,
+    ns: Option<String>,
+    namespaces: Vec<String>,
+}
+
+/// Counters returned by `clojure.test/run-tests`
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct TestSummary {
+    pub test: i64,
+    pub pass: i64,
+    pub fail: i64,
+    pub error: i64,
+}
+
+#[derive(Debug)]
+pub struct RunTestsResult {
+    /// `None` when tests couldn't be run, the exception is in `eval`
+    pub summary: Option<TestSummary>,
+    pub eval: EvalResult,
+}
+
+impl RunTests {
+    pub fn new(session: Session, namespaces: Vec<String>) -> Result<Self, Error> {
+        for ns in namespaces.iter() {
+            check_ns_name(ns)?;
+        }
+
+        Ok(Self {
+            session,
+            ns: None,
+            namespaces,
+        })
+    }
+
+    /// NS to evaluate in, so the report has file names relative to it
+    pub fn ns(mut self, ns: Option<String>) -> Self {
+        self.ns = ns;
+        self
+    }
+
+    fn code(&self) -> String {
+        let namespaces = self.namespaces.join(" ");
+
+        format!(
+            "(do (doseq [ns '[{0}]]
+                   (try (clojure.core/require ns :reload)
+                        (catch java.io.FileNotFoundException _)))
+                 (let [nss (keep find-ns '[{0}])]
+                   (if (seq nss)
+                     (select-keys (apply clojure.test/run-tests nss) [:test :pass :fail :error])
+                     {{:test 0 :pass 0 :fail 0 :error 0}})))",
+            namespaces
+        )
+    }
+
+    fn eval(&self) -> Eval {
+        Eval::new(self.session.clone(), self.code()).ns(self.ns.clone())
+    }
+}
+
+/// Reads `{:test 1 :pass 1 :fail 0 :error 0}` printed by nrepl
+fn parse_test_summary(value: &str) -> Option<TestSummary> {
+    let items = match reader::read_str(value).ok()??.form {
+        reader::Form::Map(items) => items,
+        _ => return None,
+    };
+    let mut summary = TestSummary::default();
+
+    for kv in items.chunks(2) {
+        let n = match kv.get(1).map(|v| &v.form) {
+            Some(reader::Form::Number(reader::Number::Int(n))) => *n,
+            _ => continue,
+        };
+        match kv[0].as_keyword().as_deref() {
+            Some("test") => summary.test = n,
+            Some("pass") => summary.pass = n,
+            Some("fail") => summary.fail = n,
+            Some("error") => summary.error = n,
+            _ => {}
+        }
+    }
+
+    Some(summary)
+}
+
+impl From<&RunTests> for nrepl::Op {
+    fn from(op: &RunTests) -> nrepl::Op {
+        nrepl::Op::from(&op.eval())
+    }
+}
+
+impl nrepl::NreplOp<RunTestsResult> for RunTests {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<RunTestsResult, Self::Error> {
+        let eval = nrepl::NreplOp::send(&self.eval(), n)?;
+        let summary = match eval.ex {
+            Some(_) => None,
+            None => eval.values.last().and_then(|v| parse_test_summary(v)),
+        };
+
+        Ok(RunTestsResult { summary, eval })
+    }
+}
+
+/// Vars referencing `sym` (`fn-refs`) or referenced by it (`fn-deps`), cider's xref ops
+pub struct FnRefs {
+    session: Session,
+    ns: String,
+    sym: String,
+    deps: bool,
+}
+
+/// Var found by xref op, `file` is a location as `info` reports it
+#[derive(Debug, PartialEq)]
+pub struct XrefVar {
+    pub name: String,
+    pub file: Option<String>,
+    pub line: Option<i64>,
+    pub column: Option<i64>,
+}
+
+impl FnRefs {
+    pub fn new(session: Session, ns: String, sym: String) -> Self {
+        Self {
+            session,
+            ns,
+            sym,
+            deps: false,
+        }
+    }
+
+    pub fn deps(mut self, deps: bool) -> Self {
+        self.deps = deps;
+        self
+    }
+
+    pub fn op_name(&self) -> &'static str {
+        if self.deps {
+            "fn-deps"
+        } else {
+            "fn-refs"
+        }
+    }
+}
+
+impl From<&FnRefs> for nrepl::Op {
+    fn from(op: &FnRefs) -> nrepl::Op {
+        nrepl::Op::new(
+            op.op_name().to_string(),
+            vec![
+                ("ns".to_string(), op.ns.to_string()),
+                ("sym".to_string(), op.sym.to_string()),
+                ("session".to_string(), op.session.id()),
+            ],
+        )
+    }
+}
+
+fn parse_xref_var(val: BencodeValue) -> Result<XrefVar, StdError> {
+    let mut resp = nrepl::Resp::try_from(val).map_err(nrepl::Error::BencodeFormatError)?;
+    // Missing file is sent as empty list
+    let file = match resp.get("file") {
+        Some(BencodeValue::Bytes(_)) => get_str_bencode(&mut resp, "file")?,
+        _ => None,
+    };
+
+    Ok(XrefVar {
+        name: get_str_bencode(&mut resp, "name")?.unwrap_or_default(),
+        file,
+        line: get_int_bencode(&mut resp, "line")?,
+        column: get_int_bencode(&mut resp, "column")?,
+    })
+}
+
+impl nrepl::NreplOp<Vec<XrefVar>> for FnRefs {
+    type Error = StdError;
+
+    fn send(&self, n: &nrepl::NreplStream) -> Result<Vec<XrefVar>, Self::Error> {
+        let op_name = self.op_name();
+        if !self.session.is_op_available(op_name) {
+            return Err(Error::XrefOpUnavailable {
+                op: op_name.to_string(),
+            }
+            .into());
+        }
+
+        match n.op(self)? {
+            nrepl::Status::Done(resps) | nrepl::Status::State(resps) => {
+                for mut resp in resps {
+                    if let Some(BencodeValue::List(vars)) = resp.remove(op_name) {
+                        return vars.into_iter().map(parse_xref_var).collect();
+                    }
+                }
+                Ok(vec![])
+            }
+
+            status => Err(Error::BadStatus {
+                status: status.name(),
+            }
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ns_vars_code_test() {
+        let session = Session::new(
+            "nrepl://localhost:1".to_string(),
+            "s".to_string(),
+            HashSet::new(),
+        );
+        let cljs = session.clone().with_kind(SessionKind::Cljs);
+
+        assert_eq!(
+            NsVars::new(session, "foo.core".to_string())
+                .private(true)
+                .code(),
+            "(some->> (find-ns 'foo.core) ns-interns keys (map str))"
+        );
+        assert_eq!(
+            NsVars::new(cljs, "foo.core".to_string()).code(),
+            "(->> (ns-publics 'foo.core) keys (map str))"
+        );
+    }
+
+    #[test]
+    fn require_code_test() {
+        let session = Session::new(
+            "nrepl://localhost:1".to_string(),
+            "s".to_string(),
+            HashSet::new(),
+        );
+        let require = |ns: &str| Require::new(session.clone(), ns.to_string());
+
+        assert_eq!(
+            require("foo.core-test").unwrap().reload(Reload::All).code(),
+            "(clojure.core/require 'foo.core-test :reload-all)"
+        );
+        for ns in ["", "foo) (bar", "foo bar", "\"foo\"", ":foo", "1foo"] {
+            assert!(require(ns).is_err(), "{}", ns);
+        }
+    }
+
+    #[test]
+    fn run_tests_names_test() {
+        let session = Session::new(
+            "nrepl://localhost:1".to_string(),
+            "s".to_string(),
+            HashSet::new(),
+        );
+        let run_tests = |nss: &[&str]| {
+            RunTests::new(
+                session.clone(),
+                nss.iter().map(|ns| ns.to_string()).collect(),
+            )
+        };
+
+        assert!(run_tests(&["foo.core-test", "foo.util-test"]).is_ok());
+        assert!(run_tests(&["foo.core-test", "foo] (bar"]).is_err());
+    }
+
+    #[test]
+    fn parse_compile_error_test() {
+        let error = |file: &str, line, column, message: &str| CompileError {
+            file: file.to_string(),
+            line,
+            column,
+            message: message.to_string(),
+        };
+
+        assert_eq!(
+            parse_compile_error(
+                "Syntax error compiling at (foo/core.clj:3:1).\n\
+                 Unable to resolve symbol: x in this context\n"
+            ),
+            Some(error(
+                "foo/core.clj",
+                3,
+                1,
+                "Unable to resolve symbol: x in this context"
+            ))
+        );
+        assert_eq!(
+            parse_compile_error(
+                "Syntax error macroexpanding clojure.core/let at (/tmp/p/src/c.clj:10:5).\n\
+                 [x] - failed: even-number-of-forms? at: [:bindings]\n"
+            ),
+            Some(error(
+                "/tmp/p/src/c.clj",
+                10,
+                5,
+                "[x] - failed: even-number-of-forms? at: [:bindings]"
+            ))
+        );
+        assert_eq!(
+            parse_compile_error(
+                "CompilerException java.lang.RuntimeException: Unable to resolve symbol: x \
+                 in this context, compiling:(foo/core.clj:3:1)\n"
+            ),
+            Some(error(
+                "foo/core.clj",
+                3,
+                1,
+                "java.lang.RuntimeException: Unable to resolve symbol: x in this context"
+            ))
+        );
+        assert_eq!(
+            parse_compile_error("Execution error (ArithmeticException) at foo/f (core.clj:3).\n"),
+            None
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Discovery of nrepl port from files written by build tools when they start nrepl
+
+use super::transport::Addr;
+use crate::project;
+use serde::Serialize;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Port files relative to directories of project, in the order they are checked.
+/// Leiningen, Clojure CLI and Babashka write `.nrepl-port`, older Leiningen `target/repl-port`
+pub const PORT_FILES: [&str; 4] = [
+    ".nrepl-port",
+    ".shadow-cljs/nrepl.port",
+    "target/repl-port",
+    ".bb-nrepl-port",
+];
+
+/// Port of nrepl and the file it was read from
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PortFile {
+    pub port: u16,
+    pub file: String,
+    /// Something listens on the port, it's not left by nrepl which isn't running anymore
+    pub alive: bool,
+}
+
+/// Port written to `file`
+pub fn read_port(file: &Path) -> Option<u16> {
+    std::fs::read_to_string(file).ok()?.trim().parse().ok()
+}
+
+/// Tells if something listens on `addr`
+pub fn is_alive(addr: &Addr) -> bool {
+    match addr {
+        Addr::Tcp { host, port } => (host.as_str(), *port)
+            .to_socket_addrs()
+            .map(|mut socket_addrs| {
+                socket_addrs.any(|socket_addr| {
+                    TcpStream::connect_timeout(&socket_addr, Duration::from_millis(500)).is_ok()
+                })
+            })
+            .unwrap_or(false),
+        Addr::Unix(path) => UnixStream::connect(path).is_ok(),
+    }
+}
+
+/// `start` and its parents up to the root of its project, only `start` outside of projects
+fn search_dirs(start: &Path) -> Vec<PathBuf> {
+    match project::find_root(start) {
+        Some(root) => start
+            .ancestors()
+            .take_while(|dir| dir.starts_with(&root))
+            .map(Path::to_path_buf)
+            .collect(),
+        None => vec![start.to_path_buf()],
+    }
+}
+
+/// The first port file in `dirs` with nrepl listening on its port of `host`. When all of them
+/// are stale the first one is returned, so connecting to it reports why it failed.
+pub fn discover_in(dirs: &[PathBuf], host: &str) -> Option<PortFile> {
+    let mut stale = None;
+
+    for file in dirs
+        .iter()
+        .flat_map(|dir| PORT_FILES.iter().map(move |name| dir.join(name)))
+    {
+        let port = match read_port(&file) {
+            Some(port) => port,
+            None => continue,
+        };
+        let port_file = PortFile {
+            port,
+            file: file.to_string_lossy().to_string(),
+            alive: is_alive(&Addr::tcp(host, port)),
+        };
+
+        if port_file.alive {
+            return Some(port_file);
+        }
+        stale = stale.or(Some(port_file));
+    }
+
+    stale
+}
+
+/// Finds port of nrepl for `file` walking up from its directory to the project root, then
+/// from `cwd`, the working directory of the caller. Ports are probed on `host`
+pub fn discover(file: Option<&Path>, cwd: &Path, host: &str) -> Option<PortFile> {
+    let mut dirs = vec![];
+
+    if let Some(path) = file.and_then(|f| f.canonicalize().ok()) {
+        let dir = if path.is_dir() {
+            Some(path.as_path())
+        } else {
+            path.parent()
+        };
+        dirs.extend(dir.map(search_dirs).unwrap_or_default());
+    }
+    for dir in search_dirs(cwd) {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+
+    discover_in(&dirs, host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::net::TcpListener;
+
+    #[test]
+    fn discover_test() {
+        let dir = std::env::temp_dir().join(format!("unrepl-port-test-{}", std::process::id()));
+        let sub = dir.join("src/foo");
+        fs::create_dir_all(&sub).unwrap();
+        fs::create_dir_all(dir.join(".shadow-cljs")).unwrap();
+        fs::write(dir.join("deps.edn"), "{}").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stale = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stale_port = stale.local_addr().unwrap().port();
+        drop(stale);
+
+        let dirs = search_dirs(&sub);
+        assert_eq!(dirs, vec![sub.clone(), dir.join("src"), dir.clone()]);
+        assert_eq!(discover_in(&dirs, "127.0.0.1"), None);
+
+        fs::write(dir.join(".nrepl-port"), stale_port.to_string()).unwrap();
+        let found = discover_in(&dirs, "127.0.0.1").unwrap();
+        assert_eq!((found.port, found.alive), (stale_port, false));
+
+        let shadow_file = dir.join(".shadow-cljs/nrepl.port");
+        fs::write(&shadow_file, format!("{}\n", port)).unwrap();
+        assert_eq!(
+            discover_in(&dirs, "localhost"),
+            Some(PortFile {
+                port,
+                file: shadow_file.to_string_lossy().to_string(),
+                alive: true,
+            })
+        );
+
+        fs::remove_dir_all(&dir).unwrap_or_default();
+    }
+}
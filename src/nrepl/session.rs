@@ -1,13 +1,15 @@
+//! Module for maintaining persistent session-id within single nrepl connection
+
+#![allow(non_local_definitions)]
+
 use crate::config;
-use crate::config::Session;
+use crate::config::{Session, SessionKind};
 use crate::nrepl;
 use crate::nrepl::NreplOp;
 use failure::{Error as StdError, Fail};
-use nrepl::ops::{CloneSession, Describe, LsSessions};
+use nrepl::ops::{CloneSession, Describe, Eval, LsSessions};
 use serde_bencode::value::Value as BencodeValue;
 
-///! Module for maintaining persistent session-id within single nrepl connection
-
 #[derive(Debug, Fail)]
 pub enum Error {
     #[fail(display = "io error while managing session data: {}", ioerr)]
@@ -18,6 +20,10 @@ pub enum Error {
     BadSessionIdValue { bencode: BencodeValue },
     #[fail(display = "config error: {}", cfgerr)]
     ConfigError { cfgerr: config::Error },
+    #[fail(display = "no ClojureScript session, start it with `unrepl cljs`")]
+    NoCljsSession,
+    #[fail(display = "failed to upgrade session to ClojureScript: {}", ex)]
+    CljsUpgradeFailed { ex: String },
 }
 
 impl From<std::io::Error> for Error {
@@ -32,6 +38,9 @@ impl From<config::Error> for Error {
     }
 }
 
+/// Setting with kind of session used for `.cljc` files
+const CLJC_SETTING: &str = "cljc-session";
+
 fn create_session(nrepl: &nrepl::NreplStream) -> Result<Session, StdError> {
     let id = CloneSession::new(None).send(nrepl)?;
     let describe = Describe::new(false).send(nrepl)?;
@@ -43,6 +52,29 @@ fn create_session(nrepl: &nrepl::NreplStream) -> Result<Session, StdError> {
     ))
 }
 
+/// Clones a new session and evaluates `upgrade_code` in it, like
+/// `(cider.piggieback/cljs-repl ...)` or `(shadow.cljs.devtools.api/nrepl-select ...)`
+fn create_cljs_session(
+    nrepl: &nrepl::NreplStream,
+    upgrade_code: &str,
+) -> Result<Session, StdError> {
+    let session = create_session(nrepl)?
+        .with_kind(SessionKind::Cljs)
+        .with_upgrade_code(Some(upgrade_code.to_string()));
+
+    let res = Eval::new(session.clone(), upgrade_code.to_string()).send(nrepl)?;
+
+    if let Some(ex) = res.root_ex.or(res.ex) {
+        let err = res.err.join("");
+        return Err(Error::CljsUpgradeFailed {
+            ex: if err.is_empty() { ex } else { err },
+        }
+        .into());
+    }
+
+    Ok(session)
+}
+
 fn session_id_exists(n: &nrepl::NreplStream, session_id: &String) -> Result<bool, StdError> {
     let op = LsSessions::new();
 
@@ -57,17 +89,75 @@ fn session_id_exists(n: &nrepl::NreplStream, session_id: &String) -> Result<bool
 
 /// Searches for a known session in nrepl otherwise creates a new one
 pub fn get_existing_session_id(n: &nrepl::NreplStream) -> Result<Session, StdError> {
-    let mb_session = config::load_session(n.addr_string())?;
+    get_session(n, SessionKind::Clj)
+}
 
-    if let Some(existing_session) = mb_session {
+/// Same as `get_existing_session_id`, but for a session of `kind`. ClojureScript session is
+/// recreated with the code it was started with, so it has to be started once by `start_cljs`
+pub fn get_session(n: &nrepl::NreplStream, kind: SessionKind) -> Result<Session, StdError> {
+    if let Some(session) = n.cached_session(kind) {
+        return Ok(session);
+    }
+
+    let session = find_or_create_session(n, kind)?;
+    n.cache_session(session.clone());
+
+    Ok(session)
+}
+
+fn find_or_create_session(n: &nrepl::NreplStream, kind: SessionKind) -> Result<Session, StdError> {
+    let mb_session = config::load_session(n.addr_string(), kind)?;
+
+    if let Some(existing_session) = &mb_session {
         if session_id_exists(n, &existing_session.id())? {
-            return Ok(existing_session);
+            return Ok(existing_session.clone());
         }
     }
 
-    let new_session = create_session(n)?;
+    let new_session = match kind {
+        SessionKind::Clj => create_session(n)?,
+        SessionKind::Cljs => match mb_session.as_ref().and_then(|s| s.upgrade_code()) {
+            Some(upgrade_code) => create_cljs_session(n, upgrade_code)?,
+            None => return Err(Error::NoCljsSession.into()),
+        },
+    };
 
     config::save_session(&new_session)?;
 
     Ok(new_session)
 }
+
+/// Starts a new ClojureScript session with `upgrade_code` and persists it for the address
+pub fn start_cljs(n: &nrepl::NreplStream, upgrade_code: &str) -> Result<Session, StdError> {
+    let session = create_cljs_session(n, upgrade_code)?;
+
+    config::save_session(&session)?;
+    n.cache_session(session.clone());
+
+    Ok(session)
+}
+
+/// Kind of session `.cljc` files are evaluated in
+pub fn cljc_kind(n: &nrepl::NreplStream) -> Result<SessionKind, StdError> {
+    Ok(config::load_setting(&n.addr_string(), CLJC_SETTING)?
+        .and_then(|kind| SessionKind::from_name(&kind))
+        .unwrap_or(SessionKind::Clj))
+}
+
+pub fn set_cljc_kind(n: &nrepl::NreplStream, kind: SessionKind) -> Result<(), StdError> {
+    config::save_setting(&n.addr_string(), CLJC_SETTING, kind.name())
+}
+
+/// Picks session by extension of `file`: `.cljs` files go to ClojureScript session, `.cljc` to
+/// the one chosen by `set_cljc_kind` and everything else to Clojure session
+pub fn get_file_session(n: &nrepl::NreplStream, file: Option<&str>) -> Result<Session, StdError> {
+    get_session(n, file_kind(n, file)?)
+}
+
+pub fn file_kind(n: &nrepl::NreplStream, file: Option<&str>) -> Result<SessionKind, StdError> {
+    match file {
+        Some(f) if f.ends_with(".cljs") => Ok(SessionKind::Cljs),
+        Some(f) if f.ends_with(".cljc") => cljc_kind(n),
+        _ => Ok(SessionKind::Clj),
+    }
+}
@@ -0,0 +1,201 @@
+//! Sockets nrepl listens on: TCP sockets of local or remote hosts and Unix domain sockets
+
+#![allow(non_local_definitions)]
+
+use failure::Fail;
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+const TCP_SCHEME: &str = "nrepl://";
+const UNIX_SCHEME: &str = "nrepl+unix://";
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum Error {
+    #[fail(
+        display = "Bad nrepl URI {}, expected nrepl://HOST:PORT or nrepl+unix:///PATH",
+        uri
+    )]
+    BadUri { uri: String },
+}
+
+/// Address of nrepl, written as `nrepl://host:port` or `nrepl+unix:///path/to/socket` URI.
+/// Sessions and settings are stored for the URI.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Addr {
+    Tcp { host: String, port: u16 },
+    Unix(PathBuf),
+}
+
+impl Addr {
+    pub fn tcp(host: &str, port: u16) -> Self {
+        Addr::Tcp {
+            host: host.to_string(),
+            port,
+        }
+    }
+}
+
+impl FromStr for Addr {
+    type Err = Error;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        let bad_uri = || Error::BadUri {
+            uri: uri.to_string(),
+        };
+
+        if let Some(path) = uri.strip_prefix(UNIX_SCHEME) {
+            return match path.starts_with('/') {
+                true => Ok(Addr::Unix(PathBuf::from(path))),
+                false => Err(bad_uri()),
+            };
+        }
+
+        let (host, port) = uri
+            .strip_prefix(TCP_SCHEME)
+            .and_then(|authority| authority.trim_end_matches('/').rsplit_once(':'))
+            .ok_or_else(bad_uri)?;
+        // IPv6 addresses are in brackets
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        match (host.is_empty(), port.parse::<u16>()) {
+            (false, Ok(port)) => Ok(Addr::tcp(host, port)),
+            _ => Err(bad_uri()),
+        }
+    }
+}
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Addr::Tcp { host, port } if host.contains(':') => {
+                write!(f, "{}[{}]:{}", TCP_SCHEME, host, port)
+            }
+            Addr::Tcp { host, port } => write!(f, "{}{}:{}", TCP_SCHEME, host, port),
+            Addr::Unix(path) => write!(f, "{}{}", UNIX_SCHEME, path.display()),
+        }
+    }
+}
+
+/// Connection to nrepl, its clone reads responses while the original sends ops
+pub enum Transport {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Transport {
+    pub fn connect(addr: &Addr) -> std::io::Result<Transport> {
+        match addr {
+            Addr::Tcp { host, port } => {
+                let mut last_err = None;
+
+                // Host name can resolve to several addresses, like IPv4 and IPv6 ones
+                for socket_addr in (host.as_str(), *port).to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&socket_addr, Duration::new(3, 0)) {
+                        Ok(tcp) => {
+                            tcp.set_nonblocking(false)?;
+                            return Ok(Transport::Tcp(tcp));
+                        }
+                        Err(e) => last_err = Some(e),
+                    }
+                }
+
+                Err(last_err.unwrap_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "host has no addresses")
+                }))
+            }
+            Addr::Unix(path) => Ok(Transport::Unix(UnixStream::connect(path)?)),
+        }
+    }
+
+    pub fn try_clone(&self) -> std::io::Result<Transport> {
+        match self {
+            Transport::Tcp(tcp) => Ok(Transport::Tcp(tcp.try_clone()?)),
+            Transport::Unix(unix) => Ok(Transport::Unix(unix.try_clone()?)),
+        }
+    }
+
+    /// Closes connection for all clones, so reading clone sees the end of stream
+    pub fn shutdown(&self) -> std::io::Result<()> {
+        match self {
+            Transport::Tcp(tcp) => tcp.shutdown(Shutdown::Both),
+            Transport::Unix(unix) => unix.shutdown(Shutdown::Both),
+        }
+    }
+}
+
+impl Read for Transport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Transport::Tcp(tcp) => tcp.read(buf),
+            Transport::Unix(unix) => unix.read(buf),
+        }
+    }
+}
+
+impl Write for Transport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Transport::Tcp(tcp) => tcp.write(buf),
+            Transport::Unix(unix) => unix.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Transport::Tcp(tcp) => tcp.flush(),
+            Transport::Unix(unix) => unix.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addr_test() {
+        let parse = |uri: &str| uri.parse::<Addr>();
+
+        assert_eq!(
+            parse("nrepl://localhost:7888"),
+            Ok(Addr::tcp("localhost", 7888))
+        );
+        assert_eq!(
+            parse("nrepl://10.0.0.2:7888/"),
+            Ok(Addr::tcp("10.0.0.2", 7888))
+        );
+        assert_eq!(parse("nrepl://[::1]:7888"), Ok(Addr::tcp("::1", 7888)));
+        assert_eq!(
+            parse("nrepl+unix:///tmp/nrepl.sock"),
+            Ok(Addr::Unix(PathBuf::from("/tmp/nrepl.sock")))
+        );
+
+        for uri in &[
+            "nrepl://[::1]:7888",
+            "nrepl://localhost:7888",
+            "nrepl+unix:///tmp/nrepl.sock",
+        ] {
+            assert_eq!(parse(uri).unwrap().to_string(), *uri);
+        }
+
+        for uri in &[
+            "localhost:7888",
+            "nrepl://localhost",
+            "nrepl://:7888",
+            "nrepl://localhost:port",
+            "nrepl+unix://nrepl.sock",
+        ] {
+            assert_eq!(
+                parse(uri),
+                Err(Error::BadUri {
+                    uri: uri.to_string()
+                })
+            );
+        }
+    }
+}
@@ -0,0 +1,362 @@
+//! Reads `ns` declaration out of Clojure source without asking nrepl
+
+#![allow(non_local_definitions)]
+
+use crate::config::SessionKind;
+use crate::reader::{self, Form, Node, Pos};
+use failure::Fail;
+use serde::Serialize;
+use std::io::Read;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "io error while reading source: {}", ioerr)]
+    IOError { ioerr: std::io::Error },
+    #[fail(display = "failed to read source: {}", error)]
+    ReadError { error: reader::Error },
+}
+
+impl From<std::io::Error> for Error {
+    fn from(ioerr: std::io::Error) -> Self {
+        Self::IOError { ioerr }
+    }
+}
+
+impl From<reader::Error> for Error {
+    fn from(error: reader::Error) -> Self {
+        Self::ReadError { error }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Refer {
+    All,
+    Only(Vec<String>),
+}
+
+/// Single namespace required by `:require` or `:use`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Require {
+    pub ns: String,
+    pub alias: Option<String>,
+    pub refer: Option<Refer>,
+    /// Position of namespace name in the libspec
+    pub pos: Pos,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NsDecl {
+    pub name: String,
+    /// Position of namespace name
+    pub pos: Pos,
+    /// Docstring following namespace name
+    pub doc: Option<String>,
+    pub requires: Vec<Require>,
+    /// Fully qualified names of imported classes
+    pub imports: Vec<String>,
+    /// `clojure.core` vars excluded with `(:refer-clojure :exclude [...])`
+    pub refer_clojure_exclude: Vec<String>,
+    /// Set when only some `clojure.core` vars are referred with `(:refer-clojure :only [...])`
+    pub refer_clojure_only: Option<Vec<String>>,
+}
+
+impl NsDecl {
+    /// Returns namespace `alias` stands for
+    pub fn resolve_alias(&self, alias: &str) -> Option<&str> {
+        self.requires
+            .iter()
+            .find(|r| r.alias.as_deref() == Some(alias))
+            .map(|r| r.ns.as_str())
+    }
+}
+
+fn symbol_names(nodes: &[Node]) -> Vec<String> {
+    nodes.iter().filter_map(|n| n.as_symbol()).collect()
+}
+
+fn is_keyword(node: Option<&Node>) -> bool {
+    matches!(node.map(|n| &n.form), Some(Form::Keyword { .. }))
+}
+
+/// Parses a single libspec: `foo.bar`, `[foo.bar :as b :refer [x]]` or a prefix list
+fn parse_libspec(
+    node: &Node,
+    prefix: Option<&str>,
+    is_use: bool,
+    features: &[&str],
+    out: &mut Vec<Require>,
+) {
+    let full_name = |name: String| match prefix {
+        Some(prefix) => format!("{}.{}", prefix, name),
+        None => name,
+    };
+
+    match &node.form {
+        Form::Symbol { .. } => out.push(Require {
+            ns: full_name(node.as_symbol().unwrap()),
+            alias: None,
+            refer: if is_use { Some(Refer::All) } else { None },
+            pos: node.start,
+        }),
+        Form::Vector(items) | Form::List(items) => {
+            let items = reader::resolve_conditionals(items, features);
+
+            let name = match items.first().and_then(|n| n.as_symbol()) {
+                Some(name) => full_name(name),
+                None => return,
+            };
+
+            // Prefix list: `[foo bar [baz :as b]]`
+            if items.len() > 1 && !is_keyword(items.get(1)) {
+                for item in items[1..].iter() {
+                    parse_libspec(item, Some(&name), is_use, features, out);
+                }
+                return;
+            }
+
+            let mut require = Require {
+                ns: name,
+                alias: None,
+                refer: if is_use { Some(Refer::All) } else { None },
+                pos: items[0].start,
+            };
+
+            for opt in items[1..].chunks(2) {
+                let (key, value) = match opt {
+                    [key, value] => (key.as_keyword().unwrap_or_default(), value),
+                    _ => continue,
+                };
+
+                match (key.as_str(), &value.form) {
+                    ("as", Form::Symbol { .. }) | ("as-alias", Form::Symbol { .. }) => {
+                        require.alias = value.as_symbol()
+                    }
+                    ("refer", Form::Keyword { .. })
+                        if value.as_keyword().as_deref() == Some("all") =>
+                    {
+                        require.refer = Some(Refer::All)
+                    }
+                    ("refer", Form::Vector(syms))
+                    | ("refer", Form::List(syms))
+                    | ("only", Form::Vector(syms))
+                    | ("only", Form::List(syms)) => {
+                        require.refer = Some(Refer::Only(symbol_names(syms)))
+                    }
+                    _ => {}
+                }
+            }
+
+            out.push(require);
+        }
+        _ => {}
+    }
+}
+
+fn parse_import(node: &Node, out: &mut Vec<String>) {
+    match &node.form {
+        Form::Symbol { .. } => out.push(node.as_symbol().unwrap()),
+        Form::Vector(items) | Form::List(items) => {
+            if let Some(package) = items.first().and_then(|n| n.as_symbol()) {
+                for class in symbol_names(&items[1..]) {
+                    out.push(format!("{}.{}", package, class));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Parses `(ns ...)` form, `features` pick branches of reader conditionals
+pub fn parse_ns_form(items: &[Node], features: &[&str]) -> Option<NsDecl> {
+    let items = reader::resolve_conditionals(items, features);
+
+    let name = items.get(1).and_then(|n| n.as_symbol())?;
+
+    let mut decl = NsDecl {
+        name,
+        pos: items[1].start,
+        doc: items.get(2).and_then(|n| n.as_str()).map(String::from),
+        requires: vec![],
+        imports: vec![],
+        refer_clojure_exclude: vec![],
+        refer_clojure_only: None,
+    };
+
+    for clause in items[2..].iter() {
+        if let Form::List(clause_items) = &clause.form {
+            let clause_items = reader::resolve_conditionals(clause_items, features);
+
+            match clause_items.first().and_then(|n| n.as_keyword()).as_deref() {
+                Some(k) if k == "require" || k == "use" => {
+                    for item in clause_items[1..].iter() {
+                        parse_libspec(item, None, k == "use", features, &mut decl.requires);
+                    }
+                }
+                Some("import") => {
+                    for item in clause_items[1..].iter() {
+                        parse_import(item, &mut decl.imports);
+                    }
+                }
+                Some("refer-clojure") => {
+                    for opt in clause_items[1..].chunks(2) {
+                        if let [key, Node {
+                            form: Form::Vector(syms),
+                            ..
+                        }] = opt
+                        {
+                            match key.as_keyword().as_deref() {
+                                Some("exclude") => decl.refer_clojure_exclude = symbol_names(syms),
+                                Some("only") => decl.refer_clojure_only = Some(symbol_names(syms)),
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Some(decl)
+}
+
+/// Tells if `node` is `(ns ...)` form
+pub fn is_ns_form(node: &Node) -> bool {
+    match &node.form {
+        Form::List(items) => items.first().and_then(|n| n.as_symbol()).as_deref() == Some("ns"),
+        _ => false,
+    }
+}
+
+/// Finds the first `ns` form in `source` and parses it as Clojure
+pub fn read_ns_decl(source: &str) -> Result<Option<NsDecl>, Error> {
+    let mut reader = reader::Reader::new(source);
+
+    while let Some(node) = reader.read()? {
+        if is_ns_form(&node) {
+            return Ok(parse_ns_form(
+                node.children().unwrap(),
+                &SessionKind::Clj.features(),
+            ));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Same as `read_ns_decl`, but reads source from `path`, `-` stands for stdin
+pub fn read_file_ns_decl(path: &str) -> Result<Option<NsDecl>, Error> {
+    let mut source = String::new();
+
+    if path == "-" {
+        std::io::stdin().read_to_string(&mut source)?;
+    } else {
+        std::fs::File::open(path)?.read_to_string(&mut source)?;
+    }
+
+    read_ns_decl(&source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_ns_name_test() {
+        let src = ";; comment\n(ns ^{:doc \"Docs\"} foo.bar\n  \"Docstring\")\n(def x 1)";
+
+        let decl = read_ns_decl(src).unwrap().unwrap();
+
+        assert_eq!(decl.name, "foo.bar");
+        assert_eq!(decl.doc.as_deref(), Some("Docstring"));
+        assert_eq!((decl.pos.line, decl.pos.column), (2, 20));
+        assert_eq!(read_ns_decl("(def x 1)").unwrap(), None);
+    }
+
+    #[test]
+    fn reads_requires_test() {
+        let src = r#"
+(ns foo.core
+  (:require [clojure.string :as str]
+            [clojure.set :refer [union difference]]
+            [foo.util :refer :all]
+            clojure.walk
+            (foo [a :as fa] b)
+            #?(:clj [foo.jvm :as jvm] :cljs [foo.js :as js]))
+  (:use [foo.old :only [legacy]])
+  (:import (java.io File Reader)
+           java.util.UUID))
+"#;
+        let decl = read_ns_decl(src).unwrap().unwrap();
+        let requires = decl
+            .requires
+            .iter()
+            .map(|r| Require {
+                pos: Pos::default(),
+                ..r.clone()
+            })
+            .collect::<Vec<Require>>();
+
+        assert_eq!(
+            requires,
+            vec![
+                Require {
+                    ns: "clojure.string".to_string(),
+                    alias: Some("str".to_string()),
+                    refer: None,
+                    pos: Pos::default()
+                },
+                Require {
+                    ns: "clojure.set".to_string(),
+                    alias: None,
+                    refer: Some(Refer::Only(vec![
+                        "union".to_string(),
+                        "difference".to_string()
+                    ])),
+                    pos: Pos::default()
+                },
+                Require {
+                    ns: "foo.util".to_string(),
+                    alias: None,
+                    refer: Some(Refer::All),
+                    pos: Pos::default()
+                },
+                Require {
+                    ns: "clojure.walk".to_string(),
+                    alias: None,
+                    refer: None,
+                    pos: Pos::default()
+                },
+                Require {
+                    ns: "foo.a".to_string(),
+                    alias: Some("fa".to_string()),
+                    refer: None,
+                    pos: Pos::default()
+                },
+                Require {
+                    ns: "foo.b".to_string(),
+                    alias: None,
+                    refer: None,
+                    pos: Pos::default()
+                },
+                Require {
+                    ns: "foo.jvm".to_string(),
+                    alias: Some("jvm".to_string()),
+                    refer: None,
+                    pos: Pos::default()
+                },
+                Require {
+                    ns: "foo.old".to_string(),
+                    alias: None,
+                    refer: Some(Refer::Only(vec!["legacy".to_string()])),
+                    pos: Pos::default()
+                },
+            ]
+        );
+        assert_eq!(
+            decl.imports,
+            vec!["java.io.File", "java.io.Reader", "java.util.UUID"]
+        );
+        assert_eq!(decl.resolve_alias("str"), Some("clojure.string"));
+    }
+}
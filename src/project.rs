@@ -0,0 +1,204 @@
+//! Layout of Clojure project: its root directory and source paths
+
+use crate::reader::{self, Form, Node};
+use std::path::{Path, PathBuf};
+
+/// Files found in the root directory of a project
+pub const ROOT_MARKERS: [&str; 5] = [
+    "deps.edn",
+    "project.clj",
+    "shadow-cljs.edn",
+    "build.boot",
+    "bb.edn",
+];
+
+const DEFAULT_SOURCE_PATHS: [&str; 2] = ["src", "test"];
+
+const SOURCE_EXTENSIONS: [&str; 3] = ["clj", "cljs", "cljc"];
+
+/// The closest directory to `start` having one of `ROOT_MARKERS`
+pub fn find_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| ROOT_MARKERS.iter().any(|m| dir.join(m).is_file()))
+        .map(|dir| dir.to_path_buf())
+}
+
+/// Root of project containing `file`, or its directory when there's no project. `-` (stdin)
+/// belongs to the current directory.
+pub fn file_root(file: &str) -> Result<PathBuf, std::io::Error> {
+    let dir = if file == "-" {
+        std::env::current_dir()?
+    } else {
+        let path = Path::new(file).canonicalize()?;
+        path.parent().map(Path::to_path_buf).unwrap_or(path)
+    };
+
+    Ok(find_root(&dir).unwrap_or(dir))
+}
+
+fn map_get<'a>(items: &'a [Node], key: &str) -> Option<&'a Node> {
+    items
+        .chunks(2)
+        .find(|kv| kv[0].as_keyword().as_deref() == Some(key))
+        .and_then(|kv| kv.get(1))
+}
+
+fn strings(node: Option<&Node>) -> Vec<String> {
+    node.and_then(|n| n.children())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|i| i.as_str())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// `:paths` and `:extra-paths` of aliases in `deps.edn`
+fn deps_paths(source: &str) -> Vec<String> {
+    let items = match reader::read_str(source) {
+        Ok(Some(Node {
+            form: Form::Map(items),
+            ..
+        })) => items,
+        _ => return vec![],
+    };
+    let mut paths = strings(map_get(&items, "paths"));
+
+    if let Some(Form::Map(aliases)) = map_get(&items, "aliases").map(|a| &a.form) {
+        for alias in aliases.iter().skip(1).step_by(2) {
+            if let Form::Map(alias) = &alias.form {
+                paths.extend(strings(map_get(alias, "extra-paths")));
+            }
+        }
+    }
+
+    paths
+}
+
+/// `:source-paths` and `:test-paths` of `defproject` or `:source-paths` of `shadow-cljs.edn`
+fn lein_paths(source: &str) -> Vec<String> {
+    let node = match reader::read_str(source) {
+        Ok(Some(node)) => node,
+        _ => return vec![],
+    };
+    let items = match &node.form {
+        Form::List(items) if items.len() > 3 => &items[3..],
+        Form::Map(items) => &items[..],
+        _ => return vec![],
+    };
+
+    let mut paths = strings(map_get(items, "source-paths"));
+    paths.extend(strings(map_get(items, "test-paths")));
+    paths
+}
+
+/// Existing source directories of project in `root`
+pub fn source_paths(root: &Path) -> Vec<PathBuf> {
+    let read = |name: &str| std::fs::read_to_string(root.join(name)).unwrap_or_default();
+
+    let mut paths = deps_paths(&read("deps.edn"));
+    paths.extend(lein_paths(&read("project.clj")));
+    paths.extend(lein_paths(&read("shadow-cljs.edn")));
+    if paths.is_empty() {
+        paths = DEFAULT_SOURCE_PATHS.iter().map(|p| p.to_string()).collect();
+    }
+
+    let mut dirs: Vec<PathBuf> = vec![];
+    for path in paths {
+        let dir = root.join(path);
+        if dir.is_dir() && !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+
+    dirs
+}
+
+/// Tells if `path` is a Clojure or ClojureScript file by its extension
+pub fn is_source_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| SOURCE_EXTENSIONS.iter().any(|s| e == *s))
+}
+
+fn collect_sources(dir: &Path, out: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    // Symlinked directories are not followed, they may form loops
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            collect_sources(&path, out);
+        } else if is_source_file(&path) {
+            out.push(path);
+        }
+    }
+}
+
+/// Clojure and ClojureScript files in source paths of project in `root`
+pub fn source_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+
+    for dir in source_paths(root) {
+        collect_sources(&dir, &mut files);
+    }
+
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Clojure and ClojureScript files in `dir` and its subdirectories
+pub fn dir_source_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    collect_sources(dir, &mut files);
+    files.sort();
+    files
+}
+
+/// Path of `file` relative to the source path of its project containing it, the path Clojure
+/// loads it by from classpath, like `foo/core.clj`
+pub fn resource_path(file: &Path) -> Option<String> {
+    let root = find_root(file.parent()?)?;
+
+    source_paths(&root)
+        .iter()
+        .find_map(|dir| file.strip_prefix(dir).ok())
+        .map(|resource| resource.to_string_lossy().to_string())
+}
+
+/// File of classpath `resource` in source paths of project in `root`
+pub fn find_resource(root: &Path, resource: &str) -> Option<PathBuf> {
+    source_paths(root)
+        .into_iter()
+        .map(|dir| dir.join(resource))
+        .find(|file| file.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_test() {
+        let deps = r#"{:paths ["src" "resources"]
+                       :aliases {:test {:extra-paths ["test"]} :dev {:main-opts []}}}"#;
+        let project = r#"(defproject foo "0.1.0"
+                           :dependencies [[org.clojure/clojure "1.10.1"]]
+                           :source-paths ["src/clj"] :test-paths ["test/clj"])"#;
+
+        assert_eq!(deps_paths(deps), vec!["src", "resources", "test"]);
+        assert_eq!(lein_paths(project), vec!["src/clj", "test/clj"]);
+        assert_eq!(
+            lein_paths("(defproject foo \"0.1.0\")"),
+            Vec::<String>::new()
+        );
+        assert_eq!(deps_paths("{:deps {}}"), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,1011 @@
+//! Reader for Clojure and EDN source, produces forms annotated with their positions
+
+#![allow(non_local_definitions)]
+
+use failure::Fail;
+use serde::Serialize;
+use serde_json::value::Value as JsonValue;
+use std::fmt;
+
+/// Position in source, `line` and `column` start from 1 the same way editors count them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize)]
+pub struct Pos {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Pos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum Error {
+    #[fail(display = "{}: unexpected end of input", _0)]
+    UnexpectedEof(Pos),
+    #[fail(display = "{}: unexpected `{}`", _0, _1)]
+    UnexpectedChar(Pos, char),
+    #[fail(display = "{}: invalid number `{}`", _0, _1)]
+    InvalidNumber(Pos, String),
+    #[fail(display = "{}: invalid character literal `\\{}`", _0, _1)]
+    InvalidChar(Pos, String),
+    #[fail(display = "{}: invalid token `{}`", _0, _1)]
+    InvalidToken(Pos, String),
+    #[fail(display = "{}: map literal must contain an even number of forms", _0)]
+    OddMapForms(Pos),
+}
+
+impl Error {
+    pub fn pos(&self) -> Pos {
+        match self {
+            Self::UnexpectedEof(pos)
+            | Self::UnexpectedChar(pos, _)
+            | Self::InvalidNumber(pos, _)
+            | Self::InvalidChar(pos, _)
+            | Self::InvalidToken(pos, _)
+            | Self::OddMapForms(pos) => *pos,
+        }
+    }
+
+    /// Input ended in the middle of a form, more input could make it readable
+    pub fn is_eof(&self) -> bool {
+        matches!(self, Self::UnexpectedEof(_))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    Int(i64),
+    /// Integer with `N` suffix or not fitting into `i64`
+    BigInt(String),
+    Float(f64),
+    /// Decimal with `M` suffix
+    BigDec(String),
+    Ratio(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Form {
+    Nil,
+    Bool(bool),
+    Number(Number),
+    Str(String),
+    Char(char),
+    Regex(String),
+    Symbol {
+        ns: Option<String>,
+        name: String,
+    },
+    /// `auto` is for `::kw` and `::alias/kw`, which are resolved against current ns
+    Keyword {
+        ns: Option<String>,
+        name: String,
+        auto: bool,
+    },
+    List(Vec<Node>),
+    Vector(Vec<Node>),
+    /// Keys and values in order they appear in source
+    Map(Vec<Node>),
+    Set(Vec<Node>),
+    /// `#:ns{...}`, or `#::{...}`/`#::alias{...}` when `auto`
+    NamespacedMap {
+        ns: Option<String>,
+        auto: bool,
+        forms: Vec<Node>,
+    },
+    Quote(Box<Node>),
+    SyntaxQuote(Box<Node>),
+    Unquote(Box<Node>),
+    UnquoteSplicing(Box<Node>),
+    Deref(Box<Node>),
+    /// `#'foo`
+    Var(Box<Node>),
+    /// `#(...)`, holds forms of the body list
+    Fn(Vec<Node>),
+    /// `#=(...)`
+    Eval(Box<Node>),
+    /// `##Inf`, `##-Inf`, `##NaN`
+    SymbolicValue(String),
+    /// `#inst "..."`, `#uuid "..."` and any other `#tag form`
+    Tagged {
+        tag: String,
+        form: Box<Node>,
+    },
+    /// `#?(...)` or `#?@(...)` when `splicing`, holds feature/form pairs
+    ReaderConditional {
+        splicing: bool,
+        forms: Vec<Node>,
+    },
+}
+
+/// Form together with its position and metadata attached with `^`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub form: Form,
+    pub start: Pos,
+    pub end: Pos,
+    pub meta: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(form: Form, start: Pos, end: Pos) -> Self {
+        Self {
+            form,
+            start,
+            end,
+            meta: vec![],
+        }
+    }
+
+    /// Full name of symbol, `ns/name` when it's qualified
+    pub fn as_symbol(&self) -> Option<String> {
+        match &self.form {
+            Form::Symbol { ns: Some(ns), name } => Some(format!("{}/{}", ns, name)),
+            Form::Symbol { ns: None, name } => Some(name.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Name of keyword without colons, `ns/name` when it's qualified
+    pub fn as_keyword(&self) -> Option<String> {
+        match &self.form {
+            Form::Keyword {
+                ns: Some(ns), name, ..
+            } => Some(format!("{}/{}", ns, name)),
+            Form::Keyword { ns: None, name, .. } => Some(name.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.form {
+            Form::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Children of list, vector, map and set
+    pub fn children(&self) -> Option<&Vec<Node>> {
+        match &self.form {
+            Form::List(nodes)
+            | Form::Vector(nodes)
+            | Form::Map(nodes)
+            | Form::Set(nodes)
+            | Form::Fn(nodes) => Some(nodes),
+            Form::NamespacedMap { forms, .. } => Some(forms),
+            _ => None,
+        }
+    }
+
+    /// Tells if metadata has `^:key` or `^{:key true}`
+    pub fn has_meta_flag(&self, key: &str) -> bool {
+        self.meta.iter().any(|m| match &m.form {
+            Form::Keyword { .. } => m.as_keyword().as_deref() == Some(key),
+            Form::Map(forms) => forms.chunks(2).any(|kv| {
+                kv[0].as_keyword().as_deref() == Some(key)
+                    && kv.get(1).is_some_and(|v| v.form == Form::Bool(true))
+            }),
+            _ => false,
+        })
+    }
+
+    /// Converts EDN data into JSON, keywords and symbols become strings
+    pub fn to_json(&self) -> JsonValue {
+        match &self.form {
+            Form::Nil => JsonValue::Null,
+            Form::Bool(b) => JsonValue::Bool(*b),
+            Form::Number(Number::Int(n)) => JsonValue::from(*n),
+            Form::Number(Number::Float(n)) => JsonValue::from(*n),
+            Form::Str(s) => JsonValue::String(s.to_string()),
+            Form::List(nodes) | Form::Vector(nodes) | Form::Set(nodes) => {
+                JsonValue::Array(nodes.iter().map(|n| n.to_json()).collect())
+            }
+            Form::Map(forms) | Form::NamespacedMap { forms, .. } => JsonValue::Object(
+                forms
+                    .chunks(2)
+                    .map(|kv| {
+                        let key = match &kv[0].form {
+                            Form::Str(s) => s.to_string(),
+                            Form::Keyword { .. } => kv[0].as_keyword().unwrap(),
+                            _ => kv[0].to_string(),
+                        };
+                        (
+                            key,
+                            kv.get(1).map(|v| v.to_json()).unwrap_or(JsonValue::Null),
+                        )
+                    })
+                    .collect(),
+            ),
+            Form::Tagged { form, .. } => form.to_json(),
+            _ => JsonValue::String(self.to_string()),
+        }
+    }
+}
+
+fn write_seq(f: &mut fmt::Formatter<'_>, open: &str, nodes: &[Node], close: &str) -> fmt::Result {
+    write!(f, "{}", open)?;
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", node)?;
+    }
+    write!(f, "{}", close)
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.form {
+            Form::Nil => write!(f, "nil"),
+            Form::Bool(b) => write!(f, "{}", b),
+            Form::Number(Number::Int(n)) => write!(f, "{}", n),
+            Form::Number(Number::BigInt(n)) => write!(f, "{}N", n),
+            Form::Number(Number::Float(n)) => write!(f, "{:?}", n),
+            Form::Number(Number::BigDec(n)) => write!(f, "{}M", n),
+            Form::Number(Number::Ratio(n, d)) => write!(f, "{}/{}", n, d),
+            Form::Str(s) => write!(f, "{:?}", s),
+            Form::Char(c) => match c {
+                '\n' => write!(f, "\\newline"),
+                ' ' => write!(f, "\\space"),
+                '\t' => write!(f, "\\tab"),
+                '\r' => write!(f, "\\return"),
+                c => write!(f, "\\{}", c),
+            },
+            Form::Regex(r) => write!(f, "#\"{}\"", r),
+            Form::Symbol { .. } => write!(f, "{}", self.as_symbol().unwrap()),
+            Form::Keyword { auto, .. } => {
+                write!(
+                    f,
+                    "{}{}",
+                    if *auto { "::" } else { ":" },
+                    self.as_keyword().unwrap()
+                )
+            }
+            Form::List(nodes) => write_seq(f, "(", nodes, ")"),
+            Form::Vector(nodes) => write_seq(f, "[", nodes, "]"),
+            Form::Map(nodes) => write_seq(f, "{", nodes, "}"),
+            Form::Set(nodes) => write_seq(f, "#{", nodes, "}"),
+            Form::NamespacedMap { ns, auto, forms } => {
+                let prefix = format!(
+                    "#{}{}{{",
+                    if *auto { "::" } else { ":" },
+                    ns.as_deref().unwrap_or("")
+                );
+                write_seq(f, &prefix, forms, "}")
+            }
+            Form::Quote(node) => write!(f, "'{}", node),
+            Form::SyntaxQuote(node) => write!(f, "`{}", node),
+            Form::Unquote(node) => write!(f, "~{}", node),
+            Form::UnquoteSplicing(node) => write!(f, "~@{}", node),
+            Form::Deref(node) => write!(f, "@{}", node),
+            Form::Var(node) => write!(f, "#'{}", node),
+            Form::Fn(nodes) => write_seq(f, "#(", nodes, ")"),
+            Form::Eval(node) => write!(f, "#={}", node),
+            Form::SymbolicValue(v) => write!(f, "##{}", v),
+            Form::Tagged { tag, form } => write!(f, "#{} {}", tag, form),
+            Form::ReaderConditional { splicing, forms } => {
+                write_seq(f, if *splicing { "#?@(" } else { "#?(" }, forms, ")")
+            }
+        }
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    c.is_whitespace() || c == ','
+}
+
+fn is_terminating(c: char) -> bool {
+    is_whitespace(c) || "()[]{}\"';@^`~\\".contains(c)
+}
+
+pub struct Reader {
+    chars: Vec<char>,
+    idx: usize,
+    pos: Pos,
+}
+
+impl Reader {
+    pub fn new(source: &str) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            idx: 0,
+            pos: Pos {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.idx).copied()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.chars.get(self.idx + n).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+
+        self.idx += 1;
+        self.pos.offset += c.len_utf8();
+
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        } else {
+            self.pos.column += 1;
+        }
+
+        Some(c)
+    }
+
+    fn expect_bump(&mut self) -> Result<char, Error> {
+        self.bump().ok_or(Error::UnexpectedEof(self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if is_whitespace(c) {
+                self.bump();
+            } else if c == ';' || (c == '#' && self.peek_nth(1) == Some('!')) {
+                while let Some(c) = self.bump() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Current position, after skipping whitespace and comments
+    pub fn pos(&mut self) -> Pos {
+        self.skip_whitespace();
+        self.pos
+    }
+
+    pub fn is_eof(&mut self) -> bool {
+        self.skip_whitespace();
+        self.peek().is_none()
+    }
+
+    /// Reads the rest of symbol-like token which starts with `token`
+    fn read_token(&mut self, mut token: String) -> String {
+        while let Some(c) = self.peek() {
+            // `'` is allowed inside of symbols: `foo'`
+            if is_terminating(c) && (c != '\'' || token.is_empty()) {
+                break;
+            }
+            token.push(c);
+            self.bump();
+        }
+
+        token
+    }
+
+    fn read_string_body(&mut self, raw: bool) -> Result<String, Error> {
+        let mut s = String::new();
+
+        loop {
+            let pos = self.pos;
+            match self.expect_bump()? {
+                '"' => return Ok(s),
+                '\\' if raw => {
+                    s.push('\\');
+                    s.push(self.expect_bump()?);
+                }
+                '\\' => match self.expect_bump()? {
+                    'n' => s.push('\n'),
+                    't' => s.push('\t'),
+                    'r' => s.push('\r'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'u' => {
+                        let hex: String = (0..4).filter_map(|_| self.bump()).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(std::char::from_u32)
+                            .ok_or_else(|| Error::InvalidChar(pos, format!("u{}", hex)))?;
+                        s.push(c);
+                    }
+                    c @ '0'..='7' => {
+                        let mut oct = c.to_string();
+                        while oct.len() < 3 && self.peek().is_some_and(|c| ('0'..='7').contains(&c))
+                        {
+                            oct.push(self.bump().unwrap());
+                        }
+                        let c = u32::from_str_radix(&oct, 8)
+                            .ok()
+                            .and_then(std::char::from_u32)
+                            .ok_or_else(|| Error::InvalidChar(pos, oct.clone()))?;
+                        s.push(c);
+                    }
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn read_delimited(&mut self, close: char) -> Result<Vec<Node>, Error> {
+        let mut nodes = vec![];
+
+        loop {
+            self.skip_whitespace();
+
+            match self.peek() {
+                Some(c) if c == close => {
+                    self.bump();
+                    return Ok(nodes);
+                }
+                Some(_) => {
+                    if let Some(node) = self.read_node()? {
+                        nodes.push(node);
+                    }
+                }
+                None => return Err(Error::UnexpectedEof(self.pos)),
+            }
+        }
+    }
+
+    fn read_map_forms(&mut self, start: Pos) -> Result<Vec<Node>, Error> {
+        let forms = self.read_delimited('}')?;
+
+        if forms.len() % 2 != 0 {
+            return Err(Error::OddMapForms(start));
+        }
+
+        Ok(forms)
+    }
+
+    /// Reads next form, skips forms discarded with `#_`, `None` means end of input
+    pub fn read(&mut self) -> Result<Option<Node>, Error> {
+        loop {
+            if self.is_eof() {
+                return Ok(None);
+            }
+
+            if let Some(node) = self.read_node()? {
+                return Ok(Some(node));
+            }
+        }
+    }
+
+    /// Reads next form failing on end of input
+    fn read_required(&mut self) -> Result<Node, Error> {
+        loop {
+            self.skip_whitespace();
+
+            if self.peek().is_none() {
+                return Err(Error::UnexpectedEof(self.pos));
+            }
+
+            if let Some(node) = self.read_node()? {
+                return Ok(node);
+            }
+        }
+    }
+
+    fn wrap(&mut self, f: fn(Box<Node>) -> Form) -> Result<Form, Error> {
+        Ok(f(Box::new(self.read_required()?)))
+    }
+
+    /// Reads a single node, `None` is for discarded form
+    fn read_node(&mut self) -> Result<Option<Node>, Error> {
+        self.skip_whitespace();
+
+        let start = self.pos;
+        let c = self.expect_bump()?;
+
+        let form = match c {
+            '(' => Form::List(self.read_delimited(')')?),
+            '[' => Form::Vector(self.read_delimited(']')?),
+            '{' => Form::Map(self.read_map_forms(start)?),
+            ')' | ']' | '}' => return Err(Error::UnexpectedChar(start, c)),
+            '"' => Form::Str(self.read_string_body(false)?),
+            '\\' => Form::Char(self.read_char(start)?),
+            '\'' => self.wrap(Form::Quote)?,
+            '`' => self.wrap(Form::SyntaxQuote)?,
+            '@' => self.wrap(Form::Deref)?,
+            '~' => {
+                if self.peek() == Some('@') {
+                    self.bump();
+                    self.wrap(Form::UnquoteSplicing)?
+                } else {
+                    self.wrap(Form::Unquote)?
+                }
+            }
+            '^' => {
+                let meta = self.read_required()?;
+                let mut node = self.read_required()?;
+                // Metadata applies right to left: `^:a ^:b x` has both, `:a` read first
+                node.meta.insert(0, meta);
+                return Ok(Some(node));
+            }
+            '#' => return self.read_dispatch(start),
+            ':' => self.read_keyword(start)?,
+            _ => {
+                let token = self.read_token(c.to_string());
+                self.parse_token(start, token)?
+            }
+        };
+
+        Ok(Some(Node::new(form, start, self.pos)))
+    }
+
+    fn read_char(&mut self, start: Pos) -> Result<char, Error> {
+        let first = self.expect_bump()?;
+        let token = self.read_token(first.to_string());
+
+        if token.chars().count() == 1 {
+            return Ok(first);
+        }
+
+        let c = match token.as_str() {
+            "newline" => Some('\n'),
+            "space" => Some(' '),
+            "tab" => Some('\t'),
+            "backspace" => Some('\u{8}'),
+            "formfeed" => Some('\u{c}'),
+            "return" => Some('\r'),
+            t if t.starts_with('u') && t.len() == 5 => u32::from_str_radix(&t[1..], 16)
+                .ok()
+                .and_then(std::char::from_u32),
+            t if t.starts_with('o') && t.len() > 1 && t.len() <= 4 => {
+                u32::from_str_radix(&t[1..], 8)
+                    .ok()
+                    .and_then(std::char::from_u32)
+            }
+            _ => None,
+        };
+
+        c.ok_or(Error::InvalidChar(start, token))
+    }
+
+    fn read_keyword(&mut self, start: Pos) -> Result<Form, Error> {
+        let auto = self.peek() == Some(':');
+        if auto {
+            self.bump();
+        }
+
+        let token = self.read_token(String::new());
+
+        if token.is_empty() || token.ends_with(':') || token.contains("::") {
+            return Err(Error::InvalidToken(start, format!(":{}", token)));
+        }
+
+        let (ns, name) = split_ns(&token);
+
+        Ok(Form::Keyword { ns, name, auto })
+    }
+
+    fn parse_token(&mut self, start: Pos, token: String) -> Result<Form, Error> {
+        let first = token.chars().next().unwrap();
+        let second = token.chars().nth(1);
+
+        if first.is_ascii_digit()
+            || ((first == '-' || first == '+') && second.is_some_and(|c| c.is_ascii_digit()))
+        {
+            return parse_number(&token)
+                .map(Form::Number)
+                .ok_or(Error::InvalidNumber(start, token));
+        }
+
+        match token.as_str() {
+            "nil" => return Ok(Form::Nil),
+            "true" => return Ok(Form::Bool(true)),
+            "false" => return Ok(Form::Bool(false)),
+            _ => {}
+        }
+
+        if token.ends_with(':') || token.contains("::") {
+            return Err(Error::InvalidToken(start, token));
+        }
+
+        let (ns, name) = split_ns(&token);
+
+        Ok(Form::Symbol { ns, name })
+    }
+
+    fn read_dispatch(&mut self, start: Pos) -> Result<Option<Node>, Error> {
+        let c = self.expect_bump()?;
+
+        let form = match c {
+            '_' => {
+                self.read_required()?;
+                return Ok(None);
+            }
+            '{' => Form::Set(self.read_delimited('}')?),
+            '(' => Form::Fn(self.read_delimited(')')?),
+            '"' => Form::Regex(self.read_string_body(true)?),
+            '\'' => self.wrap(Form::Var)?,
+            '=' => self.wrap(Form::Eval)?,
+            '^' => {
+                let meta = self.read_required()?;
+                let mut node = self.read_required()?;
+                node.meta.insert(0, meta);
+                return Ok(Some(node));
+            }
+            '#' => Form::SymbolicValue(self.read_token(String::new())),
+            '?' => {
+                let splicing = self.peek() == Some('@');
+                if splicing {
+                    self.bump();
+                }
+                self.skip_whitespace();
+                let pos = self.pos;
+                match self.expect_bump()? {
+                    '(' => Form::ReaderConditional {
+                        splicing,
+                        forms: self.read_delimited(')')?,
+                    },
+                    c => return Err(Error::UnexpectedChar(pos, c)),
+                }
+            }
+            ':' => {
+                let auto = self.peek() == Some(':');
+                if auto {
+                    self.bump();
+                }
+                let ns = self.read_token(String::new());
+                if ns.is_empty() && !auto {
+                    return Err(Error::InvalidToken(start, "#:".to_string()));
+                }
+                self.skip_whitespace();
+                let pos = self.pos;
+                match self.expect_bump()? {
+                    '{' => Form::NamespacedMap {
+                        ns: if ns.is_empty() { None } else { Some(ns) },
+                        auto,
+                        forms: self.read_map_forms(start)?,
+                    },
+                    c => return Err(Error::UnexpectedChar(pos, c)),
+                }
+            }
+            c if c.is_alphabetic() => Form::Tagged {
+                tag: self.read_token(c.to_string()),
+                form: Box::new(self.read_required()?),
+            },
+            c => return Err(Error::UnexpectedChar(start, c)),
+        };
+
+        Ok(Some(Node::new(form, start, self.pos)))
+    }
+}
+
+/// Splits `ns/name`, keeps `/` and `ns//` as names
+fn split_ns(token: &str) -> (Option<String>, String) {
+    match token.find('/') {
+        Some(idx) if idx > 0 && idx < token.len() - 1 => {
+            (Some(token[..idx].to_string()), token[idx + 1..].to_string())
+        }
+        _ => (None, token.to_string()),
+    }
+}
+
+fn parse_number(token: &str) -> Option<Number> {
+    let (sign, digits) = match token.chars().next()? {
+        '-' => ("-", &token[1..]),
+        '+' => ("", &token[1..]),
+        _ => ("", token),
+    };
+
+    if let Some(n) = digits.strip_suffix('N') {
+        return parse_int(n).map(|(radix, n)| {
+            Number::BigInt(match i128::from_str_radix(n, radix) {
+                Ok(n) => format!("{}{}", sign, n),
+                Err(_) => format!("{}{}", sign, n),
+            })
+        });
+    }
+
+    if let Some((radix, n)) = parse_int(digits) {
+        return Some(
+            match i64::from_str_radix(&format!("{}{}", sign, n), radix) {
+                Ok(n) => Number::Int(n),
+                Err(_) => Number::BigInt(format!("{}{}", sign, n)),
+            },
+        );
+    }
+
+    if let Some((n, d)) = digits.split_once('/') {
+        if !n.is_empty() && !d.is_empty() && (n.to_string() + d).chars().all(|c| c.is_ascii_digit())
+        {
+            return Some(Number::Ratio(format!("{}{}", sign, n), d.to_string()));
+        }
+        return None;
+    }
+
+    if let Some(n) = digits.strip_suffix('M') {
+        return n
+            .parse::<f64>()
+            .ok()
+            .map(|_| Number::BigDec(format!("{}{}", sign, n)));
+    }
+
+    if digits
+        .chars()
+        .all(|c| c.is_ascii_digit() || "eE.+-".contains(c))
+    {
+        return format!("{}{}", sign, digits)
+            .parse::<f64>()
+            .ok()
+            .map(Number::Float);
+    }
+
+    None
+}
+
+/// Parses unsigned integer literal, returns radix and digits
+fn parse_int(digits: &str) -> Option<(u32, &str)> {
+    if digits.is_empty() {
+        return None;
+    }
+
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        return if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            Some((16, hex))
+        } else {
+            None
+        };
+    }
+
+    if let Some((radix, n)) = digits.split_once(['r', 'R']) {
+        let radix = radix.parse::<u32>().ok().filter(|r| (2..=36).contains(r))?;
+        return if !n.is_empty() && n.chars().all(|c| c.is_digit(radix)) {
+            Some((radix, n))
+        } else {
+            None
+        };
+    }
+
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    if digits.len() > 1 && digits.starts_with('0') {
+        return if digits.chars().all(|c| ('0'..='7').contains(&c)) {
+            Some((8, &digits[1..]))
+        } else {
+            None
+        };
+    }
+
+    Some((10, digits))
+}
+
+/// Reads all forms from `source`
+pub fn read_all(source: &str) -> Result<Vec<Node>, Error> {
+    let mut reader = Reader::new(source);
+    let mut nodes = vec![];
+
+    while let Some(node) = reader.read()? {
+        nodes.push(node);
+    }
+
+    Ok(nodes)
+}
+
+/// Reads the first form from `source`
+pub fn read_str(source: &str) -> Result<Option<Node>, Error> {
+    Reader::new(source).read()
+}
+
+fn contains(node: &Node, line: usize, column: usize) -> bool {
+    (node.start.line, node.start.column) <= (line, column)
+        && (line, column) < (node.end.line, node.end.column)
+}
+
+/// Form at `line` and `column`: the innermost list, vector, map or set containing it or
+/// the top level form when `outermost`
+pub fn form_at(nodes: &[Node], line: usize, column: usize, outermost: bool) -> Option<&Node> {
+    let node = nodes.iter().find(|n| contains(n, line, column))?;
+
+    if outermost {
+        return Some(node);
+    }
+
+    let inner = node
+        .children()
+        .and_then(|items| form_at(items, line, column, false))
+        .filter(|n| n.children().is_some());
+
+    Some(inner.unwrap_or(node))
+}
+
+/// Replaces reader conditionals with forms of the first branch matching one of `features`
+///
+/// Splicing conditionals get their list spliced in place, nested collections are processed too
+pub fn resolve_conditionals(nodes: &[Node], features: &[&str]) -> Vec<Node> {
+    let mut out = vec![];
+
+    for node in nodes {
+        match &node.form {
+            Form::ReaderConditional { splicing, forms } => {
+                let branch = forms.chunks(2).find(|pair| {
+                    pair.len() == 2
+                        && pair[0]
+                            .as_keyword()
+                            .is_some_and(|k| features.contains(&k.as_str()))
+                });
+
+                if let Some([_, form]) = branch {
+                    match (&form.form, splicing) {
+                        (Form::List(items), true) | (Form::Vector(items), true) => {
+                            out.extend(resolve_conditionals(items, features))
+                        }
+                        _ => out.extend(resolve_conditionals(std::slice::from_ref(form), features)),
+                    }
+                }
+            }
+            _ => {
+                let mut node = node.clone();
+                node.form = match node.form {
+                    Form::List(items) => Form::List(resolve_conditionals(&items, features)),
+                    Form::Vector(items) => Form::Vector(resolve_conditionals(&items, features)),
+                    Form::Map(items) => Form::Map(resolve_conditionals(&items, features)),
+                    Form::Set(items) => Form::Set(resolve_conditionals(&items, features)),
+                    Form::Fn(items) => Form::Fn(resolve_conditionals(&items, features)),
+                    form => form,
+                };
+                out.push(node);
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_one(s: &str) -> Node {
+        read_str(s).unwrap().unwrap()
+    }
+
+    fn sym(ns: Option<&str>, name: &str) -> Form {
+        Form::Symbol {
+            ns: ns.map(|s| s.to_string()),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn reads_atoms_test() {
+        assert_eq!(read_one("nil").form, Form::Nil);
+        assert_eq!(read_one("true").form, Form::Bool(true));
+        assert_eq!(read_one("42").form, Form::Number(Number::Int(42)));
+        assert_eq!(read_one("-0x1F").form, Form::Number(Number::Int(-31)));
+        assert_eq!(read_one("2r101").form, Form::Number(Number::Int(5)));
+        assert_eq!(read_one("017").form, Form::Number(Number::Int(15)));
+        assert_eq!(
+            read_one("7N").form,
+            Form::Number(Number::BigInt("7".to_string()))
+        );
+        assert_eq!(read_one("1.5e3").form, Form::Number(Number::Float(1500.0)));
+        assert_eq!(
+            read_one("1.5M").form,
+            Form::Number(Number::BigDec("1.5".to_string()))
+        );
+        assert_eq!(
+            read_one("-1/3").form,
+            Form::Number(Number::Ratio("-1".to_string(), "3".to_string()))
+        );
+        assert_eq!(
+            read_one("\"a\\n\\u0041\"").form,
+            Form::Str("a\nA".to_string())
+        );
+        assert_eq!(read_one("\\newline").form, Form::Char('\n'));
+        assert_eq!(read_one("\\(").form, Form::Char('('));
+        assert_eq!(read_one("#\"\\d+\"").form, Form::Regex("\\d+".to_string()));
+        assert_eq!(read_one("foo/bar").form, sym(Some("foo"), "bar"));
+        assert_eq!(
+            read_one("clojure.core//").form,
+            sym(Some("clojure.core"), "/")
+        );
+        assert_eq!(read_one("/").form, sym(None, "/"));
+        assert_eq!(
+            read_one("::str/x").form,
+            Form::Keyword {
+                ns: Some("str".to_string()),
+                name: "x".to_string(),
+                auto: true
+            }
+        );
+        assert!(read_str("1.2.3").is_err());
+    }
+
+    #[test]
+    fn reads_macros_test() {
+        let node = read_one("^:private ^{:doc \"d\"} (defn- f [x] `(~x ~@[x] @x #'f))");
+
+        assert_eq!(node.meta.len(), 2);
+        assert!(node.has_meta_flag("private"));
+        assert_eq!(node.to_string(), "(defn- f [x] `(~x ~@[x] @x #'f))");
+
+        assert_eq!(read_one("#(+ % #_ignored 1)").to_string(), "#(+ % 1)");
+        assert_eq!(read_one("#inst \"2020\"").to_string(), "#inst \"2020\"");
+        assert_eq!(
+            read_one("##Inf").form,
+            Form::SymbolicValue("Inf".to_string())
+        );
+        assert_eq!(read_one("#:a{:b 1}").to_string(), "#:a{:b 1}");
+        assert_eq!(read_one("#::{:b 1}").to_string(), "#::{:b 1}");
+        assert_eq!(read_one("#{1 2}").to_string(), "#{1 2}");
+    }
+
+    #[test]
+    fn reader_conditionals_test() {
+        let nodes = read_all("[1 #?(:clj 2 :cljs 3) #?@(:cljs [4] :default [5 6])]").unwrap();
+        let resolved = resolve_conditionals(&nodes, &["clj"]);
+
+        assert_eq!(resolved[0].to_string(), "[1 2]");
+        assert_eq!(
+            resolve_conditionals(&nodes, &["cljs"])[0].to_string(),
+            "[1 3 4]"
+        );
+        assert_eq!(
+            resolve_conditionals(&nodes, &["default"])[0].to_string(),
+            "[1 5 6]"
+        );
+    }
+
+    #[test]
+    fn positions_test() {
+        let nodes = read_all("; comment\n(foo\n  bar)").unwrap();
+        let list = &nodes[0];
+
+        assert_eq!((list.start.line, list.start.column), (2, 1));
+        assert_eq!((list.end.line, list.end.column), (3, 7));
+
+        let bar = &list.children().unwrap()[1];
+        assert_eq!((bar.start.line, bar.start.column), (3, 3));
+        assert_eq!(bar.start.offset, 17);
+    }
+
+    #[test]
+    fn form_at_test() {
+        let source = "(ns foo)\n(defn f [x]\n  (inc (* x 2)))";
+        let nodes = read_all(source).unwrap();
+        let text = |line, column, outermost| {
+            form_at(&nodes, line, column, outermost).map(|n| &source[n.start.offset..n.end.offset])
+        };
+
+        assert_eq!(text(3, 10, false), Some("(* x 2)"));
+        assert_eq!(text(3, 4, false), Some("(inc (* x 2))"));
+        assert_eq!(text(2, 9, false), Some("[x]"));
+        assert_eq!(text(3, 10, true), Some("(defn f [x]\n  (inc (* x 2)))"));
+        assert_eq!(text(3, 20, false), None);
+    }
+
+    #[test]
+    fn errors_test() {
+        assert!(read_all("(foo [bar").unwrap_err().is_eof());
+        assert!(!read_all("(foo]").unwrap_err().is_eof());
+        assert_eq!(
+            read_all("{:a}").unwrap_err(),
+            Error::OddMapForms(Pos {
+                offset: 0,
+                line: 1,
+                column: 1
+            })
+        );
+    }
+
+    #[test]
+    fn to_json_test() {
+        assert_eq!(
+            read_one("{:a [1 \"s\" nil] \"b\" {:c/d true}}").to_json(),
+            serde_json::json!({"a": [1, "s", null], "b": {"c/d": true}})
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! nrepl servers started by unrepl for projects with the build tool of project,
+//! cider-nrepl middleware is added to them
+
+#![allow(non_local_definitions)]
+
+use crate::config::{self, Server};
+use crate::nrepl::port;
+use crate::nrepl::transport::Addr;
+use failure::{Error as StdError, Fail};
+use std::fs::File;
+use std::net::TcpListener;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const NREPL_VERSION: &str = "1.0.0";
+const CIDER_NREPL_VERSION: &str = "0.28.5";
+/// Servers listen only on the loopback interface
+pub const LOCAL_HOST: &str = "127.0.0.1";
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Don't know how to start nrepl in {}", root)]
+    UnknownTool { root: String },
+    #[fail(display = "Failed to start {}: {}", program, error)]
+    SpawnFailed {
+        program: String,
+        error: std::io::Error,
+    },
+    #[fail(
+        display = "{} exited with {} before nrepl started, see {}",
+        tool, status, log
+    )]
+    Exited {
+        tool: String,
+        status: String,
+        log: String,
+    },
+    #[fail(display = "nrepl didn't start in {} seconds, see {}", secs, log)]
+    Timeout { secs: u64, log: String },
+}
+
+/// Build tool starting nrepl
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tool {
+    Lein,
+    Clojure,
+    Shadow,
+    Bb,
+}
+
+impl Tool {
+    pub const NAMES: [&'static str; 4] = ["lein", "clojure", "shadow-cljs", "bb"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Lein => "lein",
+            Tool::Clojure => "clojure",
+            Tool::Shadow => "shadow-cljs",
+            Tool::Bb => "bb",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lein" => Some(Tool::Lein),
+            "clojure" => Some(Tool::Clojure),
+            "shadow-cljs" => Some(Tool::Shadow),
+            "bb" => Some(Tool::Bb),
+            _ => None,
+        }
+    }
+
+    /// Tool of project in `root` by its files, Clojure tools go before ClojureScript and
+    /// Babashka ones when project has files of several of them
+    pub fn detect(root: &Path) -> Option<Self> {
+        [
+            ("project.clj", Tool::Lein),
+            ("deps.edn", Tool::Clojure),
+            ("shadow-cljs.edn", Tool::Shadow),
+            ("bb.edn", Tool::Bb),
+        ]
+        .iter()
+        .find(|(file, _)| root.join(file).is_file())
+        .map(|(_, tool)| *tool)
+    }
+
+    /// Port file written by the tool relative to project root, Babashka doesn't write one, so
+    /// it's written by `start`
+    pub fn port_file(&self) -> &'static str {
+        match self {
+            Tool::Lein | Tool::Clojure => ".nrepl-port",
+            Tool::Shadow => ".shadow-cljs/nrepl.port",
+            Tool::Bb => ".bb-nrepl-port",
+        }
+    }
+
+    /// Command starting nrepl with cider-nrepl, `port` is used only by Babashka, which doesn't
+    /// support nrepl middleware
+    fn command(&self, port: u16) -> Command {
+        let mut cmd = Command::new(match self {
+            Tool::Shadow => "npx",
+            _ => self.name(),
+        });
+
+        match self {
+            Tool::Lein => cmd.args([
+                "update-in",
+                ":dependencies",
+                "conj",
+                &format!("[nrepl/nrepl \"{}\"]", NREPL_VERSION),
+                "--",
+                "update-in",
+                ":plugins",
+                "conj",
+                &format!("[cider/cider-nrepl \"{}\"]", CIDER_NREPL_VERSION),
+                "--",
+                "repl",
+                ":headless",
+                ":host",
+                LOCAL_HOST,
+            ]),
+            Tool::Clojure => cmd.args([
+                "-Sdeps",
+                &format!(
+                    "{{:deps {{nrepl/nrepl {{:mvn/version \"{}\"}} \
+                     cider/cider-nrepl {{:mvn/version \"{}\"}}}}}}",
+                    NREPL_VERSION, CIDER_NREPL_VERSION
+                ),
+                "-M",
+                "-m",
+                "nrepl.cmdline",
+                "--middleware",
+                "[cider.nrepl/cider-middleware]",
+            ]),
+            // shadow-cljs adds cider-nrepl middleware when it's on classpath
+            Tool::Shadow => cmd.args([
+                "shadow-cljs",
+                "-d",
+                &format!("nrepl/nrepl:{}", NREPL_VERSION),
+                "-d",
+                &format!("cider/cider-nrepl:{}", CIDER_NREPL_VERSION),
+                "server",
+            ]),
+            Tool::Bb => cmd.args(["nrepl-server", &format!("{}:{}", LOCAL_HOST, port)]),
+        };
+
+        cmd
+    }
+}
+
+/// Log of server of project in `root` in unrepl's directory
+fn log_path(root: &Path) -> std::path::PathBuf {
+    let name = root.to_string_lossy().replace('/', "_");
+    config::config_path()
+        .join("servers")
+        .join(format!("{}.log", name))
+}
+
+fn free_port() -> Result<u16, StdError> {
+    Ok(TcpListener::bind((LOCAL_HOST, 0))?.local_addr()?.port())
+}
+
+/// Tells if process `pid` is running
+pub fn is_running(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Starts nrepl of project in `root` and waits until it listens on the port from port file.
+/// The server runs in its own process group, so it's not stopped with the terminal of unrepl
+pub fn start(root: &Path, tool: Tool, timeout: Duration) -> Result<Server, StdError> {
+    let log = log_path(root);
+    std::fs::create_dir_all(log.parent().unwrap())?;
+    let log_file = File::create(&log)?;
+    let port_file = root.join(tool.port_file());
+    let bb_port = free_port()?;
+
+    let mut child = tool
+        .command(bb_port)
+        .current_dir(root)
+        .stdin(Stdio::null())
+        .stdout(log_file.try_clone()?)
+        .stderr(log_file)
+        .process_group(0)
+        .spawn()
+        .map_err(|error| Error::SpawnFailed {
+            program: tool.name().to_string(),
+            error,
+        })?;
+
+    let log = log.to_string_lossy().to_string();
+    let started = Instant::now();
+    loop {
+        let port = match tool {
+            Tool::Bb => Some(bb_port),
+            _ => port::read_port(&port_file),
+        };
+        if port.is_some_and(|port| port::is_alive(&Addr::tcp(LOCAL_HOST, port))) {
+            break;
+        }
+
+        if let Some(status) = child.try_wait()? {
+            return Err(Error::Exited {
+                tool: tool.name().to_string(),
+                status: status.to_string(),
+                log,
+            }
+            .into());
+        }
+        if started.elapsed() > timeout {
+            kill(child.id());
+            return Err(Error::Timeout {
+                secs: timeout.as_secs(),
+                log,
+            }
+            .into());
+        }
+
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    if tool == Tool::Bb {
+        std::fs::write(&port_file, bb_port.to_string())?;
+    }
+
+    let server = Server {
+        root: root.to_string_lossy().to_string(),
+        tool: tool.name().to_string(),
+        pid: child.id(),
+        port_file: port_file.to_string_lossy().to_string(),
+        log,
+    };
+    config::save_server(&server)?;
+
+    Ok(server)
+}
+
+/// Terminates process group `pid`
+fn kill(pid: u32) {
+    Command::new("kill")
+        .args(["-TERM", "--", &format!("-{}", pid)])
+        .stderr(Stdio::null())
+        .status()
+        .ok();
+}
+
+/// Stops `server` and forgets it, port file is removed when unrepl wrote it
+pub fn stop(server: &Server) -> Result<(), StdError> {
+    if is_running(server.pid) {
+        kill(server.pid);
+    }
+    if Tool::from_name(&server.tool) == Some(Tool::Bb) {
+        std::fs::remove_file(&server.port_file).unwrap_or_default();
+    }
+
+    config::delete_server(&server.root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_test() {
+        let dir = std::env::temp_dir().join(format!("unrepl-server-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(Tool::detect(&dir), None);
+        std::fs::write(dir.join("bb.edn"), "{}").unwrap();
+        assert_eq!(Tool::detect(&dir), Some(Tool::Bb));
+        std::fs::write(dir.join("shadow-cljs.edn"), "{}").unwrap();
+        std::fs::write(dir.join("deps.edn"), "{}").unwrap();
+        assert_eq!(Tool::detect(&dir), Some(Tool::Clojure));
+
+        for name in Tool::NAMES.iter() {
+            assert_eq!(Tool::from_name(name).map(|t| t.name()), Some(*name));
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap_or_default();
+    }
+}